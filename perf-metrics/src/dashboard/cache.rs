@@ -1,77 +1,121 @@
 //! This module is used to support the display of cached state related metrics.
-use super::commons::*;
+#[cfg(feature = "enable_prometheus")]
+use super::prometheus::{PrometheusMetrics, PrometheusText};
+use super::{
+    commons::*,
+    csv::{CsvTable, CsvTables},
+};
+use crate::metrics::HotKeysReport;
 use revm_utils::{
-    metrics::types::{CacheDbRecord, Function},
+    metrics::{
+        hot_keys::KeyCount,
+        types::{CacheDbRecord, Function},
+    },
     time_utils::convert_cycles_to_ns_f64,
 };
 
 const COL_WIDTH_BIG: usize = 20;
 const COL_WIDTH_MIDDLE: usize = 14;
+/// Number of the hot keys printed in each table.
+const HOT_KEYS_TOP_N: usize = 20;
+/// Wide enough for a storage key, i.e. an address and a slot in hex.
+const KEY_COL_WIDTH: usize = 112;
 
 #[derive(Default, Debug, Copy, Clone)]
-struct CacheStat {
-    hits: u64,
-    misses: u64,
-    miss_ratio: f64,
-    penalty: f64,
-    avg_penalty: f64,
+pub(super) struct CacheStat {
+    pub(super) hits: u64,
+    pub(super) misses: u64,
+    pub(super) miss_ratio: f64,
+    pub(super) penalty: f64,
+    pub(super) avg_penalty: f64,
+    pub(super) avg_hit: f64,
+    pub(super) avg_delta: f64,
 }
 
-const CACHE_STATS_LEN: usize = 5;
-#[derive(Debug)]
-struct CacheStats {
-    functions: [CacheStat; CACHE_STATS_LEN],
-}
+impl CacheStat {
+    fn new(hits: u64, misses: u64, penalty_cycles: u64, hit_cycles: u64) -> Self {
+        // The averages are 0 instead of NaN if there is no hit or miss, and so is the
+        // delta, which is meaningless without both.
+        let avg_us = |cycles: u64, calls: u64| {
+            if calls == 0 {
+                return 0.0;
+            }
+            convert_cycles_to_ns_f64(cycles) / (1000 * calls) as f64
+        };
+        let avg_penalty = avg_us(penalty_cycles, misses);
+        let avg_hit = avg_us(hit_cycles, hits);
+        let avg_delta = if hits == 0 || misses == 0 {
+            0.0
+        } else {
+            avg_penalty - avg_hit
+        };
+        let miss_ratio = if hits + misses == 0 {
+            0.0
+        } else {
+            misses as f64 / (hits + misses) as f64
+        };
+        CacheStat {
+            hits,
+            misses,
+            miss_ratio,
+            penalty: cycles_as_secs(penalty_cycles),
+            avg_penalty,
+            avg_hit,
+            avg_delta,
+        }
+    }
 
-impl CacheStats {
-    fn print_item(&self, function: &str, index: usize) {
+    fn print(&self, function: &str) {
         println!(
-            "{: <COL_WIDTH_BIG$}{:>COL_WIDTH_MIDDLE$}{:>COL_WIDTH_MIDDLE$}{:>COL_WIDTH_BIG$.3}{:>COL_WIDTH_BIG$.3}{:>COL_WIDTH_BIG$.3}",
-            function, self.functions[index].hits, self.functions[index].misses, self.functions[index].miss_ratio * 100.0, self.functions[index].penalty, self.functions[index].avg_penalty
+            "{: <COL_WIDTH_BIG$}{:>COL_WIDTH_MIDDLE$}{:>COL_WIDTH_MIDDLE$}{:>COL_WIDTH_BIG$.3}{:>COL_WIDTH_BIG$.3}{:>COL_WIDTH_BIG$.3}{:>COL_WIDTH_BIG$.3}{:>COL_WIDTH_BIG$.3}",
+            function, self.hits, self.misses, self.miss_ratio * 100.0, self.penalty, self.avg_penalty, self.avg_hit, self.avg_delta
         );
     }
-}
 
-impl Default for CacheStats {
-    fn default() -> Self {
-        CacheStats {
-            functions: [CacheStat::default(); CACHE_STATS_LEN],
-        }
+    fn csv_row(&self, function: &str) -> Vec<String> {
+        vec![
+            function.to_string(),
+            self.hits.to_string(),
+            self.misses.to_string(),
+            format!("{:.3}", self.miss_ratio * 100.0),
+            format!("{:.3}", self.penalty),
+            format!("{:.3}", self.avg_penalty),
+            format!("{:.3}", self.avg_hit),
+            format!("{:.3}", self.avg_delta),
+        ]
     }
 }
 
+#[derive(Debug, Default)]
+pub(super) struct CacheStats {
+    pub(super) functions: [CacheStat; Function::COUNT],
+    pub(super) total: CacheStat,
+}
+
 impl From<&CacheDbRecord> for CacheStats {
     fn from(record: &CacheDbRecord) -> Self {
-        let mut cache_stats = CacheStats::default();
-
-        let total_stats = record.access_count();
         let hit_stats = record.hit_stats();
         let miss_stats = record.miss_stats();
         let penalty_stats = record.penalty_stats();
+        let hit_latency_stats = record.hit_latency_stats();
 
-        for index in 0..total_stats.function.len() {
-            cache_stats.functions[index].hits = hit_stats.function[index];
-            cache_stats.functions[index].misses = miss_stats.function[index];
-            cache_stats.functions[index].miss_ratio =
-                miss_stats.function[index] as f64 / total_stats.function[index] as f64;
-            cache_stats.functions[index].penalty =
-                cycles_as_secs(penalty_stats.time.function[index]);
-            cache_stats.functions[index].avg_penalty =
-                convert_cycles_to_ns_f64(penalty_stats.time.function[index])
-                    / (1000 * miss_stats.function[index]) as f64;
-        }
-        cache_stats.functions[CACHE_STATS_LEN - 1].hits = hit_stats.function.iter().sum();
-        cache_stats.functions[CACHE_STATS_LEN - 1].misses = miss_stats.function.iter().sum();
-        cache_stats.functions[CACHE_STATS_LEN - 1].miss_ratio =
-            cache_stats.functions[CACHE_STATS_LEN - 1].misses as f64
-                / total_stats.function.iter().sum::<u64>() as f64;
-        cache_stats.functions[CACHE_STATS_LEN - 1].penalty =
-            cycles_as_secs(penalty_stats.time.function.iter().sum());
-        cache_stats.functions[CACHE_STATS_LEN - 1].avg_penalty =
-            convert_cycles_to_ns_f64(penalty_stats.time.function.iter().sum())
-                / (1000 * cache_stats.functions[CACHE_STATS_LEN - 1].misses) as f64;
-
-        cache_stats
+        let functions = Function::ALL.map(|function| {
+            let index = function as usize;
+            CacheStat::new(
+                hit_stats.function[index],
+                miss_stats.function[index],
+                penalty_stats.time.function[index],
+                hit_latency_stats.time.function[index],
+            )
+        });
+        let total = CacheStat::new(
+            hit_stats.function.iter().sum(),
+            miss_stats.function.iter().sum(),
+            penalty_stats.time.function.iter().sum(),
+            hit_latency_stats.time.function.iter().sum(),
+        );
+
+        CacheStats { functions, total }
     }
 }
 
@@ -79,29 +123,119 @@ impl Print for CacheStats {
     fn print_title(&self) {
         println!("================================================ Metric of State ===========================================");
         println!(
-            "{: <COL_WIDTH_BIG$}{:>COL_WIDTH_MIDDLE$}{:>COL_WIDTH_MIDDLE$}{:>COL_WIDTH_BIG$}{:>COL_WIDTH_BIG$}{:>COL_WIDTH_BIG$}",
-            "State functions", "Hits", "Misses", "Miss ratio (%)","Penalty time(s)", "Avg penalty (us)"
+            "{: <COL_WIDTH_BIG$}{:>COL_WIDTH_MIDDLE$}{:>COL_WIDTH_MIDDLE$}{:>COL_WIDTH_BIG$}{:>COL_WIDTH_BIG$}{:>COL_WIDTH_BIG$}{:>COL_WIDTH_BIG$}{:>COL_WIDTH_BIG$}",
+            "State functions", "Hits", "Misses", "Miss ratio (%)","Penalty time(s)", "Avg penalty (us)", "Avg hit (us)", "Miss delta (us)"
         );
     }
 
     fn print_content(&self) {
-        self.print_item("blockhash", Function::BlockHash as usize);
-        self.print_item("code_by_hash", Function::CodeByHash as usize);
-        self.print_item("load_account/basic", Function::LoadCacheAccount as usize);
-        self.print_item("storage", Function::Storage as usize);
-        self.print_item("total", CACHE_STATS_LEN - 1);
+        for function in Function::ALL {
+            self.functions[function as usize].print(function.name());
+        }
+        self.total.print("total");
+    }
+}
+
+impl CsvTables for CacheDbRecord {
+    fn csv_tables(&self) -> Vec<CsvTable> {
+        let stats = CacheStats::from(self);
+        let mut table = CsvTable::new(
+            "cache",
+            "function,hits,misses,miss_ratio_pct,penalty_s,avg_penalty_us,avg_hit_us,miss_delta_us",
+        );
+        for function in Function::ALL {
+            table.push_row(&stats.functions[function as usize].csv_row(function.name()));
+        }
+        table.push_row(&stats.total.csv_row("total"));
+        vec![table]
+    }
+}
+
+#[cfg(feature = "enable_prometheus")]
+impl PrometheusMetrics for CacheDbRecord {
+    fn write_metrics(&self, text: &mut PrometheusText) {
+        let penalty = self.penalty_stats();
+        let counters = [
+            (
+                "revm_cache_hits_total",
+                "The number of cache hits of each state function.",
+                self.hit_stats().function.map(|hits| hits as f64),
+            ),
+            (
+                "revm_cache_misses_total",
+                "The number of cache misses of each state function.",
+                self.miss_stats().function.map(|misses| misses as f64),
+            ),
+            (
+                "revm_cache_miss_penalty_seconds_total",
+                "The time spent on the cache misses of each state function.",
+                penalty.time.function.map(cycles_as_secs),
+            ),
+        ];
+        for (name, help, values) in counters {
+            text.family(name, "counter", help);
+            for function in Function::ALL {
+                text.sample(
+                    name,
+                    &[("function", function.name())],
+                    values[function as usize],
+                );
+            }
+        }
+
+        let name = "revm_cache_miss_penalty_duration_seconds";
+        text.family(
+            name,
+            "histogram",
+            "The time distribution of the cache misses of each state function.",
+        );
+        for function in Function::ALL {
+            text.log_histogram(
+                name,
+                &[("function", function.name())],
+                &penalty.function_percentile[function as usize],
+            );
+        }
+
+        let name = "revm_cache_hit_duration_seconds";
+        text.family(
+            name,
+            "histogram",
+            "The time distribution of the cache hits.",
+        );
+        text.log_histogram(name, &[], &self.hit_latency_stats().percentile);
     }
 }
 
+/// Write the size of the cached state as a gauge.
+#[cfg(feature = "enable_prometheus")]
+pub(super) fn write_state_size(text: &mut PrometheusText, size: usize) {
+    let name = "revm_cache_state_size";
+    text.family(name, "gauge", "The size of the cached state.");
+    text.sample(name, &[], size as f64);
+}
+
 trait PrintPenalty {
     fn print_penalty(&self);
 }
 
 impl PrintPenalty for CacheDbRecord {
     fn print_penalty(&self) {
+        let penalty = self.penalty_stats();
+        println!();
+        println!("=====================================Penalty percentile=====================================");
+        print_histogram_header("State functions");
+        for function in Function::ALL {
+            print_histogram_row(
+                function.name(),
+                &penalty.function_percentile[function as usize],
+            );
+        }
+        print_histogram_row("total", &penalty.percentile);
         println!();
-        println!("================Penalty percentile=============");
-        self.penalty_stats().percentile.print_content();
+
+        println!("====================================Hit latency percentile==================================");
+        self.hit_latency_stats().percentile.print_content();
         println!();
     }
 }
@@ -113,6 +247,40 @@ impl Print for CacheDbRecord {
     }
 }
 
+fn print_hot_keys(name: &str, keys: &[KeyCount]) {
+    println!(
+        "{:<6}{:<KEY_COL_WIDTH$}{:>COL_WIDTH_MIDDLE$}{:>COL_WIDTH_MIDDLE$}",
+        "Rank", name, "Count", "Max error"
+    );
+    for (rank, key) in keys.iter().enumerate() {
+        println!(
+            "{:<6}{:<KEY_COL_WIDTH$}{:>COL_WIDTH_MIDDLE$}{:>COL_WIDTH_MIDDLE$}",
+            rank + 1,
+            key.key.to_string(),
+            key.count,
+            key.error
+        );
+    }
+}
+
+impl Print for HotKeysReport {
+    fn print_title(&self) {
+        println!(
+            "==================== Hot keys of State (block {} - {}) ====================",
+            self.from_block, self.to_block
+        );
+    }
+
+    fn print_content(&self) {
+        print_hot_keys(
+            "Most accessed keys",
+            &self.record.accesses.top(HOT_KEYS_TOP_N),
+        );
+        println!();
+        print_hot_keys("Most missed keys", &self.record.misses.top(HOT_KEYS_TOP_N));
+    }
+}
+
 pub(super) fn print_state_size(block_number: u64, size: usize) {
     println!();
     println! {"block_number: {:?}, State size: {:?}", block_number, size};
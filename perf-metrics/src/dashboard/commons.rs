@@ -1,5 +1,5 @@
 #[cfg(any(feature = "enable_opcode_metrics", feature = "enable_cache_record",))]
-use revm_utils::metrics::types::TimeDistributionStats;
+use revm_utils::metrics::types::LogHistogram;
 
 /// This trait is used to support the display of metric records.
 pub trait Print {
@@ -17,48 +17,50 @@ pub trait Print {
 const COL_WIDTH: usize = 15;
 
 #[cfg(any(feature = "enable_opcode_metrics", feature = "enable_cache_record",))]
-impl Print for TimeDistributionStats {
-    fn print_content(&self) {
-        let total_cnt: u64 = self.us_percentile.iter().map(|&v| v).sum();
-        let mut cuml = 0.0;
+const NAME_COL_WIDTH: usize = 20;
 
-        println!(
-            "{:<COL_WIDTH$} {:>COL_WIDTH$} {:>COL_WIDTH$}",
-            "Time (ns)", "Count (%)", "Cuml. (%)"
-        );
-        for index in 0..self.span_in_ns {
-            let pct = self.ns_percentile[index] as f64 / total_cnt as f64;
-            cuml += pct;
-            println!(
-                "{:<COL_WIDTH$} {:>COL_WIDTH$.3} {:>COL_WIDTH$.3}",
-                (index + 1) * 100,
-                pct * 100.0,
-                cuml * 100.0
-            );
-        }
+/// The quantiles printed for LogHistogram.
+#[cfg(any(feature = "enable_opcode_metrics", feature = "enable_cache_record",))]
+const QUANTILES: [(f64, &str); 4] = [
+    (0.5, "p50 (ns)"),
+    (0.9, "p90 (ns)"),
+    (0.99, "p99 (ns)"),
+    (0.999, "p999 (ns)"),
+];
 
-        let ns_span_in_us = ((self.span_in_ns * 100) as f64 / 1000.0) as usize;
-        for index in ns_span_in_us..self.span_in_us {
-            let pct = self.us_percentile[index] as f64 / total_cnt as f64;
-            cuml += pct;
-            println!(
-                "{:<COL_WIDTH$} {:>COL_WIDTH$.3} {:>COL_WIDTH$.3}",
-                (index + 1) * 1000,
-                pct * 100.0,
-                cuml * 100.0
-            );
-        }
+/// Print the header of the table printed by print_histogram_row.
+#[cfg(any(feature = "enable_opcode_metrics", feature = "enable_cache_record",))]
+pub(super) fn print_histogram_header(name: &str) {
+    print!(
+        "{:<NAME_COL_WIDTH$}{:>COL_WIDTH$}{:>COL_WIDTH$}",
+        name, "Count", "Mean (ns)"
+    );
+    for (_, quantile) in QUANTILES.iter() {
+        print!("{:>COL_WIDTH$}", quantile);
+    }
+    println!("{:>COL_WIDTH$}", "Max (ns)");
+}
 
-        println!();
-        println!();
-        println!("========>:");
-        println!("total cnt: {:?}", total_cnt);
-        println!("span_in_ns: {:?}", self.span_in_ns);
-        println!("span_in_us: {:?}", self.span_in_us);
-        println!("in_ns: {:?}", self.ns_percentile);
-        println!("in_us: {:?}", self.us_percentile);
-        println!();
-        println!();
+/// Print the count, mean, quantiles and max of histogram in a row.
+#[cfg(any(feature = "enable_opcode_metrics", feature = "enable_cache_record",))]
+pub(super) fn print_histogram_row(name: &str, histogram: &LogHistogram) {
+    print!(
+        "{:<NAME_COL_WIDTH$}{:>COL_WIDTH$}{:>COL_WIDTH$.1}",
+        name,
+        histogram.count(),
+        histogram.mean()
+    );
+    for (quantile, _) in QUANTILES.iter() {
+        print!("{:>COL_WIDTH$}", histogram.quantile(*quantile));
+    }
+    println!("{:>COL_WIDTH$}", histogram.max());
+}
+
+#[cfg(any(feature = "enable_opcode_metrics", feature = "enable_cache_record",))]
+impl Print for LogHistogram {
+    fn print_content(&self) {
+        print_histogram_header("");
+        print_histogram_row("all", self);
     }
 }
 
@@ -75,3 +77,27 @@ pub(super) fn cycles_as_secs(cycles: u64) -> f64 {
 pub(super) fn convert_bytes_to_mega(size: usize) -> f64 {
     size as f64 / 1024.0 / 1024.0
 }
+
+/// Print the health of metrics, only when some abnormal events have been absorbed.
+#[cfg(any(
+    feature = "enable_opcode_metrics",
+    feature = "enable_cache_record",
+    feature = "enable_execution_duration_record",
+    feature = "enable_tps_gas_record",
+))]
+pub(super) fn print_metrics_health(
+    block_number: u64,
+    health: &revm_utils::metrics::health::MetricsHealth,
+) {
+    if health.is_healthy() {
+        return;
+    }
+    println!();
+    println!(
+        "block_number: {:?}, Metrics health ({:?} policy): overflows: {}, negative intervals: {}",
+        block_number,
+        revm_utils::metrics::health::overflow_policy(),
+        health.overflows,
+        health.negative_intervals
+    );
+}
@@ -0,0 +1,664 @@
+//! This module is used to compare two runs, e.g. before and after a change of revm. The
+//! opcodes, state functions and execution stages of the runs are aligned by name, and the
+//! metrics which increased beyond the thresholds are reported as regressions.
+#[cfg(feature = "enable_cache_record")]
+use super::cache::CacheStats;
+#[cfg(feature = "enable_opcode_metrics")]
+use super::opcode::{OpcodeStats, OPCODE_NUMBER};
+#[cfg(feature = "enable_execution_duration_record")]
+use super::{commons::cycles_as_secs, csv::split_label};
+use super::{dump::read_dump, sink::MetricSink};
+use crate::metrics::metric::MetricEvent;
+#[cfg(feature = "enable_execution_duration_record")]
+use crate::metrics::ExecutionDurationRecord;
+#[cfg(feature = "enable_opcode_metrics")]
+use revm::revm_opcode::OpCode;
+#[cfg(feature = "enable_opcode_metrics")]
+use revm_utils::metrics::types::OpcodeRecord;
+#[cfg(feature = "enable_cache_record")]
+use revm_utils::metrics::types::{CacheDbRecord, Function};
+use serde::Serialize;
+use std::{collections::BTreeMap, fs, io, path::Path};
+
+const COL_WIDTH: usize = 15;
+/// Wide enough for the longest table name, i.e. "execution_stage".
+const TABLE_COL_WIDTH: usize = 18;
+/// Wide enough for the path of a nested execution stage.
+const ROW_COL_WIDTH: usize = 60;
+
+/// The latest cumulative records of a run.
+#[derive(Debug, Clone, Default)]
+pub struct RunRecords {
+    /// The block number of the latest record.
+    pub block_number: u64,
+    #[cfg(feature = "enable_opcode_metrics")]
+    pub opcode: Option<OpcodeRecord>,
+    #[cfg(feature = "enable_cache_record")]
+    pub cache: Option<CacheDbRecord>,
+    #[cfg(feature = "enable_execution_duration_record")]
+    pub duration: Option<ExecutionDurationRecord>,
+    /// The block number of duration, which is cumulative since the start of the run.
+    #[cfg(feature = "enable_execution_duration_record")]
+    pub duration_block: u64,
+}
+
+impl RunRecords {
+    /// Returns the latest records of the dump at path.
+    pub fn from_dump(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut records = RunRecords::default();
+        for dumped in read_dump(path)? {
+            records.record(&dumped?.event);
+        }
+        Ok(records)
+    }
+
+    /// Keep the cumulative record of event, which replaces the previous one.
+    pub fn record(&mut self, event: &MetricEvent) {
+        match event {
+            #[cfg(feature = "enable_opcode_metrics")]
+            MetricEvent::OpcodeInfo { record, .. } => self.opcode = Some((**record).clone()),
+            #[cfg(feature = "enable_cache_record")]
+            MetricEvent::CacheDbInfo { record, .. } => self.cache = Some(record.clone()),
+            #[cfg(feature = "enable_execution_duration_record")]
+            MetricEvent::ExecutionStageTime {
+                block_number,
+                record,
+                ..
+            } => {
+                self.duration = Some(*record);
+                self.duration_block = *block_number;
+            }
+            _ => return,
+        }
+        self.block_number = self.block_number.max(event.block_number());
+    }
+
+    /// Returns true if no record is kept.
+    pub fn is_empty(&self) -> bool {
+        #[allow(unused_mut)]
+        let mut empty = true;
+        #[cfg(feature = "enable_opcode_metrics")]
+        {
+            empty &= self.opcode.is_none();
+        }
+        #[cfg(feature = "enable_cache_record")]
+        {
+            empty &= self.cache.is_none();
+        }
+        #[cfg(feature = "enable_execution_duration_record")]
+        {
+            empty &= self.duration.is_none();
+        }
+        empty
+    }
+
+    /// Returns the compared metrics of the run.
+    fn samples(&self) -> Vec<Sample> {
+        #[allow(unused_mut)]
+        let mut samples = Vec::new();
+        #[cfg(feature = "enable_opcode_metrics")]
+        if let Some(record) = &self.opcode {
+            opcode_samples(record, &mut samples);
+        }
+        #[cfg(feature = "enable_cache_record")]
+        if let Some(record) = &self.cache {
+            cache_samples(record, &mut samples);
+        }
+        #[cfg(feature = "enable_execution_duration_record")]
+        if let Some(record) = &self.duration {
+            duration_samples(record, &mut samples);
+        }
+        samples
+    }
+}
+
+impl MetricSink for RunRecords {
+    fn on_event(&mut self, event: &MetricEvent) {
+        self.record(event);
+    }
+}
+
+/// A metric of a row of a table, a higher value of which is worse.
+struct Sample {
+    table: &'static str,
+    row: String,
+    metric: &'static str,
+    value: f64,
+    /// The number of executions or accesses the value is averaged over.
+    count: u64,
+}
+
+#[cfg(feature = "enable_opcode_metrics")]
+fn opcode_samples(record: &OpcodeRecord, samples: &mut Vec<Sample>) {
+    let stats = OpcodeStats::from(record);
+    let mut push = |table, row: &str, avg_cost, count| {
+        samples.push(Sample {
+            table,
+            row: row.to_string(),
+            metric: "cost_ns",
+            value: avg_cost,
+            count,
+        })
+    };
+    push(
+        "opcode",
+        "overall",
+        stats.overall.avg_cost,
+        stats.overall.count,
+    );
+    for i in 0..OPCODE_NUMBER {
+        let (Some(name), Some(stat)) = (OpCode::new(i as u8), stats.opcode[i].as_ref()) else {
+            continue;
+        };
+        push("opcode", name.as_str(), stat.avg_cost, stat.count);
+    }
+    for (category, record) in stats.merge_records.iter() {
+        if !category.is_empty() {
+            push("opcode_category", category, record.avg_cost, record.count);
+        }
+    }
+}
+
+#[cfg(feature = "enable_cache_record")]
+fn cache_samples(record: &CacheDbRecord, samples: &mut Vec<Sample>) {
+    let stats = CacheStats::from(record);
+    let functions = Function::ALL
+        .iter()
+        .map(|function| (function.name(), &stats.functions[*function as usize]))
+        .chain([("total", &stats.total)]);
+    for (name, stat) in functions {
+        let metrics = [
+            ("avg_hit_us", stat.avg_hit, stat.hits),
+            ("avg_penalty_us", stat.avg_penalty, stat.misses),
+            (
+                "miss_ratio_pct",
+                stat.miss_ratio * 100.0,
+                stat.hits + stat.misses,
+            ),
+        ];
+        for (metric, value, count) in metrics {
+            samples.push(Sample {
+                table: "cache",
+                row: name.to_string(),
+                metric,
+                value,
+                count,
+            });
+        }
+    }
+}
+
+/// The stages are named by their path, e.g. "write_to_db/sort state changes", since the
+/// names are only unique among the stages of the same parent.
+#[cfg(feature = "enable_execution_duration_record")]
+fn duration_samples(record: &ExecutionDurationRecord, samples: &mut Vec<Sample>) {
+    // The names of the items which contain the current item, by level.
+    let mut parents: Vec<&str> = Vec::new();
+    for (label, cycles) in record.items().iter() {
+        let (level, stage) = split_label(label);
+        parents.truncate(level);
+        parents.push(stage);
+        samples.push(Sample {
+            table: "execution_stage",
+            row: parents.join("/"),
+            metric: "time_s",
+            value: cycles_as_secs(*cycles),
+            // The time is not averaged.
+            count: u64::MAX,
+        });
+    }
+}
+
+/// The thresholds beyond which a change of a metric is reported.
+#[derive(Debug, Clone)]
+pub struct CompareThresholds {
+    /// The relative change beyond which a metric regressed or improved.
+    relative: f64,
+    /// The relative thresholds of the tables which override relative.
+    tables: BTreeMap<String, f64>,
+    /// The rows executed or accessed fewer times in either run are skipped.
+    min_count: u64,
+}
+
+impl Default for CompareThresholds {
+    fn default() -> Self {
+        CompareThresholds::new(0.05)
+    }
+}
+
+impl CompareThresholds {
+    /// Creates thresholds reporting the relative changes beyond relative, e.g. 0.05 for
+    /// 5%.
+    pub fn new(relative: f64) -> Self {
+        CompareThresholds {
+            relative,
+            tables: BTreeMap::new(),
+            min_count: 100,
+        }
+    }
+
+    /// Use relative for the metrics of table instead, the tables are "opcode",
+    /// "opcode_category", "cache" and "execution_stage".
+    pub fn with_table(mut self, table: impl Into<String>, relative: f64) -> Self {
+        self.tables.insert(table.into(), relative);
+        self
+    }
+
+    /// Skip the rows executed or accessed fewer than min_count times in either run, whose
+    /// averages are noisy, the default is 100.
+    pub fn with_min_count(mut self, min_count: u64) -> Self {
+        self.min_count = min_count;
+        self
+    }
+
+    fn relative(&self, table: &str) -> f64 {
+        self.tables.get(table).copied().unwrap_or(self.relative)
+    }
+}
+
+/// Whether a metric changed beyond its threshold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ChangeStatus {
+    Unchanged,
+    Improved,
+    Regressed,
+}
+
+impl ChangeStatus {
+    fn name(&self) -> &'static str {
+        match self {
+            ChangeStatus::Unchanged => "unchanged",
+            ChangeStatus::Improved => "improved",
+            ChangeStatus::Regressed => "REGRESSED",
+        }
+    }
+}
+
+/// The change of a metric of a row, a higher value of which is worse.
+#[derive(Debug, Clone, Serialize)]
+pub struct MetricChange {
+    /// Table name, e.g. "opcode".
+    pub table: &'static str,
+    /// Row name, e.g. the opcode.
+    pub row: String,
+    /// Metric name, with its unit as the suffix.
+    pub metric: &'static str,
+    /// Value in the baseline run.
+    pub baseline: f64,
+    /// Value in the current run.
+    pub current: f64,
+    /// Absolute change, i.e. current - baseline.
+    pub delta: f64,
+    /// Relative change, i.e. delta / baseline.
+    pub relative: f64,
+    pub status: ChangeStatus,
+}
+
+/// The result of a comparison, which decides the exit code of perf-report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOutcome {
+    /// No metric regressed.
+    Passed,
+    /// Some metrics regressed.
+    Regressed,
+    /// No metric was compared, e.g. either run has no records.
+    NothingCompared,
+}
+
+impl CompareOutcome {
+    /// Returns 0 if passed, 1 if regressed, or 2 if nothing was compared.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CompareOutcome::Passed => 0,
+            CompareOutcome::Regressed => 1,
+            CompareOutcome::NothingCompared => 2,
+        }
+    }
+}
+
+/// The comparison of the metrics of two runs, in the order of the tables.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ComparisonReport {
+    /// The block number of the latest record of the baseline run.
+    pub baseline_block: u64,
+    /// The block number of the latest record of the current run.
+    pub current_block: u64,
+    /// The change of each metric present in both runs.
+    pub changes: Vec<MetricChange>,
+    /// Why some metrics were not compared.
+    pub notes: Vec<String>,
+}
+
+impl ComparisonReport {
+    /// Compare the metrics of current against those of baseline. The metrics which are
+    /// missing in either run, or whose baseline is zero, are skipped. The time of the
+    /// execution stages is cumulative, so it is only compared if both runs end at the
+    /// same block.
+    pub fn compare(
+        baseline: &RunRecords,
+        current: &RunRecords,
+        thresholds: &CompareThresholds,
+    ) -> Self {
+        let mut notes = Vec::new();
+        for (name, run) in [("baseline", baseline), ("current", current)] {
+            if run.is_empty() {
+                notes.push(format!("the {} run has no records", name));
+            }
+        }
+        #[allow(unused_mut)]
+        let mut baseline_samples = baseline.samples();
+        #[cfg(feature = "enable_execution_duration_record")]
+        if baseline.duration.is_some()
+            && current.duration.is_some()
+            && baseline.duration_block != current.duration_block
+        {
+            notes.push(format!(
+                "execution_stage is skipped, since its time is cumulative up to block {} in \
+                 the baseline run but {} in the current run",
+                baseline.duration_block, current.duration_block
+            ));
+            baseline_samples.retain(|sample| sample.table != "execution_stage");
+        }
+
+        let current_samples: BTreeMap<_, _> = current
+            .samples()
+            .into_iter()
+            .map(|sample| {
+                (
+                    (sample.table, sample.row, sample.metric),
+                    (sample.value, sample.count),
+                )
+            })
+            .collect();
+
+        let mut changes = Vec::new();
+        for sample in baseline_samples {
+            let key = (sample.table, sample.row, sample.metric);
+            let Some(&(value, count)) = current_samples.get(&key) else {
+                continue;
+            };
+            if sample.count.min(count) < thresholds.min_count
+                || !sample.value.is_finite()
+                || !value.is_finite()
+                || sample.value <= 0.0
+            {
+                continue;
+            }
+
+            let (table, row, metric) = key;
+            let delta = value - sample.value;
+            let relative = delta / sample.value;
+            let threshold = thresholds.relative(table);
+            let status = if relative > threshold {
+                ChangeStatus::Regressed
+            } else if relative < -threshold {
+                ChangeStatus::Improved
+            } else {
+                ChangeStatus::Unchanged
+            };
+            changes.push(MetricChange {
+                table,
+                row,
+                metric,
+                baseline: sample.value,
+                current: value,
+                delta,
+                relative,
+                status,
+            });
+        }
+
+        ComparisonReport {
+            baseline_block: baseline.block_number,
+            current_block: current.block_number,
+            changes,
+            notes,
+        }
+    }
+
+    /// Returns whether the comparison passed, regressed or compared nothing.
+    pub fn outcome(&self) -> CompareOutcome {
+        if self.changes.is_empty() {
+            CompareOutcome::NothingCompared
+        } else if self.has_regression() {
+            CompareOutcome::Regressed
+        } else {
+            CompareOutcome::Passed
+        }
+    }
+
+    /// Returns the metrics which regressed.
+    pub fn regressions(&self) -> impl Iterator<Item = &MetricChange> {
+        self.changes
+            .iter()
+            .filter(|change| change.status == ChangeStatus::Regressed)
+    }
+
+    /// Returns whether any metric regressed.
+    pub fn has_regression(&self) -> bool {
+        self.regressions().next().is_some()
+    }
+
+    /// Returns the change of every compared metric in csv format.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("table,row,metric,baseline,current,delta,relative_pct,status\n");
+        for change in self.changes.iter() {
+            csv.push_str(&format!(
+                "{},{},{},{:.6},{:.6},{:.6},{:.3},{}\n",
+                change.table,
+                change.row,
+                change.metric,
+                change.baseline,
+                change.current,
+                change.delta,
+                change.relative * 100.0,
+                change.status.name(),
+            ));
+        }
+        csv
+    }
+
+    /// Returns the whole report in json format.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Serialize comparison report failed")
+    }
+
+    /// Write the report to path, in json format if the extension of path is "json",
+    /// otherwise in csv format.
+    pub fn write_to_file(&self, path: &Path) -> io::Result<()> {
+        let content = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => self.to_json(),
+            _ => self.to_csv(),
+        };
+        fs::write(path, content)
+    }
+
+    /// Print the regressed and then the improved metrics, each from the largest change.
+    pub fn print(&self) {
+        println!();
+        println!("==========================================Comparison==========================================");
+        println!(
+            "baseline up to block {}, current up to block {}",
+            self.baseline_block, self.current_block
+        );
+        for note in self.notes.iter() {
+            println!("note: {}", note);
+        }
+        println!(
+            "{:<TABLE_COL_WIDTH$}{:<ROW_COL_WIDTH$}{:<COL_WIDTH$}{:>COL_WIDTH$}{:>COL_WIDTH$}{:>COL_WIDTH$}{:>COL_WIDTH$}{:>COL_WIDTH$}",
+            "Table", "Row", "Metric", "Baseline", "Current", "Delta", "Change (%)", "Status",
+        );
+
+        let mut changed: Vec<&MetricChange> = self
+            .changes
+            .iter()
+            .filter(|change| change.status != ChangeStatus::Unchanged)
+            .collect();
+        changed.sort_by(|a, b| {
+            (b.status == ChangeStatus::Regressed)
+                .cmp(&(a.status == ChangeStatus::Regressed))
+                .then(b.relative.abs().total_cmp(&a.relative.abs()))
+        });
+        for change in changed.iter() {
+            println!(
+                "{:<TABLE_COL_WIDTH$}{:<ROW_COL_WIDTH$}{:<COL_WIDTH$}{:>COL_WIDTH$.3}{:>COL_WIDTH$.3}{:>COL_WIDTH$.3}{:>COL_WIDTH$.2}{:>COL_WIDTH$}",
+                change.table,
+                change.row,
+                change.metric,
+                change.baseline,
+                change.current,
+                change.delta,
+                change.relative * 100.0,
+                change.status.name(),
+            );
+        }
+
+        let regressions = self.regressions().count();
+        println!();
+        println!(
+            "{} regressed, {} improved, {} compared metrics",
+            regressions,
+            changed.len() - regressions,
+            self.changes.len()
+        );
+        println!();
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "enable_opcode_metrics")]
+mod tests {
+    use super::*;
+
+    const ADD: u8 = 0x01;
+    const MUL: u8 = 0x02;
+
+    /// Returns a run which executed ADD and MUL 1000 times each in the given cycles.
+    fn run(add_cycles: u64, mul_cycles: u64) -> RunRecords {
+        let mut record = OpcodeRecord {
+            is_updated: true,
+            ..Default::default()
+        };
+        record.opcode_record[ADD as usize] = (1000, add_cycles, 3000);
+        record.opcode_record[MUL as usize] = (1000, mul_cycles, 5000);
+        RunRecords {
+            block_number: 100,
+            opcode: Some(record),
+            ..Default::default()
+        }
+    }
+
+    fn status(report: &ComparisonReport, table: &str, row: &str) -> Option<ChangeStatus> {
+        report
+            .changes
+            .iter()
+            .find(|change| change.table == table && change.row == row)
+            .map(|change| change.status)
+    }
+
+    #[test]
+    fn test_thresholds() {
+        let baseline = run(100_000, 100_000);
+        let current = run(110_000, 97_000);
+
+        let report = ComparisonReport::compare(&baseline, &current, &CompareThresholds::new(0.05));
+        assert_eq!(
+            status(&report, "opcode", "ADD"),
+            Some(ChangeStatus::Regressed)
+        );
+        assert_eq!(
+            status(&report, "opcode", "MUL"),
+            Some(ChangeStatus::Unchanged)
+        );
+        assert_eq!(report.outcome(), CompareOutcome::Regressed);
+
+        let report = ComparisonReport::compare(&current, &baseline, &CompareThresholds::new(0.05));
+        assert_eq!(
+            status(&report, "opcode", "ADD"),
+            Some(ChangeStatus::Improved)
+        );
+        assert_eq!(report.outcome(), CompareOutcome::Passed);
+    }
+
+    #[test]
+    fn test_table_thresholds() {
+        let baseline = run(100_000, 100_000);
+        let current = run(110_000, 110_000);
+        let thresholds = CompareThresholds::new(0.05).with_table("opcode", 0.2);
+
+        let report = ComparisonReport::compare(&baseline, &current, &thresholds);
+        assert_eq!(
+            status(&report, "opcode", "ADD"),
+            Some(ChangeStatus::Unchanged)
+        );
+        assert_eq!(
+            status(&report, "opcode", "overall"),
+            Some(ChangeStatus::Unchanged)
+        );
+        // The other tables keep the default threshold.
+        assert_eq!(
+            status(&report, "opcode_category", "arithmetic"),
+            Some(ChangeStatus::Regressed)
+        );
+    }
+
+    #[test]
+    fn test_min_count() {
+        let baseline = run(100_000, 100_000);
+        let current = run(200_000, 200_000);
+        let thresholds = CompareThresholds::new(0.05).with_min_count(1001);
+
+        let report = ComparisonReport::compare(&baseline, &current, &thresholds);
+        assert_eq!(status(&report, "opcode", "ADD"), None);
+        // The overall row counts both opcodes.
+        assert_eq!(
+            status(&report, "opcode", "overall"),
+            Some(ChangeStatus::Regressed)
+        );
+    }
+
+    #[test]
+    fn test_outcome_exit_codes() {
+        let thresholds = CompareThresholds::default();
+        let empty = RunRecords::default();
+        let report = ComparisonReport::compare(&empty, &run(100_000, 100_000), &thresholds);
+        assert_eq!(report.outcome(), CompareOutcome::NothingCompared);
+        assert_eq!(
+            report.notes,
+            vec!["the baseline run has no records".to_string()]
+        );
+
+        let thresholds = CompareThresholds::default().with_min_count(u64::MAX);
+        let report = ComparisonReport::compare(&run(1, 1), &run(2, 2), &thresholds);
+        assert_eq!(report.outcome(), CompareOutcome::NothingCompared);
+
+        let outcomes = [
+            CompareOutcome::Passed,
+            CompareOutcome::Regressed,
+            CompareOutcome::NothingCompared,
+        ];
+        let codes: Vec<i32> = outcomes.iter().map(CompareOutcome::exit_code).collect();
+        assert_eq!(codes, vec![0, 1, 2]);
+    }
+
+    #[cfg(feature = "enable_execution_duration_record")]
+    #[test]
+    fn test_durations_of_different_blocks_are_skipped() {
+        let with_duration = |block_number| RunRecords {
+            duration: Some(ExecutionDurationRecord::default()),
+            duration_block: block_number,
+            ..run(100_000, 100_000)
+        };
+        let report = ComparisonReport::compare(
+            &with_duration(100),
+            &with_duration(200),
+            &CompareThresholds::default(),
+        );
+        assert!(report
+            .changes
+            .iter()
+            .all(|change| change.table != "execution_stage"));
+        assert_eq!(report.notes.len(), 1);
+        assert_eq!(report.outcome(), CompareOutcome::Passed);
+    }
+}
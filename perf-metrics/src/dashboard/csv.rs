@@ -0,0 +1,127 @@
+//! This module is used to export the tables of the dashboard in csv format, with the
+//! same computed columns as the terminal output.
+use super::{sink::MetricSink, terminal::DisplayMode};
+use crate::metrics::metric::MetricEvent;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// A table of the dashboard in csv format.
+pub(super) struct CsvTable {
+    /// The name of the table, which is used in the file name.
+    pub(super) name: &'static str,
+    /// The header and the rows of the table.
+    pub(super) content: String,
+}
+
+impl CsvTable {
+    pub(super) fn new(name: &'static str, header: &str) -> Self {
+        CsvTable {
+            name,
+            content: format!("{}\n", header),
+        }
+    }
+
+    /// Append a row, the fields are expected to be formatted already.
+    pub(super) fn push_row(&mut self, fields: &[String]) {
+        self.content.push_str(&fields.join(","));
+        self.content.push('\n');
+    }
+}
+
+/// Implemented by the records which are displayed as tables.
+pub(super) trait CsvTables {
+    /// Returns the tables shown in the terminal.
+    fn csv_tables(&self) -> Vec<CsvTable>;
+
+    /// Write each table to "{name}_{suffix}.csv" in dir, which is created if it does
+    /// not exist.
+    fn write_csv_tables(&self, dir: &Path, suffix: &str) -> io::Result<()> {
+        fs::create_dir_all(dir)?;
+        for table in self.csv_tables() {
+            fs::write(
+                dir.join(format!("{}_{}.csv", table.name, suffix)),
+                table.content,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the nesting level and the name of an indented label of the terminal output,
+/// in which each level is indented by four spaces.
+pub(super) fn split_label(label: &str) -> (usize, &str) {
+    let name = label.trim_start();
+    ((label.len() - name.len()) / 4, name)
+}
+
+/// Exports the opcode, cache and duration tables to a directory in csv format, one file
+/// per table and report, e.g. "opcodes_1000.csv", and "opcodes_1000_delta.csv" for the
+/// per-interval table.
+#[derive(Debug)]
+pub struct CsvSink {
+    dir: PathBuf,
+    /// Which of the cumulative and the per-interval tables are exported.
+    display_mode: DisplayMode,
+}
+
+impl CsvSink {
+    /// Creates a [CsvSink] exporting the cumulative tables to dir, which is created if it
+    /// does not exist.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        CsvSink {
+            dir: dir.into(),
+            display_mode: DisplayMode::default(),
+        }
+    }
+
+    /// Export the cumulative tables, the per-interval tables or both, the default is the
+    /// cumulative tables.
+    pub fn with_display_mode(mut self, display_mode: DisplayMode) -> Self {
+        self.display_mode = display_mode;
+        self
+    }
+
+    fn export<T: CsvTables>(&self, block_number: u64, record: &T, delta: &T) {
+        let mut result = Ok(());
+        if self.display_mode.cumulative() {
+            result = record.write_csv_tables(&self.dir, &block_number.to_string());
+        }
+        if self.display_mode.delta() {
+            result = result.and_then(|_| {
+                delta.write_csv_tables(&self.dir, &format!("{}_delta", block_number))
+            });
+        }
+        if let Err(err) = result {
+            eprintln!("Failed to write csv tables: {:?}", err);
+        }
+    }
+}
+
+impl MetricSink for CsvSink {
+    fn on_event(&mut self, event: &MetricEvent) {
+        match event {
+            #[cfg(feature = "enable_execution_duration_record")]
+            MetricEvent::ExecutionStageTime {
+                block_number,
+                record,
+                delta,
+            } => self.export(*block_number, record, delta),
+            #[cfg(feature = "enable_opcode_metrics")]
+            MetricEvent::OpcodeInfo {
+                block_number,
+                record,
+                delta,
+            } => self.export(*block_number, &**record, &**delta),
+            #[cfg(feature = "enable_cache_record")]
+            MetricEvent::CacheDbInfo {
+                block_number,
+                record,
+                delta,
+                ..
+            } => self.export(*block_number, record, delta),
+            _ => {}
+        }
+    }
+}
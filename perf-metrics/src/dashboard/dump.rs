@@ -0,0 +1,321 @@
+//! This module is used to dump every metric event to a file, in JSON Lines format so that
+//! the metrics can be analyzed by scripts, or in MessagePack format which is more compact,
+//! and to read the events back from the dumps.
+use super::sink::MetricSink;
+use crate::metrics::metric::MetricEvent;
+use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Cow,
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// An event with the time it is dumped, which is a line of the JSON Lines dump.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DumpedEvent<'a> {
+    /// Milliseconds since the unix epoch.
+    pub timestamp_ms: u64,
+    #[serde(flatten)]
+    pub event: Cow<'a, MetricEvent>,
+}
+
+impl<'a> DumpedEvent<'a> {
+    fn now(event: &'a MetricEvent) -> Self {
+        DumpedEvent {
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |duration| duration.as_millis() as u64),
+            event: Cow::Borrowed(event),
+        }
+    }
+}
+
+/// The format of a dump.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DumpFormat {
+    /// One JSON object per line.
+    JsonLines,
+    /// Consecutive MessagePack maps.
+    MessagePack,
+}
+
+impl DumpFormat {
+    /// Returns MessagePack if the extension of path is "msgpack" or "mpk", otherwise
+    /// JsonLines.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("msgpack" | "mpk") => DumpFormat::MessagePack,
+            _ => DumpFormat::JsonLines,
+        }
+    }
+}
+
+/// Open path for appending, it is created if it does not exist.
+fn open_for_append(path: &Path) -> io::Result<BufWriter<File>> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    Ok(BufWriter::new(file))
+}
+
+/// Appends every event to a file in JSON Lines format, each line is a JSON object with
+/// the block number, the timestamp (ms) and the kind of the event.
+#[derive(Debug)]
+pub struct JsonlSink {
+    writer: BufWriter<File>,
+}
+
+impl JsonlSink {
+    /// Open path for appending, it is created if it does not exist.
+    pub fn new(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(JsonlSink {
+            writer: open_for_append(path.as_ref())?,
+        })
+    }
+
+    /// Append event as a line.
+    fn write(&mut self, event: &DumpedEvent) -> io::Result<()> {
+        serde_json::to_writer(&mut self.writer, event)?;
+        self.writer.write_all(b"\n")
+    }
+}
+
+/// Appends every event to a file in MessagePack format, each event is a map with the
+/// same fields as a line of [JsonlSink].
+#[derive(Debug)]
+pub struct MessagePackSink {
+    writer: BufWriter<File>,
+}
+
+impl MessagePackSink {
+    /// Open path for appending, it is created if it does not exist.
+    pub fn new(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(MessagePackSink {
+            writer: open_for_append(path.as_ref())?,
+        })
+    }
+
+    /// Append event as a map.
+    fn write(&mut self, event: &DumpedEvent) -> io::Result<()> {
+        rmp_serde::encode::write_named(&mut self.writer, event).map_err(io::Error::other)
+    }
+}
+
+macro_rules! impl_dump_sink {
+    ($sink:ty) => {
+        impl MetricSink for $sink {
+            fn on_event(&mut self, event: &MetricEvent) {
+                if let Err(err) = self.write(&DumpedEvent::now(event)) {
+                    eprintln!("Failed to write metric event: {:?}", err);
+                }
+            }
+
+            fn on_replayed_event(&mut self, timestamp_ms: u64, event: &MetricEvent) {
+                let event = DumpedEvent {
+                    timestamp_ms,
+                    event: Cow::Borrowed(event),
+                };
+                if let Err(err) = self.write(&event) {
+                    eprintln!("Failed to write metric event: {:?}", err);
+                }
+            }
+
+            /// The events are flushed once the pending events are written, so that the
+            /// file can be followed while the node is running.
+            fn flush(&mut self) {
+                if let Err(err) = self.writer.flush() {
+                    eprintln!("Failed to flush metric events: {:?}", err);
+                }
+            }
+        }
+    };
+}
+
+impl_dump_sink!(JsonlSink);
+impl_dump_sink!(MessagePackSink);
+
+/// Reads the events of a dump in order.
+#[derive(Debug)]
+pub struct DumpReader {
+    reader: BufReader<File>,
+    format: DumpFormat,
+    line: String,
+}
+
+/// Open the dump at path, whose format is decided by [DumpFormat::from_path].
+pub fn read_dump(path: impl AsRef<Path>) -> io::Result<DumpReader> {
+    let path = path.as_ref();
+    Ok(DumpReader {
+        reader: BufReader::new(File::open(path)?),
+        format: DumpFormat::from_path(path),
+        line: String::new(),
+    })
+}
+
+impl DumpReader {
+    /// Returns the next event, or None at the end of the dump.
+    fn read_event(&mut self) -> io::Result<Option<DumpedEvent<'static>>> {
+        match self.format {
+            DumpFormat::JsonLines => loop {
+                self.line.clear();
+                if self.reader.read_line(&mut self.line)? == 0 {
+                    return Ok(None);
+                }
+                if !self.line.trim().is_empty() {
+                    return serde_json::from_str(&self.line)
+                        .map(Some)
+                        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err));
+                }
+            },
+            DumpFormat::MessagePack => {
+                if self.reader.fill_buf()?.is_empty() {
+                    return Ok(None);
+                }
+                rmp_serde::from_read(&mut self.reader)
+                    .map(Some)
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+            }
+        }
+    }
+}
+
+impl Iterator for DumpReader {
+    type Item = io::Result<DumpedEvent<'static>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_event().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns an event of every kind enabled by the features.
+    fn events() -> Vec<MetricEvent> {
+        #[allow(unused_mut)]
+        let mut events = Vec::new();
+        #[cfg(feature = "enable_execution_duration_record")]
+        events.push(MetricEvent::ExecutionStageTime {
+            block_number: 1,
+            record: Default::default(),
+            delta: Default::default(),
+        });
+        #[cfg(feature = "enable_tps_gas_record")]
+        {
+            let mut record = crate::metrics::TpsGasRecord::default();
+            record.record(2, u64::MAX as u128 + 1, u128::MAX);
+            events.push(MetricEvent::BlockTpsAndGas {
+                block_number: 2,
+                record: crate::metrics::TpsAndGasMessage::Record(record),
+            });
+            events.push(MetricEvent::BlockTpsAndGas {
+                block_number: 2,
+                record: crate::metrics::TpsAndGasMessage::Switch(true),
+            });
+        }
+        #[cfg(feature = "enable_opcode_metrics")]
+        {
+            let mut record = revm_utils::metrics::types::OpcodeRecord {
+                total_time: 300,
+                is_updated: true,
+                ..Default::default()
+            };
+            record.opcode_record[0x54] = (2, 300, i128::MIN);
+            record.opcode_time_stats[0x54].record(100);
+            record.add_sload_opcode_record(42.0);
+            events.push(MetricEvent::OpcodeInfo {
+                block_number: 3,
+                record: Box::new(record.clone()),
+                delta: Default::default(),
+            });
+            events.push(MetricEvent::TxOpcodeInfo {
+                block_number: 3,
+                record: Box::new(crate::metrics::TxOpcodeRecord {
+                    block_number: 3,
+                    tx_index: 1,
+                    tx_hash: Some([7; 32]),
+                    record,
+                }),
+            });
+        }
+        #[cfg(feature = "enable_cache_record")]
+        {
+            events.push(MetricEvent::CacheDbInfo {
+                block_number: 4,
+                size: 1024,
+                record: Default::default(),
+                delta: Default::default(),
+            });
+            let mut record = revm_utils::metrics::hot_keys::HotKeyRecord::new(2);
+            record
+                .accesses
+                .record(revm_utils::metrics::AccessKey::Storage([1; 20], [2; 32]));
+            events.push(MetricEvent::CacheHotKeys {
+                block_number: 4,
+                record: crate::metrics::HotKeysReport {
+                    from_block: 1,
+                    to_block: 4,
+                    record,
+                },
+            });
+        }
+        #[cfg(any(
+            feature = "enable_opcode_metrics",
+            feature = "enable_cache_record",
+            feature = "enable_execution_duration_record",
+            feature = "enable_tps_gas_record",
+        ))]
+        events.push(MetricEvent::MetricsHealth {
+            block_number: 5,
+            health: revm_utils::metrics::health::MetricsHealth {
+                overflows: 1,
+                negative_intervals: 2,
+            },
+        });
+        events
+    }
+
+    fn round_trip(file_name: &str, sink: fn(&Path) -> Box<dyn MetricSink>) {
+        let path = std::env::temp_dir().join(format!("{}-{}", std::process::id(), file_name));
+        let _ = std::fs::remove_file(&path);
+        let events = events();
+        {
+            let mut sink = sink(&path);
+            for (timestamp_ms, event) in events.iter().enumerate() {
+                sink.on_replayed_event(timestamp_ms as u64, event);
+            }
+            sink.flush();
+        }
+
+        let dumped: Vec<DumpedEvent> = read_dump(&path)
+            .unwrap()
+            .collect::<io::Result<_>>()
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(dumped.len(), events.len());
+        for (timestamp_ms, (dumped, event)) in dumped.iter().zip(events.iter()).enumerate() {
+            assert_eq!(dumped.timestamp_ms, timestamp_ms as u64);
+            assert_eq!(dumped.event.kind(), event.kind());
+            // MetricEvent is not PartialEq, so the events are compared as JSON values.
+            assert_eq!(
+                serde_json::to_value(dumped.event.as_ref()).unwrap(),
+                serde_json::to_value(event).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_jsonl_round_trip() {
+        round_trip("events.jsonl", |path| {
+            Box::new(JsonlSink::new(path).unwrap())
+        });
+    }
+
+    #[test]
+    fn test_message_pack_round_trip() {
+        round_trip("events.msgpack", |path| {
+            Box::new(MessagePackSink::new(path).unwrap())
+        });
+    }
+}
@@ -1,25 +1,35 @@
 //! This module is used to support the display of duration related metrics.
-use super::commons::*;
+#[cfg(feature = "enable_prometheus")]
+use super::prometheus::{PrometheusMetrics, PrometheusText};
+use super::{
+    commons::*,
+    csv::{split_label, CsvTable, CsvTables},
+};
 use crate::metrics::{ExecuteTxsRecord, ExecutionDurationRecord, WriteToDbRecord};
+use revm_utils::metrics::types::{TransactTimeBreakdown, TransactTimeStats, TxOutcome, TxType};
 
 const COL_WIDTH_LARGE: usize = 50;
 const COL_WIDTH_BIG: usize = 25;
 const COL_WIDTH_MIDDLE: usize = 15;
 
-fn print_time(cat: &str, cycles: u64, total: u64) {
+/// Returns the time (s) and its percentage (%) of total.
+fn time_and_pct(cycles: u64, total: u64) -> (f64, f64) {
     let time = cycles_as_secs(cycles);
-    let pct = time / cycles_as_secs(total);
+    (time, time / cycles_as_secs(total) * 100.0)
+}
+
+fn print_time(cat: &str, cycles: u64, total: u64) {
+    let (time, pct) = time_and_pct(cycles, total);
 
     println!(
         "{:<COL_WIDTH_LARGE$}{: >COL_WIDTH_MIDDLE$.3}{: >COL_WIDTH_MIDDLE$.2}",
-        cat,
-        time,
-        pct * 100.0,
+        cat, time, pct,
     );
 }
 
 impl ExecuteTxsRecord {
-    fn print_items(&self, ident: &str, total: u64) {
+    /// Returns the label and the time (cpu cycles) of each item of the breakdown.
+    fn items(&self) -> Vec<(&'static str, u64)> {
         let misc = self.total
             - self.transact
             - self.commit_changes
@@ -28,60 +38,37 @@ impl ExecuteTxsRecord {
             - self.merge_transactions
             - self.verify_receipt
             - self.save_receipts;
-        print_time(&(ident.to_owned() + "misc"), misc, total);
-        print_time(&(ident.to_owned() + "transact"), self.transact, total);
-        print_time(
-            &(ident.to_owned() + "    revm_transact"),
-            self.revm_transact.total,
-            total,
-        );
-        print_time(
-            &(ident.to_owned() + "    preverify_transaction_inner"),
-            self.revm_transact.preverify_transaction_inner,
-            total,
-        );
-        print_time(
-            &(ident.to_owned() + "    before execute(transact_preverified_inner)"),
-            self.revm_transact.transact_preverified_inner.before_execute,
-            total,
-        );
-        print_time(
-            &(ident.to_owned() + "    execute(transact_preverified_inner)"),
-            self.revm_transact.transact_preverified_inner.execute,
-            total,
-        );
-        print_time(
-            &(ident.to_owned() + "    after_execute(transact_preverified_inner)"),
-            self.revm_transact.transact_preverified_inner.after_execute,
-            total,
-        );
-        print_time(
-            &(ident.to_owned() + "    handler_end"),
-            self.revm_transact.handle_end,
-            total,
-        );
-        print_time(&(ident.to_owned() + "commit"), self.commit_changes, total);
-        print_time(&(ident.to_owned() + "add_receipt"), self.add_receipt, total);
-        print_time(
-            &(ident.to_owned() + "apply_post_execution_state_change"),
-            self.apply_post_execution_state_change,
-            total,
-        );
-        print_time(
-            &(ident.to_owned() + "merge_transactions"),
-            self.merge_transactions,
-            total,
-        );
-        print_time(
-            &(ident.to_owned() + "verify_receipt"),
-            self.verify_receipt,
-            total,
-        );
-        print_time(
-            &(ident.to_owned() + "save receipts"),
-            self.save_receipts,
-            total,
-        );
+        vec![
+            ("misc", misc),
+            ("transact", self.transact),
+            ("    revm_transact", self.revm_transact.total),
+            (
+                "    preverify_transaction_inner",
+                self.revm_transact.preverify_transaction_inner,
+            ),
+            (
+                "    before execute(transact_preverified_inner)",
+                self.revm_transact.transact_preverified_inner.before_execute,
+            ),
+            (
+                "    execute(transact_preverified_inner)",
+                self.revm_transact.transact_preverified_inner.execute,
+            ),
+            (
+                "    after_execute(transact_preverified_inner)",
+                self.revm_transact.transact_preverified_inner.after_execute,
+            ),
+            ("    handler_end", self.revm_transact.handle_end),
+            ("commit", self.commit_changes),
+            ("add_receipt", self.add_receipt),
+            (
+                "apply_post_execution_state_change",
+                self.apply_post_execution_state_change,
+            ),
+            ("merge_transactions", self.merge_transactions),
+            ("verify_receipt", self.verify_receipt),
+            ("save receipts", self.save_receipts),
+        ]
     }
 }
 
@@ -97,14 +84,70 @@ impl Print for ExecuteTxsRecord {
     }
     fn print_content(&self) {
         print_time("total", self.total, self.total);
-        self.print_items("", self.total);
+        for (label, cycles) in self.items() {
+            print_time(label, cycles, self.total);
+        }
     }
 }
 
-fn print_time_and_size(cat: &str, size: Option<usize>, cycles: u64, total_cycles: u64) {
-    let total_time = cycles_as_secs(total_cycles);
-    let time = cycles_as_secs(cycles);
-    let pct = time / total_time;
+fn print_transact_stats(key: &str, stats: &TransactTimeStats) {
+    let total = cycles_as_secs(stats.time.total);
+    let pct = |cycles: u64| cycles_as_secs(cycles) / total * 100.0;
+    let inner = &stats.time.transact_preverified_inner;
+
+    println!(
+        "{: <COL_WIDTH_MIDDLE$}{: >COL_WIDTH_MIDDLE$}{: >COL_WIDTH_MIDDLE$.3}{: >COL_WIDTH_MIDDLE$.3}\
+        {: >COL_WIDTH_MIDDLE$.2}{: >COL_WIDTH_MIDDLE$.2}{: >COL_WIDTH_MIDDLE$.2}{: >COL_WIDTH_MIDDLE$.2}{: >COL_WIDTH_MIDDLE$.2}",
+        key,
+        stats.count,
+        total,
+        total * 1_000_000.0 / stats.count as f64,
+        pct(stats.time.preverify_transaction_inner),
+        pct(inner.before_execute),
+        pct(inner.execute),
+        pct(inner.after_execute),
+        pct(stats.time.handle_end),
+    );
+}
+
+fn print_transact_title(key: &str) {
+    println!(
+        "{: <COL_WIDTH_MIDDLE$}{: >COL_WIDTH_MIDDLE$}{: >COL_WIDTH_MIDDLE$}{: >COL_WIDTH_MIDDLE$}\
+        {: >COL_WIDTH_MIDDLE$}{: >COL_WIDTH_MIDDLE$}{: >COL_WIDTH_MIDDLE$}{: >COL_WIDTH_MIDDLE$}{: >COL_WIDTH_MIDDLE$}",
+        key,
+        "Count",
+        "Time (s)",
+        "Avg (us)",
+        "Preverify (%)",
+        "Before (%)",
+        "Execute (%)",
+        "After (%)",
+        "End (%)",
+    );
+}
+
+impl Print for TransactTimeBreakdown {
+    fn print_title(&self) {
+        println!("=================================Breakdown of revm transact by tx type and outcome=================================");
+    }
+
+    fn print_content(&self) {
+        print_transact_title("Tx type");
+        for tx_type in TxType::ALL {
+            print_transact_stats(tx_type.name(), &self.by_type[tx_type as usize]);
+        }
+        println!();
+        print_transact_title("Outcome");
+        for outcome in TxOutcome::ALL {
+            print_transact_stats(outcome.name(), &self.by_outcome[outcome as usize]);
+        }
+    }
+}
+
+/// Returns the size (MB), the time (s), its percentage (%) of total and the rate (MB/s),
+/// the size and the rate are NaN if size is None.
+fn time_and_size(size: Option<usize>, cycles: u64, total_cycles: u64) -> (f64, f64, f64, f64) {
+    let (time, pct) = time_and_pct(cycles, total_cycles);
     let (size_value, rate_value) = match size {
         Some(size) => {
             let size = convert_bytes_to_mega(size);
@@ -113,14 +156,19 @@ fn print_time_and_size(cat: &str, size: Option<usize>, cycles: u64, total_cycles
         }
         None => (None, None),
     };
+    (
+        size_value.unwrap_or(f64::NAN),
+        time,
+        pct,
+        rate_value.unwrap_or(f64::NAN),
+    )
+}
 
+fn print_time_and_size(cat: &str, size: Option<usize>, cycles: u64, total_cycles: u64) {
+    let (size, time, pct, rate) = time_and_size(size, cycles, total_cycles);
     println!(
         "{: <COL_WIDTH_LARGE$}{: >COL_WIDTH_BIG$.3}{: >COL_WIDTH_MIDDLE$.3}{: >COL_WIDTH_MIDDLE$.2}{: >COL_WIDTH_BIG$.3}",
-        cat,
-        size_value.unwrap_or(std::f64::NAN),
-        time,
-        pct * 100.0,
-        rate_value.unwrap_or(std::f64::NAN)
+        cat, size, time, pct, rate
     );
 }
 
@@ -137,223 +185,200 @@ impl Print for WriteToDbRecord {
         );
     }
     fn print_content(&self) {
+        for (label, size, cycles) in self.size_items() {
+            print_time_and_size(label, size, cycles, self.total);
+        }
+    }
+}
+
+impl WriteToDbRecord {
+    /// Returns the label, the data size and the time (cpu cycles) of each item of the
+    /// breakdown with data size.
+    fn size_items(&self) -> Vec<(&'static str, Option<usize>, u64)> {
         let total_size = self.revert_storage_size
             + self.revert_account_size
             + self.write_receipts_size
             + self.state_account_size
             + self.state_bytecode_size
             + self.state_storage_size;
+        vec![
+            ("total", Some(total_size), self.total),
+            (
+                "write storage (revert state)",
+                Some(self.revert_storage_size),
+                self.revert_storage_time,
+            ),
+            (
+                "    write storage iter time (revert state)",
+                None,
+                self.revert_storage_time - self.revert_storage_append_time,
+            ),
+            (
+                "    write storage append time (revert state)",
+                Some(self.revert_storage_size),
+                self.revert_storage_append_time,
+            ),
+            (
+                "write account (revert state)",
+                Some(self.revert_account_size),
+                self.revert_account_time,
+            ),
+            (
+                "    write account iter time (revert state)",
+                None,
+                self.revert_account_time - self.revert_account_append_time,
+            ),
+            (
+                "    write account append time (revert state)",
+                Some(self.revert_account_size),
+                self.revert_account_append_time,
+            ),
+            (
+                "write_receipts",
+                Some(self.write_receipts_size),
+                self.write_receipts_time,
+            ),
+            (
+                "    write receipts iter time",
+                None,
+                self.write_receipts_time - self.receipts_append_time,
+            ),
+            (
+                "    write receipts append time",
+                Some(self.write_receipts_size),
+                self.receipts_append_time,
+            ),
+            ("sort state changes", None, self.sort_time),
+            (
+                "write account (state changes)",
+                Some(self.state_account_size),
+                self.state_account_time,
+            ),
+            (
+                "    write account iter time (state changes)",
+                None,
+                self.state_account_time - self.state_account_upsert_time,
+            ),
+            (
+                "    write account upsert time (state changes)",
+                Some(self.state_account_size),
+                self.state_account_upsert_time,
+            ),
+            (
+                "write bytecode (state changes)",
+                Some(self.state_bytecode_size),
+                self.state_bytecode_time,
+            ),
+            (
+                "    write bytecode iter time (state changes)",
+                None,
+                self.state_bytecode_time - self.state_bytecode_upsert_time,
+            ),
+            (
+                "    write bytecode upsert time (state changes)",
+                Some(self.state_bytecode_size),
+                self.state_bytecode_upsert_time,
+            ),
+            (
+                "write storage (state_changes)",
+                Some(self.state_storage_size),
+                self.state_storage_time,
+            ),
+            (
+                "    write storage iter time (state_changes)",
+                None,
+                self.state_storage_time - self.state_storage_upsert_time,
+            ),
+            (
+                "    write storage upsert time (state_changes)",
+                Some(self.state_storage_size),
+                self.state_storage_upsert_time,
+            ),
+        ]
+    }
 
-        print_time_and_size("total", Some(total_size), self.total, self.total);
-        print_time_and_size(
-            "write storage (revert state)",
-            Some(self.revert_storage_size),
-            self.revert_storage_time,
-            self.total,
-        );
-        print_time_and_size(
-            "    write storage iter time (revert state)",
-            None,
-            self.revert_storage_time - self.revert_storage_append_time,
-            self.total,
-        );
-        print_time_and_size(
-            "    write storage append time (revert state)",
-            Some(self.revert_storage_size),
-            self.revert_storage_append_time,
-            self.total,
-        );
-        print_time_and_size(
-            "write account (revert state)",
-            Some(self.revert_account_size),
-            self.revert_account_time,
-            self.total,
-        );
-        print_time_and_size(
-            "    write account iter time (revert state)",
-            None,
-            self.revert_account_time - self.revert_account_append_time,
-            self.total,
-        );
-        print_time_and_size(
-            "    write account append time (revert state)",
-            Some(self.revert_account_size),
-            self.revert_account_append_time,
-            self.total,
-        );
-        print_time_and_size(
-            "write_receipts",
-            Some(self.write_receipts_size),
-            self.write_receipts_time,
-            self.total,
-        );
-        print_time_and_size(
-            "    write receipts iter time",
-            None,
-            self.write_receipts_time - self.receipts_append_time,
-            self.total,
-        );
-        print_time_and_size(
-            "    write receipts append time",
-            Some(self.write_receipts_size),
-            self.receipts_append_time,
-            self.total,
-        );
-        print_time_and_size("sort state changes", None, self.sort_time, self.total);
-        print_time_and_size(
-            "write account (state changes)",
-            Some(self.state_account_size),
-            self.state_account_time,
-            self.total,
-        );
-        print_time_and_size(
-            "    write account iter time (state changes)",
-            None,
-            self.state_account_time - self.state_account_upsert_time,
-            self.total,
-        );
-        print_time_and_size(
-            "    write account upsert time (state changes)",
-            Some(self.state_account_size),
-            self.state_account_upsert_time,
-            self.total,
-        );
-        print_time_and_size(
-            "write bytecode (state changes)",
-            Some(self.state_bytecode_size),
-            self.state_bytecode_time,
-            self.total,
-        );
-        print_time_and_size(
-            "    write bytecode iter time (state changes)",
-            None,
-            self.state_bytecode_time - self.state_bytecode_upsert_time,
-            self.total,
-        );
-        print_time_and_size(
-            "    write bytecode upsert time (state changes)",
-            Some(self.state_bytecode_size),
-            self.state_bytecode_upsert_time,
-            self.total,
-        );
-        print_time_and_size(
-            "write storage (state_changes)",
-            Some(self.state_storage_size),
-            self.state_storage_time,
-            self.total,
-        );
-        print_time_and_size(
-            "    write storage iter time (state_changes)",
-            None,
-            self.state_storage_time - self.state_storage_upsert_time,
-            self.total,
-        );
-        print_time_and_size(
-            "    write storage upsert time (state_changes)",
-            Some(self.state_storage_size),
-            self.state_storage_upsert_time,
-            self.total,
-        );
+    /// Returns the label and the time (cpu cycles) of each item of the breakdown.
+    fn items(&self) -> Vec<(&'static str, u64)> {
+        vec![
+            ("write storage (revert state)", self.revert_storage_time),
+            (
+                "    write storage iter time (revert state)",
+                self.revert_storage_time - self.revert_storage_append_time,
+            ),
+            (
+                "    write storage append time (revert state)",
+                self.revert_storage_append_time,
+            ),
+            ("write account (revert state)", self.revert_account_time),
+            (
+                "    write account iter time (revert state)",
+                self.revert_account_time - self.revert_account_append_time,
+            ),
+            (
+                "    write account append time (revert state)",
+                self.revert_account_append_time,
+            ),
+            ("write_receipts", self.write_receipts_time),
+            (
+                "    write receipts iter time",
+                self.write_receipts_time - self.receipts_append_time,
+            ),
+            ("    write receipts append time", self.receipts_append_time),
+            ("sort state changes", self.sort_time),
+            ("write account (state changes)", self.state_account_time),
+            (
+                "    write account iter time (state changes)",
+                self.state_account_time - self.state_account_upsert_time,
+            ),
+            (
+                "    write account upsert time (state changes)",
+                self.state_account_upsert_time,
+            ),
+            ("write bytecode (state changes)", self.state_bytecode_time),
+            (
+                "    write bytecode iter time (state changes)",
+                self.state_bytecode_time - self.state_bytecode_upsert_time,
+            ),
+            (
+                "    write bytecode upsert time (state changes)",
+                self.state_bytecode_upsert_time,
+            ),
+            ("write storage (state_changes)", self.state_storage_time),
+            (
+                "    write storage iter time (state_changes)",
+                self.state_storage_time - self.state_storage_upsert_time,
+            ),
+            (
+                "    write storage upsert time (state_changes)",
+                self.state_storage_upsert_time,
+            ),
+        ]
     }
 }
 
-impl WriteToDbRecord {
-    fn print_items(&self, ident: &str, total: u64) {
-        print_time(
-            &(ident.to_owned() + "write storage (revert state)"),
-            self.revert_storage_time,
-            total,
-        );
-        print_time(
-            &(ident.to_owned() + "    write storage iter time (revert state)"),
-            self.revert_storage_time - self.revert_storage_append_time,
-            total,
-        );
-        print_time(
-            &(ident.to_owned() + "    write storage append time (revert state)"),
-            self.revert_storage_append_time,
-            total,
-        );
-        print_time(
-            &(ident.to_owned() + "write account (revert state)"),
-            self.revert_account_time,
-            total,
-        );
-        print_time(
-            &(ident.to_owned() + "    write account iter time (revert state)"),
-            self.revert_account_time - self.revert_account_append_time,
-            total,
-        );
-        print_time(
-            &(ident.to_owned() + "    write account append time (revert state)"),
-            self.revert_account_append_time,
-            total,
-        );
-        print_time(
-            &(ident.to_owned() + "write_receipts"),
-            self.write_receipts_time,
-            total,
-        );
-        print_time(
-            &(ident.to_owned() + "    write receipts iter time"),
-            self.write_receipts_time - self.receipts_append_time,
-            total,
-        );
-        print_time(
-            &(ident.to_owned() + "    write receipts append time"),
-            self.receipts_append_time,
-            total,
-        );
-        print_time(
-            &(ident.to_owned() + "sort state changes"),
-            self.sort_time,
-            total,
-        );
-        print_time(
-            &(ident.to_owned() + "write account (state changes)"),
-            self.state_account_time,
-            total,
-        );
-        print_time(
-            &(ident.to_owned() + "    write account iter time (state changes)"),
-            self.state_account_time - self.state_account_upsert_time,
-            total,
-        );
-        print_time(
-            &(ident.to_owned() + "    write account upsert time (state changes)"),
-            self.state_account_upsert_time,
-            total,
-        );
-        print_time(
-            &(ident.to_owned() + "write bytecode (state changes)"),
-            self.state_bytecode_time,
-            total,
-        );
-        print_time(
-            &(ident.to_owned() + "    write bytecode iter time (state changes)"),
-            self.state_bytecode_time - self.state_bytecode_upsert_time,
-            total,
-        );
-        print_time(
-            &(ident.to_owned() + "    write bytecode upsert time (state changes)"),
-            self.state_bytecode_upsert_time,
-            total,
-        );
-        print_time(
-            &(ident.to_owned() + "write storage (state_changes)"),
-            self.state_storage_time,
-            total,
-        );
-        print_time(
-            &(ident.to_owned() + "    write storage iter time (state_changes)"),
-            self.state_storage_time - self.state_storage_upsert_time,
-            total,
-        );
-        print_time(
-            &(ident.to_owned() + "    write storage upsert time (state_changes)"),
-            self.state_storage_upsert_time,
-            total,
-        );
+impl ExecutionDurationRecord {
+    /// Returns the label and the time (cpu cycles) of each item of the breakdown.
+    pub(super) fn items(&self) -> Vec<(String, u64)> {
+        let misc = self.total
+            - self.block_td
+            - self.block_with_senders
+            - self.execution.total
+            - self.write_to_db.total;
+        let mut items = vec![
+            ("total".to_string(), self.total),
+            ("misc".to_string(), misc),
+            ("block_td".to_string(), self.block_td),
+            ("block_with_senders".to_string(), self.block_with_senders),
+            (
+                "execute_and_verify_receipt".to_string(),
+                self.execution.total,
+            ),
+        ];
+        let indent = |(label, cycles): (&str, u64)| ("    ".to_owned() + label, cycles);
+        items.extend(self.execution.items().into_iter().map(indent));
+        items.push(("write_to_db".to_string(), self.write_to_db.total));
+        items.extend(self.write_to_db.items().into_iter().map(indent));
+        items
     }
 }
 
@@ -369,23 +394,9 @@ impl Print for ExecutionDurationRecord {
     }
 
     fn print_content(&self) {
-        let misc = self.total
-            - self.block_td
-            - self.block_with_senders
-            - self.execution.total
-            - self.write_to_db.total;
-        print_time("total", self.total, self.total);
-        print_time("misc", misc, self.total);
-        print_time("block_td", self.block_td, self.total);
-        print_time("block_with_senders", self.block_with_senders, self.total);
-        print_time(
-            "execute_and_verify_receipt",
-            self.execution.total,
-            self.total,
-        );
-        self.execution.print_items("    ", self.total);
-        print_time("write_to_db", self.write_to_db.total, self.total);
-        self.write_to_db.print_items("    ", self.total);
+        for (label, cycles) in self.items() {
+            print_time(&label, cycles, self.total);
+        }
     }
 
     fn print(&self, _block_number: u64) {
@@ -397,8 +408,85 @@ impl Print for ExecutionDurationRecord {
 
         // Print execute txs duration.
         self.execution.print(_block_number);
+        self.execution.revm_transact_breakdown.print(_block_number);
 
         // Print write_to_db duration.
         self.write_to_db.print(_block_number);
     }
 }
+
+impl CsvTables for ExecutionDurationRecord {
+    fn csv_tables(&self) -> Vec<CsvTable> {
+        let mut stage = CsvTable::new("execution_stage", "category,level,time_s,time_pct");
+        for (label, cycles) in self.items() {
+            let (level, name) = split_label(&label);
+            let (time, pct) = time_and_pct(cycles, self.total);
+            stage.push_row(&[
+                name.to_string(),
+                level.to_string(),
+                format!("{:.3}", time),
+                format!("{:.2}", pct),
+            ]);
+        }
+
+        let write_to_db = &self.write_to_db;
+        let mut db = CsvTable::new(
+            "write_to_db",
+            "category,level,size_mb,time_s,time_pct,rate_mb_s",
+        );
+        for (label, size, cycles) in write_to_db.size_items() {
+            let (level, name) = split_label(label);
+            let (size, time, pct, rate) = time_and_size(size, cycles, write_to_db.total);
+            db.push_row(&[
+                name.to_string(),
+                level.to_string(),
+                format!("{:.3}", size),
+                format!("{:.3}", time),
+                format!("{:.2}", pct),
+                format!("{:.3}", rate),
+            ]);
+        }
+
+        vec![stage, db]
+    }
+}
+
+#[cfg(feature = "enable_prometheus")]
+impl PrometheusMetrics for ExecutionDurationRecord {
+    fn write_metrics(&self, text: &mut PrometheusText) {
+        let name = "reth_execution_stage_seconds_total";
+        text.family(
+            name,
+            "counter",
+            "The time spent on each step of the execution stage, a step is part of its parent.",
+        );
+        // The names of the items which contain the current item, by level.
+        let mut parents: Vec<String> = Vec::new();
+        for (label, cycles) in self.items() {
+            let (level, stage) = split_label(&label);
+            parents.truncate(level);
+            let parent = parents.last().map_or("", String::as_str);
+            text.sample(
+                name,
+                &[("stage", stage), ("parent", parent)],
+                cycles_as_secs(cycles),
+            );
+            parents.push(stage.to_string());
+        }
+
+        let name = "reth_write_to_db_bytes_total";
+        text.family(
+            name,
+            "counter",
+            "The size of the data written to db by each step.",
+        );
+        for (label, size, _) in self.write_to_db.size_items() {
+            let (level, category) = split_label(label);
+            if let (0, Some(size)) = (level, size) {
+                if category != "total" {
+                    text.sample(name, &[("category", category)], size as f64);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,878 @@
+//! This module is used to render the records of a run as a single html file with inline
+//! svg charts and sortable tables, which can be shared with people who don't read the
+//! terminal output.
+#[cfg(feature = "enable_cache_record")]
+use super::cache::CacheStats;
+#[cfg(any(
+    feature = "enable_opcode_metrics",
+    feature = "enable_execution_duration_record",
+))]
+use super::commons::cycles_as_secs;
+#[cfg(feature = "enable_execution_duration_record")]
+use super::csv::split_label;
+#[cfg(feature = "enable_opcode_metrics")]
+use super::opcode::{OpcodeStats, OPCODE_NUMBER};
+use super::sink::MetricSink;
+#[cfg(feature = "enable_tps_gas_record")]
+use super::tps_gas::{impl_tps_report_interval, TpsAndGasDisplayer};
+use crate::metrics::metric::MetricEvent;
+#[cfg(feature = "enable_opcode_metrics")]
+use revm::revm_opcode::OpCode;
+#[cfg(feature = "enable_opcode_metrics")]
+use revm_utils::metrics::types::{state_access_index, OpcodeRecord};
+#[cfg(feature = "enable_cache_record")]
+use revm_utils::metrics::types::{CacheDbRecord, Function};
+use std::{fmt::Write as _, fs, path::PathBuf};
+
+const CHART_WIDTH: f64 = 800.0;
+const CHART_HEIGHT: f64 = 320.0;
+/// The left, right, top and bottom margins around the plot area of a chart, the right one
+/// holds the legend.
+const MARGINS: (f64, f64, f64, f64) = (70.0, 170.0, 20.0, 45.0);
+const PALETTE: [&str; 10] = [
+    "#4e79a7", "#f28e2b", "#e15759", "#76b7b2", "#59a14f", "#edc948", "#b07aa1", "#ff9da7",
+    "#9c755f", "#bab0ac",
+];
+const NO_DATA: &str = "<p class=\"no-data\">No data.</p>";
+/// Number of ticks of a linear axis.
+const TICKS: usize = 5;
+#[cfg(feature = "enable_opcode_metrics")]
+const SLOAD: u8 = 0x54;
+/// The categories below this share of the opcode time are merged into "other".
+#[cfg(feature = "enable_opcode_metrics")]
+const MIN_PIE_SHARE: f64 = 0.01;
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em;color:#222}\
+h2{margin-top:2em;border-bottom:1px solid #ccc}\
+svg{display:block;margin:1em 0}\
+svg text{font-size:12px;fill:#333}\
+table{border-collapse:collapse;font-size:13px}\
+th,td{padding:3px 8px;border-bottom:1px solid #eee;text-align:right}\
+th:first-child,td:first-child{text-align:left}\
+th{cursor:pointer;background:#f4f4f4;position:sticky;top:0}\
+th[data-order=asc]::after{content:' \\25B2'}\
+th[data-order=desc]::after{content:' \\25BC'}\
+.no-data{color:#888}";
+
+/// Sorts a table by the column whose header is clicked, by the data-value of the cells if
+/// they are numbers, otherwise by their text.
+const SORT_SCRIPT: &str = r#"document.querySelectorAll("table.sortable").forEach(function (table) {
+  table.querySelectorAll("th").forEach(function (th, column) {
+    th.addEventListener("click", function () {
+      var ascending = th.dataset.order !== "asc";
+      table.querySelectorAll("th").forEach(function (other) { delete other.dataset.order; });
+      th.dataset.order = ascending ? "asc" : "desc";
+      var body = table.tBodies[0];
+      var rows = Array.from(body.rows);
+      rows.sort(function (a, b) {
+        var x = a.cells[column], y = b.cells[column];
+        var nx = parseFloat(x.dataset.value), ny = parseFloat(y.dataset.value);
+        var order = !isNaN(nx) && !isNaN(ny) ? nx - ny : x.textContent.localeCompare(y.textContent);
+        return ascending ? order : -order;
+      });
+      rows.forEach(function (row) { body.appendChild(row); });
+    });
+  });
+});"#;
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn format_tick(value: f64) -> String {
+    let abs = value.abs();
+    if abs >= 1_000_000.0 {
+        format!("{:.1e}", value)
+    } else if abs >= 100.0 || abs == 0.0 {
+        format!("{:.0}", value)
+    } else if abs >= 1.0 {
+        format!("{:.1}", value)
+    } else {
+        format!("{:.2}", value)
+    }
+}
+
+/// Returns TICKS evenly spaced ticks from min to max.
+fn linear_ticks(min: f64, max: f64) -> Vec<(f64, String)> {
+    (0..TICKS)
+        .map(|i| {
+            let value = min + (max - min) * i as f64 / (TICKS - 1) as f64;
+            (value, format_tick(value))
+        })
+        .collect()
+}
+
+/// Returns a tick at each power of 10 from min to max, which are positive.
+#[cfg(any(
+    feature = "enable_opcode_metrics",
+    feature = "enable_cache_record",
+    feature = "enable_tps_gas_record",
+))]
+fn log_ticks(min: f64, max: f64) -> Vec<(f64, String)> {
+    let ticks: Vec<(f64, String)> = (min.log10().ceil() as i32..=max.log10().floor() as i32)
+        .map(|exp| {
+            let value = 10f64.powi(exp);
+            (value, format_tick(value))
+        })
+        .collect();
+    if ticks.is_empty() {
+        vec![(min, format_tick(min)), (max, format_tick(max))]
+    } else {
+        ticks
+    }
+}
+
+/// An svg chart, whose plot area maps the ranges of x and y.
+struct Plot {
+    x_range: (f64, f64),
+    y_range: (f64, f64),
+    /// Whether the x axis is in log scale, in which case x_range is of log10(x).
+    log_x: bool,
+    svg: String,
+}
+
+impl Plot {
+    fn new(x_range: (f64, f64), y_range: (f64, f64), log_x: bool) -> Self {
+        let transform = |value: f64| if log_x { value.log10() } else { value };
+        let (x_min, mut x_max) = (transform(x_range.0), transform(x_range.1));
+        if x_max <= x_min {
+            x_max = x_min + 1.0;
+        }
+        let (y_min, mut y_max) = y_range;
+        if y_max <= y_min {
+            y_max = y_min + 1.0;
+        }
+        Plot {
+            x_range: (x_min, x_max),
+            y_range: (y_min, y_max),
+            log_x,
+            svg: format!(
+                "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">",
+                w = CHART_WIDTH,
+                h = CHART_HEIGHT
+            ),
+        }
+    }
+
+    fn x(&self, value: f64) -> f64 {
+        let value = if self.log_x { value.log10() } else { value };
+        let width = CHART_WIDTH - MARGINS.0 - MARGINS.1;
+        MARGINS.0 + (value - self.x_range.0) / (self.x_range.1 - self.x_range.0) * width
+    }
+
+    fn y(&self, value: f64) -> f64 {
+        let height = CHART_HEIGHT - MARGINS.2 - MARGINS.3;
+        CHART_HEIGHT
+            - MARGINS.3
+            - (value - self.y_range.0) / (self.y_range.1 - self.y_range.0) * height
+    }
+
+    fn axes(&mut self, x_ticks: &[(f64, String)], x_label: &str, y_label: &str) {
+        let (left, right) = (MARGINS.0, CHART_WIDTH - MARGINS.1);
+        let (top, bottom) = (MARGINS.2, CHART_HEIGHT - MARGINS.3);
+        let _ = write!(
+            self.svg,
+            "<line x1=\"{left}\" y1=\"{bottom}\" x2=\"{right}\" y2=\"{bottom}\" stroke=\"#333\"/>\
+            <line x1=\"{left}\" y1=\"{top}\" x2=\"{left}\" y2=\"{bottom}\" stroke=\"#333\"/>"
+        );
+        for (value, label) in x_ticks {
+            let x = self.x(*value);
+            let _ = write!(
+                self.svg,
+                "<line x1=\"{x:.1}\" y1=\"{bottom}\" x2=\"{x:.1}\" y2=\"{}\" stroke=\"#333\"/>\
+                <text x=\"{x:.1}\" y=\"{}\" text-anchor=\"middle\">{}</text>",
+                bottom + 4.0,
+                bottom + 16.0,
+                escape(label)
+            );
+        }
+        for (value, label) in linear_ticks(self.y_range.0, self.y_range.1) {
+            let y = self.y(value);
+            let _ = write!(
+                self.svg,
+                "<line x1=\"{left}\" y1=\"{y:.1}\" x2=\"{right}\" y2=\"{y:.1}\" stroke=\"#eee\"/>\
+                <text x=\"{}\" y=\"{:.1}\" text-anchor=\"end\">{}</text>",
+                left - 6.0,
+                y + 4.0,
+                escape(&label)
+            );
+        }
+        let _ = write!(
+            self.svg,
+            "<text x=\"{:.1}\" y=\"{}\" text-anchor=\"middle\">{}</text>\
+            <text x=\"14\" y=\"{:.1}\" text-anchor=\"middle\" transform=\"rotate(-90 14 {:.1})\">{}</text>",
+            (left + right) / 2.0,
+            CHART_HEIGHT - 8.0,
+            escape(x_label),
+            (top + bottom) / 2.0,
+            (top + bottom) / 2.0,
+            escape(y_label)
+        );
+    }
+
+    fn legend<'a>(&mut self, names: impl Iterator<Item = &'a str>) {
+        let x = CHART_WIDTH - MARGINS.1 + 15.0;
+        for (i, name) in names.enumerate() {
+            let y = MARGINS.2 + 10.0 + i as f64 * 18.0;
+            let _ = write!(
+                self.svg,
+                "<rect x=\"{x}\" y=\"{}\" width=\"10\" height=\"10\" fill=\"{}\"/>\
+                <text x=\"{}\" y=\"{y}\">{}</text>",
+                y - 9.0,
+                PALETTE[i % PALETTE.len()],
+                x + 15.0,
+                escape(name)
+            );
+        }
+    }
+
+    fn finish(mut self) -> String {
+        self.svg.push_str("</svg>");
+        self.svg
+    }
+}
+
+/// A named line of a line chart.
+#[cfg(any(
+    feature = "enable_opcode_metrics",
+    feature = "enable_cache_record",
+    feature = "enable_tps_gas_record",
+))]
+struct Series {
+    name: String,
+    points: Vec<(f64, f64)>,
+}
+
+/// Returns a line chart of series, in which y starts from 0. If log_x, the x of the
+/// points should be positive.
+#[cfg(any(
+    feature = "enable_opcode_metrics",
+    feature = "enable_cache_record",
+    feature = "enable_tps_gas_record",
+))]
+fn line_chart(series: &[Series], x_label: &str, y_label: &str, log_x: bool) -> String {
+    let points = || series.iter().flat_map(|series| series.points.iter());
+    if points().next().is_none() {
+        return NO_DATA.to_string();
+    }
+    let x_min = points().map(|point| point.0).fold(f64::INFINITY, f64::min);
+    let x_max = points()
+        .map(|point| point.0)
+        .fold(f64::NEG_INFINITY, f64::max);
+    let y_max = points().map(|point| point.1).fold(0.0, f64::max);
+
+    let mut plot = Plot::new((x_min, x_max), (0.0, y_max), log_x);
+    let x_ticks = if log_x {
+        log_ticks(x_min, x_max)
+    } else {
+        linear_ticks(x_min, x_max)
+    };
+    plot.axes(&x_ticks, x_label, y_label);
+    for (i, series) in series.iter().enumerate() {
+        let points: Vec<String> = series
+            .points
+            .iter()
+            .map(|(x, y)| format!("{:.1},{:.1}", plot.x(*x), plot.y(*y)))
+            .collect();
+        let _ = write!(
+            plot.svg,
+            "<polyline points=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"1.5\"/>",
+            points.join(" "),
+            PALETTE[i % PALETTE.len()]
+        );
+    }
+    plot.legend(series.iter().map(|series| series.name.as_str()));
+    plot.finish()
+}
+
+/// Returns a stacked bar chart with a bar of each (label, values), the values of which
+/// are stacked in the order of names.
+#[cfg(feature = "enable_execution_duration_record")]
+fn stacked_bar_chart(
+    names: &[String],
+    bars: &[(String, Vec<f64>)],
+    x_label: &str,
+    y_label: &str,
+) -> String {
+    if bars.is_empty() {
+        return NO_DATA.to_string();
+    }
+    let y_max = bars
+        .iter()
+        .map(|(_, values)| values.iter().sum::<f64>())
+        .fold(0.0, f64::max);
+    // Each bar is centered at its index.
+    let mut plot = Plot::new((-0.5, bars.len() as f64 - 0.5), (0.0, y_max), false);
+    let step = bars.len().div_ceil(TICKS).max(1);
+    let x_ticks: Vec<(f64, String)> = bars
+        .iter()
+        .enumerate()
+        .step_by(step)
+        .map(|(i, (label, _))| (i as f64, label.clone()))
+        .collect();
+    plot.axes(&x_ticks, x_label, y_label);
+
+    let width = (plot.x(1.0) - plot.x(0.0)) * 0.8;
+    for (i, (label, values)) in bars.iter().enumerate() {
+        let x = plot.x(i as f64) - width / 2.0;
+        let mut bottom = 0.0;
+        for (j, value) in values.iter().enumerate() {
+            let top = bottom + value.max(0.0);
+            let _ = write!(
+                plot.svg,
+                "<rect x=\"{x:.1}\" y=\"{:.1}\" width=\"{width:.1}\" height=\"{:.1}\" fill=\"{}\">\
+                <title>{}: {} {:.3}</title></rect>",
+                plot.y(top),
+                plot.y(bottom) - plot.y(top),
+                PALETTE[j % PALETTE.len()],
+                escape(label),
+                escape(&names[j]),
+                value
+            );
+            bottom = top;
+        }
+    }
+    plot.legend(names.iter().map(String::as_str));
+    plot.finish()
+}
+
+/// Returns a pie chart of slices, whose legend shows the share of each slice.
+#[cfg(feature = "enable_opcode_metrics")]
+fn pie_chart(slices: &[(String, f64)]) -> String {
+    let total: f64 = slices.iter().map(|(_, value)| value).sum();
+    if total <= 0.0 {
+        return NO_DATA.to_string();
+    }
+    let radius = (CHART_HEIGHT - MARGINS.2 - MARGINS.3) / 2.0;
+    let (cx, cy) = (MARGINS.0 + radius, MARGINS.2 + radius);
+    let mut plot = Plot::new((0.0, 1.0), (0.0, 1.0), false);
+    let mut angle = -std::f64::consts::FRAC_PI_2;
+    for (i, (name, value)) in slices.iter().enumerate() {
+        let share = value / total;
+        let color = PALETTE[i % PALETTE.len()];
+        let title = format!("<title>{} {:.2}%</title>", escape(name), share * 100.0);
+        if share >= 1.0 {
+            let _ = write!(
+                plot.svg,
+                "<circle cx=\"{cx}\" cy=\"{cy}\" r=\"{radius}\" fill=\"{color}\">{title}</circle>"
+            );
+            continue;
+        }
+        let end = angle + share * std::f64::consts::TAU;
+        let _ = write!(
+            plot.svg,
+            "<path d=\"M{cx},{cy} L{:.2},{:.2} A{radius},{radius} 0 {} 1 {:.2},{:.2} Z\" fill=\"{color}\">{title}</path>",
+            cx + radius * angle.cos(),
+            cy + radius * angle.sin(),
+            u8::from(share > 0.5),
+            cx + radius * end.cos(),
+            cy + radius * end.sin(),
+        );
+        angle = end;
+    }
+    let names: Vec<String> = slices
+        .iter()
+        .map(|(name, value)| format!("{} ({:.1}%)", name, value / total * 100.0))
+        .collect();
+    plot.legend(names.iter().map(String::as_str));
+    plot.finish()
+}
+
+/// Returns the slices of (name, value) in descending order of value, in which the unnamed
+/// ones and the ones less than MIN_PIE_SHARE of total are merged into "other".
+#[cfg(feature = "enable_opcode_metrics")]
+fn pie_slices<'a>(values: impl Iterator<Item = (&'a str, f64)>, total: f64) -> Vec<(String, f64)> {
+    let mut slices = Vec::new();
+    let mut other = 0.0;
+    for (name, value) in values {
+        if name.is_empty() || value < total * MIN_PIE_SHARE {
+            other += value;
+        } else {
+            slices.push((name.to_string(), value));
+        }
+    }
+    slices.sort_by(|a, b| b.1.total_cmp(&a.1));
+    if other > 0.0 {
+        slices.push(("other".to_string(), other));
+    }
+    slices
+}
+
+/// Returns the points of the cumulative distribution of buckets of (upper bound in ns,
+/// count) in ascending order. The buckets with an infinite bound are only counted.
+#[cfg(any(feature = "enable_opcode_metrics", feature = "enable_cache_record"))]
+fn cdf(buckets: impl Iterator<Item = (f64, u64)>) -> Vec<(f64, f64)> {
+    let buckets: Vec<(f64, u64)> = buckets.collect();
+    let total = buckets
+        .iter()
+        .fold(0u64, |total, (_, count)| total.saturating_add(*count));
+    if total == 0 {
+        return Vec::new();
+    }
+    let mut cumulative = 0u64;
+    let mut points = Vec::new();
+    for (upper_bound, count) in buckets {
+        cumulative = cumulative.saturating_add(count);
+        if count > 0 && upper_bound.is_finite() {
+            // The x axis is in log scale.
+            points.push((upper_bound.max(1.0), cumulative as f64 / total as f64));
+        }
+    }
+    points
+}
+
+/// Returns a table sortable by clicking its header, each cell of which is the text and
+/// the value it is sorted by, if it is a number.
+#[cfg(any(feature = "enable_opcode_metrics", feature = "enable_cache_record"))]
+fn sortable_table(header: &[&str], rows: &[Vec<(String, Option<f64>)>]) -> String {
+    let mut html = String::from("<table class=\"sortable\"><thead><tr>");
+    for name in header {
+        let _ = write!(html, "<th>{}</th>", escape(name));
+    }
+    html.push_str("</tr></thead><tbody>");
+    for row in rows {
+        html.push_str("<tr>");
+        for (text, value) in row {
+            match value {
+                Some(value) => {
+                    let _ = write!(html, "<td data-value=\"{}\">{}</td>", value, escape(text));
+                }
+                None => {
+                    let _ = write!(html, "<td>{}</td>", escape(text));
+                }
+            }
+        }
+        html.push_str("</tr>");
+    }
+    html.push_str("</tbody></table>");
+    html
+}
+
+/// Returns a numeric cell of sortable_table.
+#[cfg(any(feature = "enable_opcode_metrics", feature = "enable_cache_record"))]
+fn number(value: f64, precision: usize) -> (String, Option<f64>) {
+    (format!("{:.*}", precision, value), Some(value))
+}
+
+/// Collects the records of a run, and renders them as a single html file with charts
+/// when the events end.
+#[derive(Debug)]
+pub struct HtmlReportSink {
+    path: PathBuf,
+    /// The first and the last block number of the events.
+    blocks: Option<(u64, u64)>,
+
+    #[cfg(feature = "enable_tps_gas_record")]
+    tps_gas_displayer: TpsAndGasDisplayer,
+    /// The block number, tps and mgas/s of each report of the rates.
+    #[cfg(feature = "enable_tps_gas_record")]
+    rates: Vec<(u64, f64, f64)>,
+
+    /// The block number and the time (s) of each top-level stage since the previous
+    /// event, of each event.
+    #[cfg(feature = "enable_execution_duration_record")]
+    stages: Vec<(u64, Vec<(String, f64)>)>,
+
+    /// The latest cumulative records.
+    #[cfg(feature = "enable_opcode_metrics")]
+    opcode: Option<OpcodeRecord>,
+    #[cfg(feature = "enable_cache_record")]
+    cache: Option<CacheDbRecord>,
+}
+
+impl HtmlReportSink {
+    /// Creates a [HtmlReportSink] writing the report to path when the events end.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        HtmlReportSink {
+            path: path.into(),
+            blocks: None,
+
+            #[cfg(feature = "enable_tps_gas_record")]
+            tps_gas_displayer: TpsAndGasDisplayer::default(),
+            #[cfg(feature = "enable_tps_gas_record")]
+            rates: Vec::new(),
+
+            #[cfg(feature = "enable_execution_duration_record")]
+            stages: Vec::new(),
+
+            #[cfg(feature = "enable_opcode_metrics")]
+            opcode: None,
+            #[cfg(feature = "enable_cache_record")]
+            cache: None,
+        }
+    }
+
+    /// Collect event, replay_timestamp_ms is the time (ms) it was dumped if it is
+    /// replayed.
+    #[cfg_attr(not(feature = "enable_tps_gas_record"), allow(unused_variables))]
+    fn collect_event(&mut self, replay_timestamp_ms: Option<u64>, event: &MetricEvent) {
+        let block_number = event.block_number();
+        self.blocks = Some(match self.blocks {
+            Some((first, last)) => (first.min(block_number), last.max(block_number)),
+            None => (block_number, block_number),
+        });
+
+        match event {
+            #[cfg(feature = "enable_tps_gas_record")]
+            MetricEvent::BlockTpsAndGas {
+                block_number,
+                record,
+            } => {
+                let report =
+                    self.tps_gas_displayer
+                        .update(*block_number, record, replay_timestamp_ms);
+                if let (Some(block_number), Some((tps, mgas_ps))) =
+                    (report, self.tps_gas_displayer.interval_rates())
+                {
+                    self.rates.push((block_number, tps, mgas_ps));
+                }
+            }
+            #[cfg(feature = "enable_execution_duration_record")]
+            MetricEvent::ExecutionStageTime {
+                block_number,
+                delta,
+                ..
+            } => {
+                let stages = delta
+                    .items()
+                    .into_iter()
+                    .filter_map(|(label, cycles)| {
+                        let (level, name) = split_label(&label);
+                        (level == 0 && name != "total")
+                            .then(|| (name.to_string(), cycles_as_secs(cycles)))
+                    })
+                    .collect();
+                self.stages.push((*block_number, stages));
+            }
+            #[cfg(feature = "enable_opcode_metrics")]
+            MetricEvent::OpcodeInfo { record, .. } => self.opcode = Some((**record).clone()),
+            #[cfg(feature = "enable_cache_record")]
+            MetricEvent::CacheDbInfo { record, .. } => self.cache = Some(record.clone()),
+            _ => {}
+        }
+    }
+
+    #[cfg(feature = "enable_tps_gas_record")]
+    fn render_rates(&self, html: &mut String) {
+        let series = |name: &str, value: fn(&(u64, f64, f64)) -> f64| Series {
+            name: name.to_string(),
+            points: self
+                .rates
+                .iter()
+                .map(|rate| (rate.0 as f64, value(rate)))
+                .collect(),
+        };
+        html.push_str("<h2>Throughput</h2>");
+        html.push_str(&line_chart(
+            &[series("TPS", |rate| rate.1)],
+            "Block number",
+            "TPS",
+            false,
+        ));
+        html.push_str(&line_chart(
+            &[series("MGas/s", |rate| rate.2)],
+            "Block number",
+            "MGas/s",
+            false,
+        ));
+    }
+
+    #[cfg(feature = "enable_execution_duration_record")]
+    fn render_stages(&self, html: &mut String) {
+        html.push_str("<h2>Breakdown of ExecutionStage</h2>");
+        let names: Vec<String> = self
+            .stages
+            .first()
+            .map(|(_, stages)| stages.iter().map(|(name, _)| name.clone()).collect())
+            .unwrap_or_default();
+        let bars: Vec<(String, Vec<f64>)> = self
+            .stages
+            .iter()
+            .map(|(block_number, stages)| {
+                let values = stages.iter().map(|(_, time)| *time).collect();
+                (block_number.to_string(), values)
+            })
+            .collect();
+        html.push_str(&stacked_bar_chart(
+            &names,
+            &bars,
+            "Up to block number",
+            "Time (s)",
+        ));
+    }
+
+    #[cfg(feature = "enable_opcode_metrics")]
+    fn render_opcodes(&self, html: &mut String) {
+        let Some(record) = &self.opcode else {
+            return;
+        };
+        let stats = OpcodeStats::from(record);
+
+        html.push_str("<h2>Opcode time by category</h2>");
+        let slices = pie_slices(
+            stats
+                .merge_records
+                .iter()
+                .map(|(category, merged)| (*category, cycles_as_secs(merged.time))),
+            cycles_as_secs(stats.overall.time),
+        );
+        html.push_str(&pie_chart(&slices));
+
+        html.push_str("<h2>SLOAD latency</h2>");
+        let mut series = vec![Series {
+            name: "all".to_string(),
+            points: cdf(record
+                .sload_percentile
+                .buckets()
+                .map(|(upper_bound, count)| (upper_bound as f64, count))),
+        }];
+        if let Some(index) = state_access_index(SLOAD) {
+            let access = &record.state_access_record[index];
+            for (name, stats) in [("cold", &access.cold), ("warm", &access.warm)] {
+                series.push(Series {
+                    name: name.to_string(),
+                    points: cdf(stats
+                        .percentile
+                        .buckets()
+                        .map(|(upper_bound, count)| (upper_bound as f64, count))),
+                });
+            }
+        }
+        html.push_str(&line_chart(
+            &series,
+            "Latency (ns)",
+            "Cumulative share",
+            true,
+        ));
+
+        html.push_str("<h2>Opcodes</h2>");
+        let header = [
+            "Opcode",
+            "Category",
+            "Count",
+            "Time (s)",
+            "Time (%)",
+            "Cost (ns)",
+            "Total Mgas",
+            "Gas (%)",
+        ];
+        let mut rows = Vec::new();
+        for i in 0..OPCODE_NUMBER {
+            let (Some(name), Some(stat)) = (OpCode::new(i as u8), stats.opcode[i].as_ref()) else {
+                continue;
+            };
+            if stat.count == 0 {
+                continue;
+            }
+            rows.push(vec![
+                (name.as_str().to_string(), None),
+                (stat.cat.unwrap_or("").to_string(), None),
+                (stat.count.to_string(), Some(stat.count as f64)),
+                number(cycles_as_secs(stat.time), 3),
+                number(stat.time_pct * 100.0, 3),
+                number(stat.avg_cost, 1),
+                number(stat.mgas, 2),
+                number(stat.mgas_pct * 100.0, 2),
+            ]);
+        }
+        html.push_str(&sortable_table(&header, &rows));
+    }
+
+    #[cfg(feature = "enable_cache_record")]
+    fn render_cache(&self, html: &mut String) {
+        let Some(record) = &self.cache else {
+            return;
+        };
+
+        html.push_str("<h2>Cache miss penalty latency</h2>");
+        let penalty = record.penalty_stats();
+        let histograms = std::iter::once(("all", &penalty.percentile)).chain(
+            Function::ALL.iter().map(|function| {
+                (
+                    function.name(),
+                    &penalty.function_percentile[*function as usize],
+                )
+            }),
+        );
+        let series: Vec<Series> = histograms
+            .map(|(name, histogram)| Series {
+                name: name.to_string(),
+                points: cdf(histogram
+                    .buckets()
+                    .map(|(upper_bound, count)| (upper_bound as f64, count))),
+            })
+            .collect();
+        html.push_str(&line_chart(
+            &series,
+            "Latency (ns)",
+            "Cumulative share",
+            true,
+        ));
+
+        html.push_str("<h2>State functions</h2>");
+        let stats = CacheStats::from(record);
+        let header = [
+            "State function",
+            "Hits",
+            "Misses",
+            "Miss ratio (%)",
+            "Penalty time (s)",
+            "Avg penalty (us)",
+            "Avg hit (us)",
+        ];
+        let functions = Function::ALL
+            .iter()
+            .map(|function| (function.name(), &stats.functions[*function as usize]))
+            .chain([("total", &stats.total)]);
+        let rows: Vec<_> = functions
+            .map(|(name, stat)| {
+                vec![
+                    (name.to_string(), None),
+                    (stat.hits.to_string(), Some(stat.hits as f64)),
+                    (stat.misses.to_string(), Some(stat.misses as f64)),
+                    number(stat.miss_ratio * 100.0, 3),
+                    number(stat.penalty, 3),
+                    number(stat.avg_penalty, 3),
+                    number(stat.avg_hit, 3),
+                ]
+            })
+            .collect();
+        html.push_str(&sortable_table(&header, &rows));
+    }
+
+    /// Returns the report of the events so far as an html document.
+    pub fn render(&self) -> String {
+        let mut html = format!(
+            "<!DOCTYPE html><html><head><meta charset=\"utf-8\">\
+            <title>Performance report</title><style>{}</style></head><body>\
+            <h1>Performance report</h1>",
+            STYLE
+        );
+        match self.blocks {
+            Some((first, last)) => {
+                let _ = write!(html, "<p>Blocks {} to {}.</p>", first, last);
+            }
+            None => html.push_str(NO_DATA),
+        }
+
+        #[cfg(feature = "enable_tps_gas_record")]
+        self.render_rates(&mut html);
+        #[cfg(feature = "enable_execution_duration_record")]
+        self.render_stages(&mut html);
+        #[cfg(feature = "enable_opcode_metrics")]
+        self.render_opcodes(&mut html);
+        #[cfg(feature = "enable_cache_record")]
+        self.render_cache(&mut html);
+
+        let _ = write!(html, "<script>{}</script></body></html>", SORT_SCRIPT);
+        html
+    }
+}
+
+#[cfg(feature = "enable_tps_gas_record")]
+impl_tps_report_interval!(HtmlReportSink);
+
+impl MetricSink for HtmlReportSink {
+    fn on_event(&mut self, event: &MetricEvent) {
+        self.collect_event(None, event);
+    }
+
+    fn on_replayed_event(&mut self, timestamp_ms: u64, event: &MetricEvent) {
+        self.collect_event(Some(timestamp_ms), event);
+    }
+
+    fn shutdown(&mut self) {
+        if let Err(err) = fs::write(&self.path, self.render()) {
+            eprintln!("Failed to write html report: {:?}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn test_plot_maps_ranges() {
+        let (left, right) = (MARGINS.0, CHART_WIDTH - MARGINS.1);
+        let (top, bottom) = (MARGINS.2, CHART_HEIGHT - MARGINS.3);
+        let plot = Plot::new((10.0, 1000.0), (0.0, 4.0), true);
+        assert_close(plot.x(10.0), left);
+        // 100 is halfway between 10 and 1000 in log scale.
+        assert_close(plot.x(100.0), (left + right) / 2.0);
+        assert_close(plot.x(1000.0), right);
+        assert_close(plot.y(0.0), bottom);
+        assert_close(plot.y(1.0), bottom - (bottom - top) / 4.0);
+        assert_close(plot.y(4.0), top);
+
+        let plot = Plot::new((10.0, 1000.0), (0.0, 4.0), false);
+        assert_close(plot.x(505.0), (left + right) / 2.0);
+
+        // An empty range is widened rather than divided by.
+        let plot = Plot::new((100.0, 100.0), (2.0, 2.0), true);
+        assert_close(plot.x(100.0), left);
+        assert_close(plot.y(2.0), bottom);
+    }
+
+    #[test]
+    #[cfg(any(
+        feature = "enable_opcode_metrics",
+        feature = "enable_cache_record",
+        feature = "enable_tps_gas_record",
+    ))]
+    fn test_log_ticks() {
+        let ticks = log_ticks(5.0, 2000.0);
+        let values: Vec<f64> = ticks.iter().map(|tick| tick.0).collect();
+        assert_eq!(values, [10.0, 100.0, 1000.0]);
+        assert_eq!(ticks[1].1, "100");
+
+        // The bounds are the ticks if there is no power of 10 in between.
+        let ticks = log_ticks(2.0, 5.0);
+        assert_eq!(ticks, [(2.0, "2.0".to_string()), (5.0, "5.0".to_string())]);
+    }
+
+    #[test]
+    #[cfg(any(feature = "enable_opcode_metrics", feature = "enable_cache_record"))]
+    fn test_cdf() {
+        let buckets = [
+            (0.5, 1),
+            (10.0, 1),
+            (100.0, 0),
+            (1000.0, 1),
+            (f64::INFINITY, 1),
+        ];
+        // The empty bucket has no point, the infinite one is counted in the total only,
+        // and the x of the bucket below 1 is clamped for the log scale.
+        assert_eq!(
+            cdf(buckets.into_iter()),
+            [(1.0, 0.25), (10.0, 0.5), (1000.0, 0.75)]
+        );
+        assert!(cdf([(10.0, 0)].into_iter()).is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "enable_opcode_metrics")]
+    fn test_small_slices_are_merged_into_other() {
+        let values = [("stack", 30.0), ("", 5.0), ("arith", 64.5), ("memory", 0.5)];
+        let slices = pie_slices(values.into_iter(), 100.0);
+        assert_eq!(
+            slices,
+            [
+                ("arith".to_string(), 64.5),
+                ("stack".to_string(), 30.0),
+                ("other".to_string(), 5.5),
+            ]
+        );
+        assert!(pie_chart(&slices).contains("other (5.5%)"));
+
+        let slices = pie_slices([("arith", 60.0), ("stack", 40.0)].into_iter(), 100.0);
+        assert!(slices.iter().all(|(name, _)| name != "other"));
+    }
+}
@@ -1,74 +1,73 @@
-//! [DashboardListener] is used to display various metrics.
+//! [DashboardListener] is used to fan the metric events out to the sinks, e.g. to display
+//! various metrics.
 use std::{
+    fmt,
     future::Future,
     pin::Pin,
-    task::{ready, Context, Poll},
+    task::{Context, Poll},
 };
 
-#[cfg(any(
-    feature = "enable_opcode_metrics",
-    feature = "enable_cache_record",
-    feature = "enable_execution_duration_record",
-))]
-use super::commons::*;
-
+#[cfg(feature = "enable_tui")]
+use super::tui::TuiSink;
+use super::{sink::MetricSink, terminal::TerminalSink};
 use crate::metrics::metric::MetricEvent;
 use tokio::sync::mpsc::UnboundedReceiver;
 
-#[cfg(feature = "enable_tps_gas_record")]
-use super::tps_gas::TpsAndGasDisplayer;
-
-#[derive(Debug)]
 pub struct DashboardListener {
     events_rx: UnboundedReceiver<MetricEvent>,
 
-    #[cfg(feature = "enable_tps_gas_record")]
-    tps_gas_displayer: TpsAndGasDisplayer,
+    /// The sinks which every event is passed to, in order.
+    sinks: Vec<Box<dyn MetricSink>>,
+}
+
+impl fmt::Debug for DashboardListener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DashboardListener")
+            .field("events_rx", &self.events_rx)
+            .field("sinks", &self.sinks.len())
+            .finish()
+    }
 }
 
 impl DashboardListener {
-    /// Creates a new [DashboardListener] with the provided receiver of [MetricEvent].
+    /// Creates a new [DashboardListener] with the provided receiver of [MetricEvent],
+    /// which prints the metrics with a default [TerminalSink].
     pub fn new(events_rx: UnboundedReceiver<MetricEvent>) -> Self {
-        Self {
-            events_rx,
+        Self::with_sinks(events_rx, vec![Box::new(TerminalSink::default())])
+    }
 
-            #[cfg(feature = "enable_tps_gas_record")]
-            tps_gas_displayer: TpsAndGasDisplayer::default(),
+    /// Creates a new [DashboardListener] with the provided receiver of [MetricEvent],
+    /// which shows the metrics in an interactive terminal UI instead of printing them.
+    /// The metrics are printed if the terminal UI can not be started.
+    #[cfg(feature = "enable_tui")]
+    pub fn with_tui(events_rx: UnboundedReceiver<MetricEvent>) -> Self {
+        match TuiSink::start() {
+            Ok(tui) => Self::with_sinks(events_rx, vec![Box::new(tui)]),
+            Err(err) => {
+                eprintln!("Failed to start the terminal UI: {:?}", err);
+                Self::new(events_rx)
+            }
         }
     }
 
+    /// Creates a new [DashboardListener] with the provided receiver of [MetricEvent],
+    /// which passes every event to sinks.
+    pub fn with_sinks(
+        events_rx: UnboundedReceiver<MetricEvent>,
+        sinks: Vec<Box<dyn MetricSink>>,
+    ) -> Self {
+        Self { events_rx, sinks }
+    }
+
+    /// Pass every event to sink as well, after the existing sinks.
+    pub fn with_sink(mut self, sink: impl MetricSink + 'static) -> Self {
+        self.sinks.push(Box::new(sink));
+        self
+    }
+
     fn handle_event(&mut self, event: MetricEvent) {
-        match event {
-            #[cfg(feature = "enable_execution_duration_record")]
-            MetricEvent::ExecutionStageTime {
-                block_number,
-                record,
-            } => {
-                record.print(block_number);
-            }
-            #[cfg(feature = "enable_tps_gas_record")]
-            MetricEvent::BlockTpsAndGas {
-                block_number,
-                record,
-            } => {
-                self.tps_gas_displayer.print(block_number, record);
-            }
-            #[cfg(feature = "enable_opcode_metrics")]
-            MetricEvent::OpcodeInfo {
-                block_number,
-                record,
-            } => {
-                record.print(block_number);
-            }
-            #[cfg(feature = "enable_cache_record")]
-            MetricEvent::CacheDbInfo {
-                block_number,
-                size,
-                record,
-            } => {
-                super::cache::print_state_size(block_number, size);
-                record.print(block_number);
-            }
+        for sink in self.sinks.iter_mut() {
+            sink.on_event(&event);
         }
     }
 }
@@ -81,12 +80,18 @@ impl Future for DashboardListener {
 
         // Loop until we drain the `events_rx` channel
         loop {
-            let Some(event) = ready!(this.events_rx.poll_recv(cx)) else {
-                // Channel has closed
-                return Poll::Ready(());
-            };
-
-            this.handle_event(event);
+            match this.events_rx.poll_recv(cx) {
+                Poll::Ready(Some(event)) => this.handle_event(event),
+                Poll::Ready(None) => {
+                    // Channel has closed
+                    this.sinks.iter_mut().for_each(|sink| sink.shutdown());
+                    return Poll::Ready(());
+                }
+                Poll::Pending => {
+                    this.sinks.iter_mut().for_each(|sink| sink.flush());
+                    return Poll::Pending;
+                }
+            }
         }
     }
 }
@@ -1,11 +1,39 @@
 mod commons;
+#[cfg(any(
+    feature = "enable_opcode_metrics",
+    feature = "enable_cache_record",
+    feature = "enable_execution_duration_record",
+))]
+mod compare;
+#[cfg(any(
+    feature = "enable_opcode_metrics",
+    feature = "enable_cache_record",
+    feature = "enable_execution_duration_record",
+))]
+mod csv;
+mod dump;
+#[cfg(any(
+    feature = "enable_opcode_metrics",
+    feature = "enable_cache_record",
+    feature = "enable_execution_duration_record",
+    feature = "enable_tps_gas_record",
+))]
+mod html;
 mod listener;
+#[cfg(feature = "enable_prometheus")]
+mod prometheus;
+mod sink;
+mod terminal;
+#[cfg(feature = "enable_tui")]
+mod tui;
 
 #[cfg(feature = "enable_execution_duration_record")]
 mod duration;
 
 #[cfg(feature = "enable_opcode_metrics")]
 mod opcode;
+#[cfg(feature = "enable_opcode_metrics")]
+mod repricing;
 
 #[cfg(feature = "enable_cache_record")]
 mod cache;
@@ -13,4 +41,36 @@ mod cache;
 #[cfg(feature = "enable_tps_gas_record")]
 mod tps_gas;
 
+#[cfg(any(
+    feature = "enable_opcode_metrics",
+    feature = "enable_cache_record",
+    feature = "enable_execution_duration_record",
+))]
+pub use compare::{
+    ChangeStatus, CompareOutcome, CompareThresholds, ComparisonReport, MetricChange, RunRecords,
+};
+#[cfg(any(
+    feature = "enable_opcode_metrics",
+    feature = "enable_cache_record",
+    feature = "enable_execution_duration_record",
+))]
+pub use csv::CsvSink;
+pub use dump::{read_dump, DumpFormat, DumpReader, DumpedEvent, JsonlSink, MessagePackSink};
+#[cfg(any(
+    feature = "enable_opcode_metrics",
+    feature = "enable_cache_record",
+    feature = "enable_execution_duration_record",
+    feature = "enable_tps_gas_record",
+))]
+pub use html::HtmlReportSink;
 pub use listener::DashboardListener;
+#[cfg(feature = "enable_prometheus")]
+pub use prometheus::PrometheusSink;
+#[cfg(feature = "enable_opcode_metrics")]
+pub use repricing::{CategoryRepricing, OpcodeRepricing, RepricingReport, RepricingReportSink};
+pub use sink::MetricSink;
+pub use terminal::{DisplayMode, TerminalSink};
+#[cfg(feature = "enable_tps_gas_record")]
+pub use tps_gas::TpsReportInterval;
+#[cfg(feature = "enable_tui")]
+pub use tui::TuiSink;
@@ -1,12 +1,25 @@
 //! This module is used to support the display of opcode statistics metrics.
-use super::commons::*;
+#[cfg(feature = "enable_prometheus")]
+use super::prometheus::{PrometheusMetrics, PrometheusText};
+use super::{
+    commons::*,
+    csv::{CsvTable, CsvTables},
+    repricing::RepricingReport,
+};
+use crate::metrics::TxOpcodeRecord;
 use revm::revm_opcode::*;
-use revm_utils::{metrics::types::OpcodeRecord, time_utils::convert_cycles_to_ns_f64};
+use revm_utils::{
+    metrics::{
+        health::add,
+        types::{OpcodeRecord, StreamingStats, STATE_ACCESS_OPCODES},
+    },
+    time_utils::convert_cycles_to_ns_f64,
+};
 use std::collections::BTreeMap;
 
-const MGAS_TO_GAS: u64 = 1_000_000u64;
+pub(super) const MGAS_TO_GAS: u64 = 1_000_000u64;
 const COL_WIDTH: usize = 15;
-const OPCODE_NUMBER: usize = 256;
+pub(super) const OPCODE_NUMBER: usize = 256;
 
 #[derive(Debug, Clone, Copy, Default)]
 struct OpcodeInfo {
@@ -1321,30 +1334,32 @@ const MERGE_MAP: [Option<(u8, OpcodeInfo)>; OPCODE_NUMBER] = [
 ];
 
 #[derive(Default, Debug)]
-struct OpcodeMergeRecord {
-    count: u64,
-    count_pct: f64,
-    time: u64,
-    time_pct: f64,
-    avg_cost: f64,
+pub(super) struct OpcodeMergeRecord {
+    pub(super) count: u64,
+    pub(super) count_pct: f64,
+    pub(super) time: u64,
+    pub(super) time_pct: f64,
+    pub(super) avg_cost: f64,
 }
 
 #[derive(Default, Debug)]
-struct OpcodeStat {
-    count: u64,
-    count_pct: f64,
-    time: u64,
-    time_pct: f64,
-    avg_cost: f64,
-    mgas: f64,
-    mgas_pct: f64,
-    static_gas: Option<u64>,
-    dyn_gas: Option<f64>,
-    cat: Option<&'static str>,
+pub(super) struct OpcodeStat {
+    pub(super) count: u64,
+    pub(super) count_pct: f64,
+    pub(super) time: u64,
+    pub(super) time_pct: f64,
+    pub(super) avg_cost: f64,
+    pub(super) mgas: f64,
+    pub(super) mgas_pct: f64,
+    pub(super) static_gas: Option<u64>,
+    pub(super) dyn_gas: Option<f64>,
+    pub(super) cat: Option<&'static str>,
+    pub(super) time_stats: StreamingStats,
 }
 
 impl OpcodeStat {
-    fn print(&self, opcode: &str) {
+    /// Returns the (static gas, dynamic gas) columns.
+    fn gas_columns(&self) -> (String, String) {
         let static_gas = match self.static_gas {
             None => "NAN".to_string(),
             Some(s) => s.to_string(),
@@ -1360,16 +1375,43 @@ impl OpcodeStat {
                 }
             }
         };
+        (static_gas, dyn_gas)
+    }
+
+    /// Returns the (std dev, min, max) of the time in ns.
+    fn time_stats_ns(&self) -> (f64, f64, f64) {
+        let std_dev = self
+            .time_stats
+            .std_dev()
+            .map_or(f64::NAN, |v| v * convert_cycles_to_ns_f64(1));
+        let min = self
+            .time_stats
+            .min()
+            .map_or(f64::NAN, convert_cycles_to_ns_f64);
+        let max = self
+            .time_stats
+            .max()
+            .map_or(f64::NAN, convert_cycles_to_ns_f64);
+        (std_dev, min, max)
+    }
+
+    fn print(&self, opcode: &str) {
+        let (static_gas, dyn_gas) = self.gas_columns();
+        let (std_dev, min, max) = self.time_stats_ns();
 
         println!(
             "{: <COL_WIDTH$}{:>COL_WIDTH$}{:>COL_WIDTH$.3}{:>COL_WIDTH$.2}{:>COL_WIDTH$.3} \
-            {:>COL_WIDTH$.1}{:>COL_WIDTH$.2}{:>COL_WIDTH$.2}{:>COL_WIDTH$}{:>COL_WIDTH$}{:>COL_WIDTH$}",
+            {:>COL_WIDTH$.1}{:>COL_WIDTH$.1}{:>COL_WIDTH$.1}{:>COL_WIDTH$.1}{:>COL_WIDTH$.2}{:>COL_WIDTH$.2}\
+            {:>COL_WIDTH$}{:>COL_WIDTH$}{:>COL_WIDTH$}",
             opcode,
             self.count,
             self.count_pct * 100.0,
             cycles_as_secs(self.time),
             self.time_pct * 100.0,
             self.avg_cost,
+            std_dev,
+            min,
+            max,
             self.mgas,
             self.mgas_pct * 100.0,
             static_gas,
@@ -1377,6 +1419,27 @@ impl OpcodeStat {
             self.cat.unwrap_or("NAN"),
         );
     }
+
+    fn csv_row(&self, opcode: &str) -> Vec<String> {
+        let (static_gas, dyn_gas) = self.gas_columns();
+        let (std_dev, min, max) = self.time_stats_ns();
+        vec![
+            opcode.to_string(),
+            self.count.to_string(),
+            format!("{:.3}", self.count_pct * 100.0),
+            format!("{:.2}", cycles_as_secs(self.time)),
+            format!("{:.3}", self.time_pct * 100.0),
+            format!("{:.1}", self.avg_cost),
+            format!("{:.1}", std_dev),
+            format!("{:.1}", min),
+            format!("{:.1}", max),
+            format!("{:.2}", self.mgas),
+            format!("{:.2}", self.mgas_pct * 100.0),
+            static_gas,
+            dyn_gas,
+            self.cat.unwrap_or("NAN").to_string(),
+        ]
+    }
 }
 
 // Return (total_gas, static_gas, dyn_gas).
@@ -1405,10 +1468,10 @@ fn category_name(opcode: u8) -> Option<&'static str> {
 }
 
 #[derive(Debug)]
-struct OpcodeStats {
-    overall: OpcodeStat,
-    opcode: [Option<OpcodeStat>; OPCODE_NUMBER],
-    merge_records: BTreeMap<&'static str, OpcodeMergeRecord>,
+pub(super) struct OpcodeStats {
+    pub(super) overall: OpcodeStat,
+    pub(super) opcode: [Option<OpcodeStat>; OPCODE_NUMBER],
+    pub(super) merge_records: BTreeMap<&'static str, OpcodeMergeRecord>,
 }
 
 const ARRAY_REPEAT_VALUE: std::option::Option<OpcodeStat> = None;
@@ -1429,10 +1492,12 @@ impl From<&OpcodeRecord> for OpcodeStats {
         for (i, v) in record.opcode_record.iter().enumerate() {
             // opcode
             let op = i as u8;
+            // The time is scaled up if only some transactions are sampled.
+            let time = record.estimated_opcode_time(op);
             let mut opcode_stat = OpcodeStat::default();
             opcode_stat.count = v.0;
-            opcode_stat.time = v.1;
-            opcode_stat.avg_cost = convert_cycles_to_ns_f64(v.1) / v.0 as f64;
+            opcode_stat.time = time;
+            opcode_stat.avg_cost = convert_cycles_to_ns_f64(time) / v.0 as f64;
             let (op_total, op_static, op_dyn) = caculate_gas(op, v.0, v.2);
             opcode_stat.mgas = op_total / MGAS_TO_GAS as f64;
             opcode_stat.static_gas = Some(op_static);
@@ -1443,20 +1508,17 @@ impl From<&OpcodeRecord> for OpcodeStats {
                 None => "",
             };
             opcode_stat.cat = Some(cat);
+            opcode_stat.time_stats = record.opcode_time_stats[i];
             opcode_stats.opcode[i] = Some(opcode_stat);
 
             // overall
-            opcode_stats.overall.count = opcode_stats
-                .overall
-                .count
-                .checked_add(v.0)
-                .expect("overflow");
-            opcode_stats.overall.time = opcode_stats
-                .overall
-                .time
-                .checked_add(v.1)
-                .expect("overflow");
+            opcode_stats.overall.count = add(opcode_stats.overall.count, v.0);
+            opcode_stats.overall.time = add(opcode_stats.overall.time, time);
             opcode_stats.overall.mgas += opcode_stats.opcode[i].as_ref().expect("empty").mgas;
+            opcode_stats
+                .overall
+                .time_stats
+                .update(&record.opcode_time_stats[i]);
 
             // merge
             opcode_stats
@@ -1464,12 +1526,12 @@ impl From<&OpcodeRecord> for OpcodeStats {
                 .entry(cat)
                 .and_modify(|r| {
                     r.count += v.0;
-                    r.time += v.1;
+                    r.time += time;
                 })
                 .or_insert(OpcodeMergeRecord {
                     count: v.0,
                     count_pct: 0.0,
-                    time: v.1,
+                    time,
                     time_pct: 0.0,
                     avg_cost: 0.0,
                 });
@@ -1506,16 +1568,20 @@ impl From<&OpcodeRecord> for OpcodeStats {
 
 impl OpcodeStats {
     fn print_opcode_title(&self) {
-        println!("==============================================================================Opcode cost table=======================================================================");
+        println!("============================================================================================Opcode cost table============================================================================================");
         println!(
             "{: <COL_WIDTH$}{:>COL_WIDTH$}{:>COL_WIDTH$}{:>COL_WIDTH$}{:>COL_WIDTH$} \
-            {:>COL_WIDTH$}{:>COL_WIDTH$}{:>COL_WIDTH$}{:>COL_WIDTH$}{:>COL_WIDTH$}{:>COL_WIDTH$}",
+            {:>COL_WIDTH$}{:>COL_WIDTH$}{:>COL_WIDTH$}{:>COL_WIDTH$}{:>COL_WIDTH$}{:>COL_WIDTH$}\
+            {:>COL_WIDTH$}{:>COL_WIDTH$}{:>COL_WIDTH$}",
             "Opcode",
             "Count",
             "Count (%)",
             "Time (s)",
             "Time (%)",
             "Cost (ns)",
+            "Std dev (ns)",
+            "Min (ns)",
+            "Max (ns)",
             "Total Mgas",
             "Gas (%)",
             "Static gas",
@@ -1566,9 +1632,116 @@ impl OpcodeStats {
     }
 }
 
+impl CsvTables for OpcodeStats {
+    fn csv_tables(&self) -> Vec<CsvTable> {
+        let mut opcodes = CsvTable::new(
+            "opcodes",
+            "opcode,count,count_pct,time_s,time_pct,cost_ns,std_dev_ns,min_ns,max_ns,\
+            total_mgas,gas_pct,static_gas,dyn_gas,category",
+        );
+        opcodes.push_row(&self.overall.csv_row("overall"));
+        for (i, stat) in self.opcode.iter().enumerate() {
+            let (Some(name), Some(stat)) = (OpCode::new(i as u8), stat) else {
+                continue;
+            };
+            opcodes.push_row(&stat.csv_row(name.as_str()));
+        }
+
+        let mut categories = CsvTable::new(
+            "opcode_categories",
+            "category,count,count_pct,time_s,time_pct,cost_ns",
+        );
+        for (k, v) in self.merge_records.iter() {
+            if k.is_empty() {
+                continue;
+            }
+            categories.push_row(&[
+                k.to_string(),
+                v.count.to_string(),
+                format!("{:.2}", v.count_pct * 100.0),
+                format!("{:.1}", cycles_as_secs(v.time)),
+                format!("{:.3}", v.time_pct * 100.0),
+                format!("{:.3}", v.avg_cost),
+            ]);
+        }
+
+        vec![opcodes, categories]
+    }
+}
+
+impl CsvTables for OpcodeRecord {
+    fn csv_tables(&self) -> Vec<CsvTable> {
+        OpcodeStats::from(self).csv_tables()
+    }
+}
+
+#[cfg(feature = "enable_prometheus")]
+impl PrometheusMetrics for OpcodeRecord {
+    fn write_metrics(&self, text: &mut PrometheusText) {
+        let stats = OpcodeStats::from(self);
+        let opcodes: Vec<(&str, &OpcodeStat)> = stats
+            .opcode
+            .iter()
+            .enumerate()
+            .filter_map(|(i, stat)| Some((OpCode::new(i as u8)?.as_str(), stat.as_ref()?)))
+            .filter(|(_, stat)| stat.count > 0)
+            .collect();
+        let counters = [
+            (
+                "revm_opcode_executions_total",
+                "The number of executions of each opcode.",
+                opcodes.iter().map(|(_, stat)| stat.count as f64).collect::<Vec<_>>(),
+            ),
+            (
+                "revm_opcode_time_seconds_total",
+                "The execution time of each opcode, estimated if only some transactions are sampled.",
+                opcodes.iter().map(|(_, stat)| cycles_as_secs(stat.time)).collect(),
+            ),
+            (
+                "revm_opcode_gas_total",
+                "The gas consumed by each opcode.",
+                opcodes.iter().map(|(_, stat)| stat.mgas * MGAS_TO_GAS as f64).collect(),
+            ),
+        ];
+        for (name, help, values) in counters {
+            text.family(name, "counter", help);
+            for ((opcode, stat), value) in opcodes.iter().zip(values) {
+                let labels = [("opcode", *opcode), ("category", stat.cat.unwrap_or(""))];
+                text.sample(name, &labels, value);
+            }
+        }
+
+        let name = "revm_state_access_duration_seconds";
+        text.family(
+            name,
+            "histogram",
+            "The time distribution of cold and warm state accesses.",
+        );
+        for (index, opcode) in STATE_ACCESS_OPCODES.iter().enumerate() {
+            let opcode = OpCode::new(*opcode).map_or("NAN", |op| op.as_str());
+            let record = &self.state_access_record[index];
+            for (access, stats) in [("cold", &record.cold), ("warm", &record.warm)] {
+                text.log_histogram(
+                    name,
+                    &[("opcode", opcode), ("access", access)],
+                    &stats.percentile,
+                );
+            }
+        }
+
+        let name = "revm_sload_duration_seconds";
+        text.family(name, "histogram", "The time distribution of sload.");
+        text.log_histogram(name, &[], &self.sload_percentile);
+    }
+}
+
 trait ExtraPrint {
     fn print_addition_count(&self);
     fn print_sload_percentile(&self);
+    fn print_state_access(&self);
+    #[cfg(feature = "enable_data_throughput_record")]
+    fn print_data_throughput(&self);
+    fn print_sampling(&self);
 }
 
 impl ExtraPrint for OpcodeRecord {
@@ -1594,6 +1767,129 @@ impl ExtraPrint for OpcodeRecord {
         println!("=============sload time percentile=============");
         self.sload_percentile.print_content();
     }
+
+    #[cfg(feature = "enable_data_throughput_record")]
+    fn print_data_throughput(&self) {
+        println!();
+        println!(
+            "================================Data-moving opcodes================================"
+        );
+        println!(
+            "{:<COL_WIDTH$}{:>COL_WIDTH$}{:>COL_WIDTH$}{:>COL_WIDTH$}{:>COL_WIDTH$}{:>COL_WIDTH$}",
+            "Opcode", "Count", "Bytes", "Avg bytes", "Cost (ns)", "Bytes/ns",
+        );
+        for (opcode, bytes) in self.opcode_bytes.iter().enumerate() {
+            let Some(name) = OpCode::new(opcode as u8) else {
+                continue;
+            };
+            if *bytes == 0 {
+                continue;
+            }
+            let count = self.opcode_record[opcode].0;
+            let time_ns = convert_cycles_to_ns_f64(self.estimated_opcode_time(opcode as u8));
+            println!(
+                "{:<COL_WIDTH$}{:>COL_WIDTH$}{:>COL_WIDTH$}{:>COL_WIDTH$.1}{:>COL_WIDTH$.1}{:>COL_WIDTH$.3}",
+                name.as_str(),
+                count,
+                bytes,
+                *bytes as f64 / count as f64,
+                time_ns / count as f64,
+                *bytes as f64 / time_ns,
+            );
+        }
+
+        println!();
+        println!("memory words grown: {}", self.memory.words_grown);
+        println!("peak memory (bytes): {}", self.memory.peak_memory);
+        println!(
+            "avg peak memory per tx (bytes): {:.1}",
+            self.memory.avg_peak_memory()
+        );
+        println!();
+    }
+
+    fn print_sampling(&self) {
+        let sampling = &self.sampling;
+        if !sampling.is_partial() {
+            return;
+        }
+        let cycles_to_secs = |cycles: f64| convert_cycles_to_ns_f64(1) * cycles / 1e9;
+
+        println!();
+        println!("==========================================Opcode sampling==========================================");
+        println!(
+            "sampled txs: {} / {} ({:.2}%)",
+            sampling.sampled_txs,
+            sampling.txs,
+            sampling.sampled_txs as f64 / sampling.txs as f64 * 100.0
+        );
+        let (total, margin) = sampling.estimated_total_time();
+        println!(
+            "estimated opcode time (s): {:.3} ± {:.3} (95% CI)",
+            cycles_to_secs(total),
+            cycles_to_secs(margin)
+        );
+        // The opcodes of a transaction are not independent samples, so only the total has
+        // a confidence interval, which is estimated from the time of each sampled transaction.
+        println!(
+            "{:<COL_WIDTH$}{:>COL_WIDTH$}{:>COL_WIDTH$}{:>COL_WIDTH$}",
+            "Opcode", "Count", "Sampled", "Est. time (s)",
+        );
+        for (opcode, (count, _, _)) in self.opcode_record.iter().enumerate() {
+            let Some(name) = OpCode::new(opcode as u8) else {
+                continue;
+            };
+            if *count == 0 {
+                continue;
+            }
+            let time = self.estimated_opcode_time(opcode as u8);
+            println!(
+                "{:<COL_WIDTH$}{:>COL_WIDTH$}{:>COL_WIDTH$}{:>COL_WIDTH$.3}",
+                name.as_str(),
+                count,
+                self.opcode_time_stats[opcode].count,
+                cycles_to_secs(time as f64),
+            );
+        }
+        println!();
+    }
+
+    fn print_state_access(&self) {
+        println!();
+        println!("=====================================Cold/warm state access=====================================");
+        println!(
+            "{:<COL_WIDTH$}{:>COL_WIDTH$}{:>COL_WIDTH$}{:>COL_WIDTH$}{:>COL_WIDTH$}{:>COL_WIDTH$}{:>COL_WIDTH$}{:>COL_WIDTH$}{:>COL_WIDTH$}",
+            "Opcode", "Access", "Count", "Count (%)", "Time (s)", "Cost (ns)", "p50 (ns)", "p99 (ns)", "Avg gas",
+        );
+        // The ratios are 0 rather than NaN for the opcodes which are never executed.
+        let ratio = |value: f64, count: u64| {
+            if count == 0 {
+                0.0
+            } else {
+                value / count as f64
+            }
+        };
+        for (index, opcode) in STATE_ACCESS_OPCODES.iter().enumerate() {
+            let name = OpCode::new(*opcode).map_or("NAN", |op| op.as_str());
+            let record = &self.state_access_record[index];
+            let total = record.cold.count + record.warm.count;
+            for (access, stats) in [("cold", &record.cold), ("warm", &record.warm)] {
+                println!(
+                    "{:<COL_WIDTH$}{:>COL_WIDTH$}{:>COL_WIDTH$}{:>COL_WIDTH$.2}{:>COL_WIDTH$.3}{:>COL_WIDTH$.1}{:>COL_WIDTH$}{:>COL_WIDTH$}{:>COL_WIDTH$.1}",
+                    name,
+                    access,
+                    stats.count,
+                    ratio(stats.count as f64 * 100.0, total),
+                    cycles_as_secs(stats.time),
+                    ratio(convert_cycles_to_ns_f64(stats.time), stats.count),
+                    stats.percentile.quantile(0.5),
+                    stats.percentile.quantile(0.99),
+                    ratio(stats.gas as f64, stats.count),
+                );
+            }
+        }
+        println!();
+    }
 }
 
 impl Print for OpcodeRecord {
@@ -1601,7 +1897,29 @@ impl Print for OpcodeRecord {
         let opcode_stats: OpcodeStats = self.into();
         opcode_stats.print_opcodes();
         opcode_stats.print_category();
+        RepricingReport::from(&opcode_stats).print(_block_number);
+        self.print_sampling();
         self.print_addition_count();
+        #[cfg(feature = "enable_data_throughput_record")]
+        self.print_data_throughput();
+        self.print_state_access();
         self.print_sload_percentile();
     }
 }
+
+impl Print for TxOpcodeRecord {
+    fn print(&self, _block_number: u64) {
+        let tx_hash = match self.tx_hash {
+            Some(hash) => hash
+                .iter()
+                .fold("0x".to_string(), |s, b| s + &format!("{:02x}", b)),
+            None => "NAN".to_string(),
+        };
+        println!();
+        println!(
+            "block_number: {:?}, tx_index: {:?}, tx_hash: {}",
+            self.block_number, self.tx_index, tx_hash
+        );
+        self.record.print(self.block_number);
+    }
+}
@@ -0,0 +1,314 @@
+//! This module is used to expose the latest metrics in Prometheus text format from a
+//! small local HTTP server, so that a long sync can be watched in Grafana.
+use super::sink::MetricSink;
+#[cfg(feature = "enable_tps_gas_record")]
+use super::tps_gas::{impl_tps_report_interval, TpsAndGasDisplayer};
+use crate::metrics::metric::MetricEvent;
+#[cfg(any(feature = "enable_opcode_metrics", feature = "enable_cache_record"))]
+use revm_utils::metrics::types::LogHistogram;
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+#[cfg(any(feature = "enable_opcode_metrics", feature = "enable_cache_record"))]
+const NANOS_PER_SEC: f64 = 1_000_000_000.0;
+/// The exponents of the upper bounds of the exported histograms, which are powers of two
+/// from 16ns to about 1.07s.
+#[cfg(any(feature = "enable_opcode_metrics", feature = "enable_cache_record"))]
+const HISTOGRAM_BOUND_EXPONENTS: std::ops::RangeInclusive<u32> = 4..=30;
+/// A scrape which does not send its request within it is dropped.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Returns the upper bounds (ns) of the buckets of the exported histograms.
+#[cfg(any(feature = "enable_opcode_metrics", feature = "enable_cache_record"))]
+fn histogram_bounds_ns() -> impl Iterator<Item = f64> {
+    HISTOGRAM_BOUND_EXPONENTS.map(|exponent| (1u64 << exponent) as f64)
+}
+
+/// Metrics in Prometheus text format. The samples of a metric family should be written
+/// right after its description.
+#[derive(Debug, Default)]
+pub(super) struct PrometheusText {
+    content: String,
+}
+
+/// Format a value as Prometheus does, e.g. "+Inf" and "NaN".
+fn format_value(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "+Inf" } else { "-Inf" }.to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+impl PrometheusText {
+    /// Describe a metric family, kind is "counter", "gauge" or "histogram".
+    pub(super) fn family(&mut self, name: &str, kind: &str, help: &str) {
+        let _ = writeln!(self.content, "# HELP {} {}", name, help);
+        let _ = writeln!(self.content, "# TYPE {} {}", name, kind);
+    }
+
+    pub(super) fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: f64) {
+        self.content.push_str(name);
+        if !labels.is_empty() {
+            let labels: Vec<String> = labels
+                .iter()
+                .map(|(key, value)| format!("{}=\"{}\"", key, escape_label_value(value)))
+                .collect();
+            let _ = write!(self.content, "{{{}}}", labels.join(","));
+        }
+        let _ = writeln!(self.content, " {}", format_value(value));
+    }
+
+    /// Write a histogram from (upper bound in ns, count) of each bucket in ascending
+    /// order, the bounds are converted to seconds. The count of a bucket whose bound is
+    /// infinite is only included in the "+Inf" bucket.
+    #[cfg(any(feature = "enable_opcode_metrics", feature = "enable_cache_record"))]
+    fn histogram(
+        &mut self,
+        name: &str,
+        labels: &[(&str, &str)],
+        buckets: impl Iterator<Item = (f64, u64)>,
+        sum_ns: f64,
+    ) {
+        let bucket_name = format!("{}_bucket", name);
+        let mut cumulative = 0u64;
+        for (upper_bound_ns, count) in buckets {
+            cumulative = cumulative.saturating_add(count);
+            if upper_bound_ns.is_infinite() {
+                continue;
+            }
+            let le = format_value(upper_bound_ns / NANOS_PER_SEC);
+            let mut bucket_labels = labels.to_vec();
+            bucket_labels.push(("le", &le));
+            self.sample(&bucket_name, &bucket_labels, cumulative as f64);
+        }
+        let mut bucket_labels = labels.to_vec();
+        bucket_labels.push(("le", "+Inf"));
+        self.sample(&bucket_name, &bucket_labels, cumulative as f64);
+        self.sample(&format!("{}_sum", name), labels, sum_ns / NANOS_PER_SEC);
+        self.sample(&format!("{}_count", name), labels, cumulative as f64);
+    }
+
+    /// Write a LogHistogram of time (ns) as a histogram with the buckets of
+    /// histogram_bounds_ns, including the empty ones, so that every scrape has the same
+    /// series. The sub-buckets of the LogHistogram are counted by their highest value.
+    #[cfg(any(feature = "enable_opcode_metrics", feature = "enable_cache_record"))]
+    pub(super) fn log_histogram(
+        &mut self,
+        name: &str,
+        labels: &[(&str, &str)],
+        histogram: &LogHistogram,
+    ) {
+        let sum_ns = histogram.mean() * histogram.count() as f64;
+        let mut sub_buckets = histogram.buckets().peekable();
+        let mut buckets: Vec<(f64, u64)> = histogram_bounds_ns()
+            .map(|upper_bound| {
+                let mut count = 0u64;
+                while let Some((_, sub_count)) =
+                    sub_buckets.next_if(|(value, _)| *value as f64 <= upper_bound)
+                {
+                    count = count.saturating_add(sub_count);
+                }
+                (upper_bound, count)
+            })
+            .collect();
+        buckets.push((f64::INFINITY, sub_buckets.map(|(_, count)| count).sum()));
+        self.histogram(name, labels, buckets.into_iter(), sum_ns);
+    }
+}
+
+/// Implemented by the records which are exposed to Prometheus.
+pub(super) trait PrometheusMetrics {
+    fn write_metrics(&self, text: &mut PrometheusText);
+}
+
+/// Serves the latest cumulative metrics in Prometheus text format from a background
+/// thread. The metrics are kept in sections, e.g. one per kind of record, each of which
+/// is replaced when the record is updated.
+#[derive(Debug)]
+pub struct PrometheusSink {
+    sections: Arc<Mutex<BTreeMap<&'static str, String>>>,
+
+    #[cfg(feature = "enable_tps_gas_record")]
+    tps_gas_displayer: TpsAndGasDisplayer,
+}
+
+impl PrometheusSink {
+    /// Bind addr and serve the metrics at "http://{addr}/metrics" from a background
+    /// thread.
+    pub fn serve(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let sections = Arc::new(Mutex::new(BTreeMap::new()));
+        let exporter = PrometheusSink {
+            sections: sections.clone(),
+
+            #[cfg(feature = "enable_tps_gas_record")]
+            tps_gas_displayer: TpsAndGasDisplayer::default(),
+        };
+        thread::Builder::new()
+            .name("perf-metrics-prometheus".to_string())
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    if let Err(err) = stream.and_then(|stream| handle_scrape(stream, &sections)) {
+                        eprintln!("Failed to serve prometheus metrics: {:?}", err);
+                    }
+                }
+            })?;
+        Ok(exporter)
+    }
+
+    /// Replace the section with the metrics of record.
+    fn update<T: PrometheusMetrics>(&self, section: &'static str, record: &T) {
+        self.update_with(section, |text| record.write_metrics(text));
+    }
+
+    /// Replace the section with the metrics written by write.
+    fn update_with(&self, section: &'static str, write: impl FnOnce(&mut PrometheusText)) {
+        let mut text = PrometheusText::default();
+        write(&mut text);
+        self.sections
+            .lock()
+            .expect("Prometheus sections should not be poisoned!")
+            .insert(section, text.content);
+    }
+}
+
+impl PrometheusSink {
+    /// Export event, replay_timestamp_ms is the time (ms) it was dumped if it is
+    /// replayed.
+    #[cfg_attr(not(feature = "enable_tps_gas_record"), allow(unused_variables))]
+    fn export_event(&mut self, replay_timestamp_ms: Option<u64>, event: &MetricEvent) {
+        match event {
+            #[cfg(feature = "enable_execution_duration_record")]
+            MetricEvent::ExecutionStageTime { record, .. } => self.update("duration", record),
+            #[cfg(feature = "enable_tps_gas_record")]
+            MetricEvent::BlockTpsAndGas {
+                block_number,
+                record,
+            } => {
+                let report =
+                    self.tps_gas_displayer
+                        .update(*block_number, record, replay_timestamp_ms);
+                if report.is_some() {
+                    self.update("tps_gas", &self.tps_gas_displayer);
+                }
+            }
+            #[cfg(feature = "enable_opcode_metrics")]
+            MetricEvent::OpcodeInfo { record, .. } => self.update("opcode", &**record),
+            #[cfg(feature = "enable_cache_record")]
+            MetricEvent::CacheDbInfo { size, record, .. } => {
+                self.update_with("cache", |text| {
+                    record.write_metrics(text);
+                    super::cache::write_state_size(text, *size);
+                });
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(feature = "enable_tps_gas_record")]
+impl_tps_report_interval!(PrometheusSink);
+
+impl MetricSink for PrometheusSink {
+    fn on_event(&mut self, event: &MetricEvent) {
+        self.export_event(None, event);
+    }
+
+    fn on_replayed_event(&mut self, timestamp_ms: u64, event: &MetricEvent) {
+        self.export_event(Some(timestamp_ms), event);
+    }
+}
+
+fn handle_scrape(
+    mut stream: TcpStream,
+    sections: &Mutex<BTreeMap<&'static str, String>>,
+) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Drain the headers, the request has no body.
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && !header.trim_end().is_empty() {
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let response = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => {
+            let body: String = sections
+                .lock()
+                .expect("Prometheus sections should not be poisoned!")
+                .values()
+                .map(String::as_str)
+                .collect();
+            format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4; charset=utf-8\r\n\
+                Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+        }
+        _ => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+    };
+    stream.write_all(response.as_bytes())?;
+    stream.flush()
+}
+
+#[cfg(test)]
+#[cfg(any(feature = "enable_opcode_metrics", feature = "enable_cache_record"))]
+mod tests {
+    use super::*;
+
+    fn bucket_lines(histogram: &LogHistogram) -> Vec<String> {
+        let mut text = PrometheusText::default();
+        text.log_histogram("latency", &[], histogram);
+        text.content
+            .lines()
+            .filter(|line| line.starts_with("latency_bucket"))
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn test_log_histogram_has_fixed_buckets() {
+        let empty = bucket_lines(&LogHistogram::default());
+        let mut histogram = LogHistogram::default();
+        for time_ns in [20.0, 100.0, 5000.0, 3e9] {
+            histogram.record(time_ns);
+        }
+        let lines = bucket_lines(&histogram);
+
+        let series = |lines: &[String]| -> Vec<String> {
+            lines
+                .iter()
+                .map(|line| line.rsplit_once(' ').unwrap().0.to_string())
+                .collect()
+        };
+        assert_eq!(series(&lines), series(&empty));
+        assert_eq!(lines.len(), HISTOGRAM_BOUND_EXPONENTS.count() + 1);
+        assert!(lines.contains(&"latency_bucket{le=\"0.000000016\"} 0".to_string()));
+        assert!(lines.contains(&"latency_bucket{le=\"0.000000032\"} 1".to_string()));
+        assert!(lines.contains(&"latency_bucket{le=\"0.000000128\"} 2".to_string()));
+        assert!(lines.contains(&"latency_bucket{le=\"0.000008192\"} 3".to_string()));
+        assert!(lines.contains(&"latency_bucket{le=\"1.073741824\"} 3".to_string()));
+        assert!(lines.contains(&"latency_bucket{le=\"+Inf\"} 4".to_string()));
+    }
+}
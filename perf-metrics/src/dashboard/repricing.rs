@@ -0,0 +1,303 @@
+//! This module is used to support the gas repricing report, which compares the execution
+//! time per gas unit of every opcode against the average of all executed opcodes.
+use super::commons::*;
+use super::opcode::{OpcodeStats, MGAS_TO_GAS, OPCODE_NUMBER};
+use super::sink::MetricSink;
+use crate::metrics::metric::MetricEvent;
+use revm::revm_opcode::*;
+use revm_utils::{metrics::types::OpcodeRecord, time_utils::convert_cycles_to_ns_f64};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+const COL_WIDTH: usize = 15;
+/// Number of the most underpriced opcodes printed in the terminal.
+const TOP_N: usize = 10;
+
+/// Repricing information of an opcode.
+#[derive(Debug, Clone, Serialize)]
+pub struct OpcodeRepricing {
+    /// Opcode name.
+    pub opcode: &'static str,
+    /// Opcode category.
+    pub category: &'static str,
+    /// Execution count.
+    pub count: u64,
+    /// Average execution time (ns).
+    pub avg_cost_ns: f64,
+    /// Average gas charged per execution.
+    pub avg_gas: f64,
+    /// Execution time per gas unit (ns).
+    pub ns_per_gas: f64,
+    /// Relative deviation of ns_per_gas from the average of all opcodes, a positive
+    /// value means the opcode is underpriced.
+    pub deviation: f64,
+    /// The gas per execution which would make ns_per_gas equal to the average.
+    pub suggested_gas: f64,
+}
+
+/// Repricing information of an opcode category.
+#[derive(Debug, Clone, Serialize)]
+pub struct CategoryRepricing {
+    /// Category name.
+    pub category: &'static str,
+    /// Execution count.
+    pub count: u64,
+    /// Execution time per gas unit (ns).
+    pub ns_per_gas: f64,
+    /// Relative deviation of ns_per_gas from the average of all opcodes.
+    pub deviation: f64,
+}
+
+/// The gas repricing report, opcodes are ranked from the most underpriced one.
+/// Opcodes that were not executed or didn't charge any gas are not included.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RepricingReport {
+    /// Average execution time per gas unit (ns) of all opcodes.
+    pub avg_ns_per_gas: f64,
+    /// Repricing information of each opcode.
+    pub opcodes: Vec<OpcodeRepricing>,
+    /// Repricing information of each category.
+    pub categories: Vec<CategoryRepricing>,
+}
+
+impl From<&OpcodeStats> for RepricingReport {
+    fn from(stats: &OpcodeStats) -> Self {
+        let total_gas = stats.overall.mgas * MGAS_TO_GAS as f64;
+        if total_gas <= 0.0 {
+            return RepricingReport::default();
+        }
+        let avg_ns_per_gas = convert_cycles_to_ns_f64(stats.overall.time) / total_gas;
+
+        let mut opcodes = Vec::new();
+        // category -> (count, time, gas)
+        let mut categories: BTreeMap<&'static str, (u64, u64, f64)> = BTreeMap::new();
+        for i in 0..OPCODE_NUMBER {
+            let (Some(name), Some(stat)) = (OpCode::new(i as u8), stats.opcode[i].as_ref()) else {
+                continue;
+            };
+            let gas = stat.mgas * MGAS_TO_GAS as f64;
+            if stat.count == 0 || gas <= 0.0 {
+                continue;
+            }
+
+            let category = stat.cat.unwrap_or("");
+            let ns_per_gas = convert_cycles_to_ns_f64(stat.time) / gas;
+            let avg_gas = gas / stat.count as f64;
+            opcodes.push(OpcodeRepricing {
+                opcode: name.as_str(),
+                category,
+                count: stat.count,
+                avg_cost_ns: stat.avg_cost,
+                avg_gas,
+                ns_per_gas,
+                deviation: ns_per_gas / avg_ns_per_gas - 1.0,
+                suggested_gas: avg_gas * ns_per_gas / avg_ns_per_gas,
+            });
+
+            let entry = categories.entry(category).or_insert((0, 0, 0.0));
+            entry.0 += stat.count;
+            entry.1 += stat.time;
+            entry.2 += gas;
+        }
+        opcodes.sort_by(|a, b| b.deviation.total_cmp(&a.deviation));
+
+        let mut categories: Vec<CategoryRepricing> = categories
+            .into_iter()
+            .map(|(category, (count, time, gas))| {
+                let ns_per_gas = convert_cycles_to_ns_f64(time) / gas;
+                CategoryRepricing {
+                    category,
+                    count,
+                    ns_per_gas,
+                    deviation: ns_per_gas / avg_ns_per_gas - 1.0,
+                }
+            })
+            .collect();
+        categories.sort_by(|a, b| b.deviation.total_cmp(&a.deviation));
+
+        RepricingReport {
+            avg_ns_per_gas,
+            opcodes,
+            categories,
+        }
+    }
+}
+
+impl From<&OpcodeRecord> for RepricingReport {
+    fn from(record: &OpcodeRecord) -> Self {
+        let stats: OpcodeStats = record.into();
+        (&stats).into()
+    }
+}
+
+impl RepricingReport {
+    /// Returns the suggested pricing table of opcodes in csv format.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from(
+            "opcode,category,count,avg_cost_ns,avg_gas,ns_per_gas,deviation_pct,suggested_gas\n",
+        );
+        for op in self.opcodes.iter() {
+            csv.push_str(&format!(
+                "{},{},{},{:.3},{:.3},{:.6},{:.3},{:.3}\n",
+                op.opcode,
+                op.category,
+                op.count,
+                op.avg_cost_ns,
+                op.avg_gas,
+                op.ns_per_gas,
+                op.deviation * 100.0,
+                op.suggested_gas,
+            ));
+        }
+        csv
+    }
+
+    /// Returns the whole report in json format.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Serialize repricing report failed")
+    }
+
+    /// Write the report to path, in json format if the extension of path is "json",
+    /// otherwise in csv format.
+    pub fn write_to_file(&self, path: &Path) -> io::Result<()> {
+        let content = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => self.to_json(),
+            _ => self.to_csv(),
+        };
+        fs::write(path, content)
+    }
+}
+
+impl Print for RepricingReport {
+    fn print_title(&self) {
+        println!("==========================================Gas repricing==========================================");
+        println!("average ns/gas: {:.6}", self.avg_ns_per_gas);
+    }
+
+    fn print_content(&self) {
+        println!(
+            "{:<COL_WIDTH$}{:>COL_WIDTH$}{:>COL_WIDTH$}{:>COL_WIDTH$}",
+            "Opcode Cat.", "Count", "ns/gas", "Deviation (%)",
+        );
+        for cat in self.categories.iter() {
+            println!(
+                "{:<COL_WIDTH$}{:>COL_WIDTH$}{:>COL_WIDTH$.6}{:>COL_WIDTH$.2}",
+                cat.category,
+                cat.count,
+                cat.ns_per_gas,
+                cat.deviation * 100.0,
+            );
+        }
+
+        println!();
+        println!(
+            "{:<COL_WIDTH$}{:>COL_WIDTH$}{:>COL_WIDTH$}{:>COL_WIDTH$}{:>COL_WIDTH$}{:>COL_WIDTH$}",
+            "Opcode", "Cost (ns)", "Avg gas", "ns/gas", "Deviation (%)", "Suggested gas",
+        );
+        for op in self.opcodes.iter().take(TOP_N) {
+            println!(
+                "{:<COL_WIDTH$}{:>COL_WIDTH$.1}{:>COL_WIDTH$.2}{:>COL_WIDTH$.6}{:>COL_WIDTH$.2}{:>COL_WIDTH$.2}",
+                op.opcode,
+                op.avg_cost_ns,
+                op.avg_gas,
+                op.ns_per_gas,
+                op.deviation * 100.0,
+                op.suggested_gas,
+            );
+        }
+    }
+}
+
+/// Writes the gas repricing report to a file each time the opcode record is updated.
+#[derive(Debug)]
+pub struct RepricingReportSink {
+    path: PathBuf,
+}
+
+impl RepricingReportSink {
+    /// Creates a [RepricingReportSink] writing to path, in json format if the extension
+    /// of path is "json", otherwise in csv format.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        RepricingReportSink { path: path.into() }
+    }
+}
+
+impl MetricSink for RepricingReportSink {
+    fn on_event(&mut self, event: &MetricEvent) {
+        if let MetricEvent::OpcodeInfo { record, .. } = event {
+            if let Err(err) = RepricingReport::from(&**record).write_to_file(&self.path) {
+                eprintln!("Failed to write repricing report: {:?}", err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The average cycles per gas of record(), the time of STOP counts but its gas is 0.
+    const AVG_CYCLES_PER_GAS: f64 = 13100.0 / 8000.0;
+
+    /// Returns a record in which ADD spends 1 cycle and MUL spends 2 cycles per gas, and
+    /// STOP charges no gas.
+    fn record() -> OpcodeRecord {
+        let mut record = OpcodeRecord {
+            is_updated: true,
+            ..Default::default()
+        };
+        record.opcode_record[ADD as usize] = (1000, 3000, 3000);
+        record.opcode_record[MUL as usize] = (1000, 10000, 5000);
+        record.opcode_record[STOP as usize] = (10, 100, 0);
+        record
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_opcodes_are_ranked_by_deviation() {
+        let report = RepricingReport::from(&record());
+
+        let opcodes: Vec<&str> = report.opcodes.iter().map(|op| op.opcode).collect();
+        assert_eq!(opcodes, vec!["MUL", "ADD"]);
+        let (mul, add) = (&report.opcodes[0], &report.opcodes[1]);
+        assert_close(mul.deviation, 2.0 / AVG_CYCLES_PER_GAS - 1.0);
+        assert_close(add.deviation, 1.0 / AVG_CYCLES_PER_GAS - 1.0);
+        assert_close(mul.avg_gas, 5.0);
+        assert_close(mul.suggested_gas, 5.0 * 2.0 / AVG_CYCLES_PER_GAS);
+        assert_close(add.suggested_gas, 3.0 / AVG_CYCLES_PER_GAS);
+    }
+
+    #[test]
+    fn test_categories_are_merged() {
+        let report = RepricingReport::from(&record());
+
+        assert_eq!(report.categories.len(), 1);
+        let category = &report.categories[0];
+        assert_eq!(category.count, 2000);
+        assert_close(
+            category.deviation,
+            13000.0 / 8000.0 / AVG_CYCLES_PER_GAS - 1.0,
+        );
+    }
+
+    #[test]
+    fn test_empty_record_has_no_opcodes() {
+        let report = RepricingReport::from(&OpcodeRecord::default());
+
+        assert!(report.opcodes.is_empty());
+        assert!(report.categories.is_empty());
+        assert_eq!(report.to_csv().lines().count(), 1);
+    }
+}
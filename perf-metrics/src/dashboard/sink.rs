@@ -0,0 +1,27 @@
+//! This module defines [MetricSink], the consumer of metric events which
+//! [DashboardListener](super::DashboardListener) fans the events out to.
+use crate::metrics::metric::MetricEvent;
+
+/// A consumer of metric events, e.g. the terminal, a file or a network endpoint.
+///
+/// Sinks report their own errors, so that a failing sink does not affect the others.
+pub trait MetricSink: Send {
+    /// Called with each event in the order they are sent.
+    fn on_event(&mut self, event: &MetricEvent);
+
+    /// Called instead of on_event when the events are replayed from a dump, with the time
+    /// (ms since the unix epoch) the event was dumped, which the sinks measuring rates
+    /// should use instead of the current time.
+    fn on_replayed_event(&mut self, _timestamp_ms: u64, event: &MetricEvent) {
+        self.on_event(event);
+    }
+
+    /// Called when all events received so far have been handled, e.g. to flush the
+    /// buffered output.
+    fn flush(&mut self) {}
+
+    /// Called once when the channel of events is closed.
+    fn shutdown(&mut self) {
+        self.flush();
+    }
+}
@@ -0,0 +1,166 @@
+//! [TerminalSink] is used to print the metrics on stdout.
+#[cfg(any(
+    feature = "enable_opcode_metrics",
+    feature = "enable_cache_record",
+    feature = "enable_execution_duration_record",
+))]
+use super::commons::*;
+use super::sink::MetricSink;
+#[cfg(feature = "enable_tps_gas_record")]
+use super::tps_gas::{impl_tps_report_interval, TpsAndGasDisplayer};
+use crate::metrics::metric::MetricEvent;
+
+/// Which of the cumulative and the per-interval records are displayed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DisplayMode {
+    /// The records accumulated since the start.
+    #[default]
+    Cumulative,
+    /// The records since the previous event, e.g. of a single block or block range.
+    Delta,
+    /// Both the cumulative and the per-interval records.
+    Both,
+}
+
+#[cfg(any(
+    feature = "enable_opcode_metrics",
+    feature = "enable_cache_record",
+    feature = "enable_execution_duration_record",
+))]
+impl DisplayMode {
+    /// Returns whether the cumulative records are displayed.
+    pub(super) fn cumulative(&self) -> bool {
+        *self != DisplayMode::Delta
+    }
+
+    /// Returns whether the per-interval records are displayed.
+    pub(super) fn delta(&self) -> bool {
+        *self != DisplayMode::Cumulative
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct TerminalSink {
+    /// Which of the cumulative and the per-interval records are displayed.
+    display_mode: DisplayMode,
+
+    #[cfg(feature = "enable_tps_gas_record")]
+    tps_gas_displayer: TpsAndGasDisplayer,
+}
+
+impl TerminalSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Display the cumulative records, the per-interval records or both, the default is
+    /// the cumulative records.
+    pub fn with_display_mode(mut self, display_mode: DisplayMode) -> Self {
+        self.display_mode = display_mode;
+        self
+    }
+
+    /// Print the cumulative record and the record since the previous event according to
+    /// the display mode.
+    #[cfg(any(
+        feature = "enable_opcode_metrics",
+        feature = "enable_cache_record",
+        feature = "enable_execution_duration_record",
+    ))]
+    fn print_records<T: Print>(&self, block_number: u64, record: &T, delta: &T) {
+        if self.display_mode.cumulative() {
+            record.print(block_number);
+        }
+        if self.display_mode.delta() {
+            println!();
+            println!("Since the previous report, up to block {}:", block_number);
+            delta.print(block_number);
+        }
+    }
+}
+
+impl TerminalSink {
+    /// Print event, replay_timestamp_ms is the time (ms) it was dumped if it is
+    /// replayed.
+    #[cfg_attr(not(feature = "enable_tps_gas_record"), allow(unused_variables))]
+    fn print_event(&mut self, replay_timestamp_ms: Option<u64>, event: &MetricEvent) {
+        match event {
+            #[cfg(feature = "enable_execution_duration_record")]
+            MetricEvent::ExecutionStageTime {
+                block_number,
+                record,
+                delta,
+            } => {
+                self.print_records(*block_number, record, delta);
+            }
+            #[cfg(feature = "enable_tps_gas_record")]
+            MetricEvent::BlockTpsAndGas {
+                block_number,
+                record,
+            } => {
+                self.tps_gas_displayer
+                    .print(*block_number, record, replay_timestamp_ms);
+            }
+            #[cfg(feature = "enable_opcode_metrics")]
+            MetricEvent::OpcodeInfo {
+                block_number,
+                record,
+                delta,
+            } => {
+                self.print_records(*block_number, &**record, &**delta);
+            }
+            #[cfg(feature = "enable_opcode_metrics")]
+            MetricEvent::TxOpcodeInfo {
+                block_number,
+                record,
+            } => {
+                record.print(*block_number);
+            }
+            #[cfg(feature = "enable_cache_record")]
+            MetricEvent::CacheDbInfo {
+                block_number,
+                size,
+                record,
+                delta,
+            } => {
+                super::cache::print_state_size(*block_number, *size);
+                self.print_records(*block_number, record, delta);
+            }
+            #[cfg(any(
+                feature = "enable_opcode_metrics",
+                feature = "enable_cache_record",
+                feature = "enable_execution_duration_record",
+                feature = "enable_tps_gas_record",
+            ))]
+            MetricEvent::MetricsHealth {
+                block_number,
+                health,
+            } => {
+                super::commons::print_metrics_health(*block_number, health);
+            }
+            #[cfg(feature = "enable_cache_record")]
+            MetricEvent::CacheHotKeys {
+                block_number,
+                record,
+            } => {
+                record.print(*block_number);
+            }
+            // Only reachable if no metric is enabled, in which case there is no event.
+            #[allow(unreachable_patterns)]
+            _ => {}
+        }
+    }
+}
+
+#[cfg(feature = "enable_tps_gas_record")]
+impl_tps_report_interval!(TerminalSink);
+
+impl MetricSink for TerminalSink {
+    fn on_event(&mut self, event: &MetricEvent) {
+        self.print_event(None, event);
+    }
+
+    fn on_replayed_event(&mut self, timestamp_ms: u64, event: &MetricEvent) {
+        self.print_event(Some(timestamp_ms), event);
+    }
+}
@@ -1,70 +1,469 @@
 //! This module is used to support the display of tps and mgas/s.
+//!
+//! The rates are computed over the execution time, which is measured by the clock_ns
+//! passed with each message. TpsGasRecord carries no timestamp, so live events are timed
+//! with live_clock_ns when the listener receives them, and a backlog in the channel shows
+//! up as a dip followed by a burst. Replayed events are timed when they were dumped.
+//!
+//! The sinks showing the rates keep a [TpsAndGasDisplayer] in the field tps_gas_displayer,
+//! and get the builder of the interval from impl_tps_report_interval.
+#[cfg(feature = "enable_prometheus")]
+use super::prometheus::{PrometheusMetrics, PrometheusText};
 use crate::metrics::TpsAndGasMessage;
 use revm_utils::time_utils::instant::Instant;
-use std::ops::{Div, Mul};
+use std::{collections::VecDeque, time::Duration};
+
+const NANOS_PER_SEC: f64 = 1_000_000_000.0;
+const NANOS_PER_MILLI: f64 = 1_000_000.0;
+const GAS_PER_MGAS: f64 = 1_000_000.0;
+const COL_WIDTH: usize = 15;
+/// The time constant of the EWMA, the weight of a rate decays to 1/e after it.
+const EWMA_TIME_CONSTANT_NS: f64 = 60.0 * NANOS_PER_SEC;
+/// The windows of the windowed rates, which are 1, 5 and 15 minutes.
+const WINDOWS: [(&str, f64); 3] = [
+    ("1m", 60.0 * NANOS_PER_SEC),
+    ("5m", 300.0 * NANOS_PER_SEC),
+    ("15m", 900.0 * NANOS_PER_SEC),
+];
+/// Returns the time (ns) of the clock of live events, which only makes sense relative to
+/// another time of it. It is read when the listener receives an event, not when the
+/// event is sent.
+fn live_clock_ns() -> f64 {
+    Instant::now()
+        .checked_nanos_since(Instant::default())
+        .unwrap_or(0.0)
+}
+
+/// The longest window, samples older than it are discarded.
+const MAX_WINDOW_NS: f64 = WINDOWS[WINDOWS.len() - 1].1;
+
+/// When the tps and mgas/s are reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TpsReportInterval {
+    /// Report at each block whose number is a multiple of n.
+    Blocks(u64),
+    /// Report at the first block after the interval of execution time has elapsed.
+    Time(Duration),
+}
+
+impl Default for TpsReportInterval {
+    fn default() -> Self {
+        TpsReportInterval::Blocks(1000)
+    }
+}
+
+/// The number of txs and gas executed at a point of the execution time. The execution
+/// time excludes the time between the execution of blocks, e.g. writing to db.
+#[derive(Debug, Default, Clone, Copy)]
+struct Sample {
+    time_ns: f64,
+    txs: u128,
+    gas: u128,
+}
+
+impl Sample {
+    /// Returns (tps, mgas/s) from earlier to self, or None if no time elapsed.
+    fn rates_since(&self, earlier: &Sample) -> Option<(f64, f64)> {
+        let elapsed_ns = self.time_ns - earlier.time_ns;
+        if elapsed_ns <= 0.0 {
+            return None;
+        }
+        let secs = elapsed_ns / NANOS_PER_SEC;
+        let txs = self.txs.saturating_sub(earlier.txs) as f64;
+        let gas = self.gas.saturating_sub(earlier.gas) as f64;
+        Some((txs / secs, gas / GAS_PER_MGAS / secs))
+    }
+}
 
 #[derive(Debug, Default)]
 pub(super) struct TpsAndGasDisplayer {
-    pre_txs: u128,
-    pre_gas: u128,
-    last_txs: u128,
-    last_gas: u128,
-    pre_instant: Instant,
+    interval: TpsReportInterval,
+    /// The execution time before the current period of execution.
+    elapsed_ns: f64,
+    /// The clock time (ns) at the start of the current period of execution, None if it is
+    /// not executing.
+    period_start: Option<f64>,
+    /// The latest sample.
+    last: Sample,
+    /// The sample at the previous report.
+    last_report: Sample,
+    /// The samples within the longest window, and one before it.
+    samples: VecDeque<Sample>,
+    /// The rates of the reports within the longest window, as (time_ns, tps, mgas/s).
+    reports: VecDeque<(f64, f64, f64)>,
+    /// The EWMA of (tps, mgas/s).
+    ewma: Option<(f64, f64)>,
+    /// The (tps, mgas/s) of each row of the latest report.
+    rates: Vec<(&'static str, Option<(f64, f64)>)>,
 }
 
 impl TpsAndGasDisplayer {
-    const N: u64 = 1000;
+    pub(super) fn new(interval: TpsReportInterval) -> Self {
+        TpsAndGasDisplayer {
+            interval,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the execution time since the start at clock_ns.
+    fn now_ns(&self, clock_ns: f64) -> f64 {
+        let period_ns = self
+            .period_start
+            .map_or(0.0, |start| (clock_ns - start).max(0.0));
+        self.elapsed_ns + period_ns
+    }
 
-    fn update_tps_and_gas(&mut self, block_number: u64, txs: u128, gas: u128) {
-        if 0 == block_number % Self::N {
-            self.print_content(block_number, txs, gas);
+    fn push_sample(&mut self, sample: Sample) {
+        self.last = sample;
+        self.samples.push_back(sample);
+        // Keep one sample before the longest window, so that it is fully covered.
+        while self.samples.len() > 2 && sample.time_ns - self.samples[1].time_ns >= MAX_WINDOW_NS {
+            self.samples.pop_front();
         }
+    }
+
+    /// Returns true if the rates are reported.
+    fn update_tps_and_gas(
+        &mut self,
+        block_number: u64,
+        txs: u128,
+        gas: u128,
+        clock_ns: f64,
+    ) -> bool {
+        let sample = Sample {
+            time_ns: self.now_ns(clock_ns),
+            txs,
+            gas,
+        };
+        self.push_sample(sample);
 
-        self.last_txs = txs;
-        self.last_gas = gas;
+        let should_report = match self.interval {
+            TpsReportInterval::Blocks(n) => n != 0 && block_number.is_multiple_of(n),
+            TpsReportInterval::Time(interval) => {
+                sample.time_ns - self.last_report.time_ns >= interval.as_nanos() as f64
+            }
+        };
+        should_report && self.report()
     }
 
-    fn start_record(&mut self) {
-        self.pre_txs = self.last_txs;
-        self.pre_gas = self.last_gas;
-        self.pre_instant = Instant::now();
+    fn start_record(&mut self, clock_ns: f64) {
+        self.period_start = Some(clock_ns);
+        let sample = Sample {
+            time_ns: self.elapsed_ns,
+            ..self.last
+        };
+        self.push_sample(sample);
     }
 
-    fn stop_record(&mut self, block_number: u64) {
-        self.print_content(block_number, self.last_txs, self.last_gas);
+    /// Returns true if the rates are reported.
+    fn stop_record(&mut self, clock_ns: f64) -> bool {
+        self.elapsed_ns = self.now_ns(clock_ns);
+        self.period_start = None;
+        let sample = Sample {
+            time_ns: self.elapsed_ns,
+            ..self.last
+        };
+        self.push_sample(sample);
+        self.report()
     }
 
-    fn print_content(&mut self, block_number: u64, txs: u128, gas: u128) {
-        let now = Instant::now();
-        let elapsed_ns = now.checked_nanos_since(self.pre_instant).unwrap_or(0.0);
-        let delta_txs = txs - self.pre_txs;
-        let delta_gas = gas - self.pre_gas;
+    /// Returns (tps, mgas/s) over the latest window_ns of execution time.
+    fn windowed_rates(&self, window_ns: f64) -> Option<(f64, f64)> {
+        let cutoff = self.last.time_ns - window_ns;
+        let earlier = self
+            .samples
+            .iter()
+            .rev()
+            .find(|sample| sample.time_ns <= cutoff)
+            .or(self.samples.front())?;
+        self.last.rates_since(earlier)
+    }
+
+    fn print_rates(name: &str, rates: Option<(f64, f64)>) {
+        let (tps, mgas_ps) = rates.unwrap_or((f64::NAN, f64::NAN));
+        println!(
+            "{:<COL_WIDTH$}{:>COL_WIDTH$.1}{:>COL_WIDTH$.3}",
+            name, tps, mgas_ps
+        );
+    }
+
+    /// Compute the rates since the previous report, returns false if no time elapsed.
+    fn report(&mut self) -> bool {
+        let Some((tps, mgas_ps)) = self.last.rates_since(&self.last_report) else {
+            return false;
+        };
+        let elapsed_ns = self.last.time_ns - self.last_report.time_ns;
+        self.last_report = self.last;
+
+        let alpha = 1.0 - (-elapsed_ns / EWMA_TIME_CONSTANT_NS).exp();
+        let ewma = match self.ewma {
+            Some((ewma_tps, ewma_mgas_ps)) => (
+                ewma_tps + alpha * (tps - ewma_tps),
+                ewma_mgas_ps + alpha * (mgas_ps - ewma_mgas_ps),
+            ),
+            None => (tps, mgas_ps),
+        };
+        self.ewma = Some(ewma);
 
-        let tps = delta_txs.mul(1000_000_000).div(elapsed_ns as u128);
-        let mgas_ps = (delta_gas as f64)
-            .mul(1000_000_000 as f64)
-            .div(elapsed_ns as f64);
+        self.reports.push_back((self.last.time_ns, tps, mgas_ps));
+        while self
+            .reports
+            .front()
+            .is_some_and(|report| self.last.time_ns - report.0 > MAX_WINDOW_NS)
+        {
+            self.reports.pop_front();
+        }
+        let min = self
+            .reports
+            .iter()
+            .fold((f64::MAX, f64::MAX), |min, report| {
+                (min.0.min(report.1), min.1.min(report.2))
+            });
+        let max = self.reports.iter().fold((0.0f64, 0.0f64), |max, report| {
+            (max.0.max(report.1), max.1.max(report.2))
+        });
+
+        let mut rates = vec![("interval", Some((tps, mgas_ps))), ("ewma", Some(ewma))];
+        for (name, window_ns) in WINDOWS {
+            rates.push((name, self.windowed_rates(window_ns)));
+        }
+        rates.push(("min (15m)", Some(min)));
+        rates.push(("max (15m)", Some(max)));
+        rates.push(("since start", self.last.rates_since(&Sample::default())));
+        self.rates = rates;
+        true
+    }
+
+    /// Returns the (tps, mgas/s) since the previous report of the latest report.
+    pub(super) fn interval_rates(&self) -> Option<(f64, f64)> {
+        self.rates.first().and_then(|(_, rates)| *rates)
+    }
+
+    /// Returns the (tps, mgas/s) of each row of the latest report.
+    #[cfg(feature = "enable_tui")]
+    pub(super) fn rates(&self) -> &[(&'static str, Option<(f64, f64)>)] {
+        &self.rates
+    }
 
-        self.pre_txs = txs;
-        self.pre_gas = gas;
-        self.pre_instant = now;
+    fn print_content(&self, block_number: u64) {
+        println!();
+        println!("block_number: {:?}", block_number);
+        println!(
+            "{:<COL_WIDTH$}{:>COL_WIDTH$}{:>COL_WIDTH$}",
+            "Rate", "TPS", "MGas/s"
+        );
+        for (name, rates) in self.rates.iter() {
+            Self::print_rates(name, *rates);
+        }
+        println!();
+    }
 
-        println!("block_number: {:?}, TPS : {:?}", block_number, tps);
-        println!("block_number: {:?}, MGas: {:.3}\n", block_number, mgas_ps);
+    /// Update the rates with message, replay_timestamp_ms is the time (ms) it was dumped if
+    /// it is replayed. Returns the block number of the new report if the rates are
+    /// reported.
+    pub(super) fn update(
+        &mut self,
+        block_number: u64,
+        message: &TpsAndGasMessage,
+        replay_timestamp_ms: Option<u64>,
+    ) -> Option<u64> {
+        let clock_ns = replay_timestamp_ms.map_or_else(live_clock_ns, |timestamp_ms| {
+            timestamp_ms as f64 * NANOS_PER_MILLI
+        });
+        self.update_at(block_number, message, clock_ns)
     }
 
-    pub(super) fn print(&mut self, block_number: u64, message: TpsAndGasMessage) {
+    /// Update the rates with message received at clock_ns, see update.
+    fn update_at(
+        &mut self,
+        block_number: u64,
+        message: &TpsAndGasMessage,
+        clock_ns: f64,
+    ) -> Option<u64> {
         match message {
-            TpsAndGasMessage::Record(record) => {
-                self.update_tps_and_gas(record.block_number, record.txs, record.gas)
+            TpsAndGasMessage::Record(record) => self
+                .update_tps_and_gas(record.block_number, record.txs, record.gas, clock_ns)
+                .then_some(record.block_number),
+            TpsAndGasMessage::Switch(true) => {
+                self.start_record(clock_ns);
+                None
             }
-            TpsAndGasMessage::Switch(switch) => {
-                if switch {
-                    self.start_record();
-                } else {
-                    self.stop_record(block_number);
-                }
+            TpsAndGasMessage::Switch(false) => self.stop_record(clock_ns).then_some(block_number),
+        }
+    }
+
+    pub(super) fn print(
+        &mut self,
+        block_number: u64,
+        message: &TpsAndGasMessage,
+        replay_timestamp_ms: Option<u64>,
+    ) {
+        if let Some(block_number) = self.update(block_number, message, replay_timestamp_ms) {
+            self.print_content(block_number);
+        }
+    }
+}
+
+/// Implement with_tps_report_interval for the sinks keeping a [TpsAndGasDisplayer] in the
+/// field tps_gas_displayer.
+macro_rules! impl_tps_report_interval {
+    ($sink:ty) => {
+        impl $sink {
+            /// Report the tps and mgas/s at the interval, the default is every 1000 blocks.
+            pub fn with_tps_report_interval(
+                mut self,
+                interval: $crate::dashboard::TpsReportInterval,
+            ) -> Self {
+                self.tps_gas_displayer =
+                    $crate::dashboard::tps_gas::TpsAndGasDisplayer::new(interval);
+                self
             }
         }
+    };
+}
+pub(super) use impl_tps_report_interval;
+
+#[cfg(feature = "enable_prometheus")]
+impl PrometheusMetrics for TpsAndGasDisplayer {
+    fn write_metrics(&self, text: &mut PrometheusText) {
+        let counters = [
+            (
+                "reth_txs_total",
+                "The number of txs executed.",
+                self.last.txs,
+            ),
+            (
+                "reth_gas_total",
+                "The gas used by the txs executed.",
+                self.last.gas,
+            ),
+        ];
+        for (name, help, value) in counters {
+            text.family(name, "counter", help);
+            text.sample(name, &[], value as f64);
+        }
+
+        let rates: Vec<(&str, (f64, f64))> = self
+            .rates
+            .iter()
+            .filter_map(|(window, rates)| Some((*window, (*rates)?)))
+            .collect();
+        let gauges = [
+            (
+                "reth_tps",
+                "The txs per second of execution time over the window of the latest report.",
+                rates.iter().map(|(_, rates)| rates.0).collect::<Vec<_>>(),
+            ),
+            (
+                "reth_mgas_per_second",
+                "The MGas per second of execution time over the window of the latest report.",
+                rates.iter().map(|(_, rates)| rates.1).collect(),
+            ),
+        ];
+        for (name, help, values) in gauges {
+            text.family(name, "gauge", help);
+            for ((window, _), value) in rates.iter().zip(values) {
+                text.sample(name, &[("window", window)], value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::TpsGasRecord;
+
+    const SEC: f64 = NANOS_PER_SEC;
+
+    fn record(block_number: u64, txs: u128) -> TpsAndGasMessage {
+        TpsAndGasMessage::Record(TpsGasRecord {
+            block_number,
+            txs,
+            gas: txs * 1000,
+        })
+    }
+
+    fn rates_of(displayer: &TpsAndGasDisplayer, name: &str) -> (f64, f64) {
+        displayer
+            .rates
+            .iter()
+            .find(|(row, _)| *row == name)
+            .and_then(|(_, rates)| *rates)
+            .unwrap_or_else(|| panic!("no rates of {name}"))
+    }
+
+    fn assert_rates(displayer: &TpsAndGasDisplayer, name: &str, tps: f64) {
+        let rates = rates_of(displayer, name);
+        assert!((rates.0 - tps).abs() < 1e-9, "{name}: {rates:?} != {tps}");
+        assert!((rates.1 - tps / 1000.0).abs() < 1e-12, "{name}: {rates:?}");
+    }
+
+    #[test]
+    fn test_block_interval_and_ewma() {
+        let mut displayer = TpsAndGasDisplayer::new(TpsReportInterval::Blocks(2));
+        displayer.update_at(0, &TpsAndGasMessage::Switch(true), 0.0);
+        assert_eq!(displayer.update_at(1, &record(1, 50), 5.0 * SEC), None);
+        assert_eq!(displayer.update_at(2, &record(2, 100), 10.0 * SEC), Some(2));
+        assert_rates(&displayer, "interval", 10.0);
+        assert_rates(&displayer, "ewma", 10.0);
+
+        assert_eq!(displayer.update_at(4, &record(4, 300), 20.0 * SEC), Some(4));
+        assert_rates(&displayer, "interval", 20.0);
+        let alpha = 1.0 - (-10.0 / 60.0f64).exp();
+        assert_rates(&displayer, "ewma", 10.0 + alpha * 10.0);
+        assert_rates(&displayer, "min (15m)", 10.0);
+        assert_rates(&displayer, "max (15m)", 20.0);
+        assert_rates(&displayer, "since start", 15.0);
+    }
+
+    #[test]
+    fn test_windowed_rates() {
+        let mut displayer = TpsAndGasDisplayer::new(TpsReportInterval::Blocks(1));
+        displayer.update_at(0, &TpsAndGasMessage::Switch(true), 0.0);
+        // 10 tps in the first 10 minutes, then 30 tps in the next 10 minutes.
+        let mut txs = 0;
+        for minute in 1..=20u64 {
+            txs += if minute <= 10 { 600 } else { 1800 };
+            let clock_ns = minute as f64 * 60.0 * SEC;
+            assert_eq!(
+                displayer.update_at(minute, &record(minute, txs), clock_ns),
+                Some(minute)
+            );
+        }
+
+        assert_rates(&displayer, "1m", 30.0);
+        assert_rates(&displayer, "5m", 30.0);
+        // From minute 5 (3000 txs) to minute 20 (24000 txs).
+        assert_rates(&displayer, "15m", 21000.0 / 900.0);
+        // The reports of the first 4 minutes are out of the 15m window.
+        assert_rates(&displayer, "min (15m)", 10.0);
+        assert_rates(&displayer, "max (15m)", 30.0);
+        assert_rates(&displayer, "since start", 20.0);
+    }
+
+    #[test]
+    fn test_time_interval_excludes_pauses() {
+        let interval = TpsReportInterval::Time(Duration::from_secs(10));
+        let mut displayer = TpsAndGasDisplayer::new(interval);
+        displayer.update_at(0, &TpsAndGasMessage::Switch(true), 0.0);
+        assert_eq!(displayer.update_at(1, &record(1, 40), 4.0 * SEC), None);
+        assert_eq!(displayer.update_at(2, &record(2, 80), 8.0 * SEC), None);
+        // Stopping the execution reports the rates so far.
+        assert_eq!(
+            displayer.update_at(2, &TpsAndGasMessage::Switch(false), 8.0 * SEC),
+            Some(2)
+        );
+        assert_rates(&displayer, "interval", 10.0);
+
+        // The 92s between the execution of blocks is not execution time.
+        displayer.update_at(2, &TpsAndGasMessage::Switch(true), 100.0 * SEC);
+        assert_eq!(displayer.update_at(3, &record(3, 130), 105.0 * SEC), None);
+        assert_eq!(
+            displayer.update_at(4, &record(4, 180), 110.0 * SEC),
+            Some(4)
+        );
+        assert_rates(&displayer, "interval", 10.0);
+        assert_rates(&displayer, "since start", 10.0);
     }
 }
@@ -0,0 +1,666 @@
+//! This module is used to show the metrics in an interactive terminal UI, with a tab for
+//! each kind of record, live sparklines and sortable, filterable tables which are updated
+//! in place, instead of printing hundreds of lines per report.
+#[cfg(feature = "enable_cache_record")]
+use super::cache::CacheStats;
+#[cfg(any(
+    feature = "enable_opcode_metrics",
+    feature = "enable_execution_duration_record",
+))]
+use super::commons::cycles_as_secs;
+#[cfg(feature = "enable_opcode_metrics")]
+use super::opcode::{OpcodeStats, OPCODE_NUMBER};
+use super::sink::MetricSink;
+#[cfg(feature = "enable_tps_gas_record")]
+use super::tps_gas::{impl_tps_report_interval, TpsAndGasDisplayer};
+use crate::metrics::metric::MetricEvent;
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout, Rect},
+    style::{Modifier, Style},
+    text::Line,
+    widgets::{Block, Cell, Paragraph, Row, Sparkline, Table, TableState, Tabs},
+    DefaultTerminal, Frame,
+};
+#[cfg(feature = "enable_opcode_metrics")]
+use revm::revm_opcode::OpCode;
+#[cfg(feature = "enable_cache_record")]
+use revm_utils::metrics::types::Function;
+use std::{
+    cmp::Ordering,
+    collections::VecDeque,
+    io,
+    sync::{Arc, Mutex},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// The interval at which the UI is redrawn and the keys are polled.
+const REFRESH_INTERVAL: Duration = Duration::from_millis(250);
+/// Number of the latest values kept for a sparkline.
+const HISTORY_LEN: usize = 500;
+const SPARKLINE_HEIGHT: u16 = 7;
+/// Number of rows scrolled by PageUp and PageDown.
+const PAGE_ROWS: u16 = 20;
+const HELP: &str =
+    "q: quit  Tab/1-5: switch tab  s: sort by next column  r: reverse  /: filter  Up/Down: scroll";
+
+/// The tabs of the UI, in order.
+#[derive(Debug, Clone, Copy)]
+enum Tab {
+    Throughput,
+    Stages,
+    Opcodes,
+    Cache,
+    Memory,
+}
+
+impl Tab {
+    const COUNT: usize = 5;
+    const ALL: [Tab; Tab::COUNT] = [
+        Tab::Throughput,
+        Tab::Stages,
+        Tab::Opcodes,
+        Tab::Cache,
+        Tab::Memory,
+    ];
+
+    fn title(&self) -> &'static str {
+        match self {
+            Tab::Throughput => "Throughput",
+            Tab::Stages => "Stages",
+            Tab::Opcodes => "Opcodes",
+            Tab::Cache => "Cache",
+            Tab::Memory => "Memory",
+        }
+    }
+}
+
+/// Returns value with the precision, or an empty string if it is not finite, e.g. the
+/// ratio of an empty record.
+#[cfg(any(
+    feature = "enable_opcode_metrics",
+    feature = "enable_cache_record",
+    feature = "enable_execution_duration_record",
+    feature = "enable_tps_gas_record",
+))]
+fn number(value: f64, precision: usize) -> String {
+    if value.is_finite() {
+        format!("{:.*}", precision, value)
+    } else {
+        String::new()
+    }
+}
+
+/// Compares two cells as numbers if both are, otherwise as text.
+fn compare_cells(a: &str, b: &str) -> Ordering {
+    let (a, b) = (a.trim(), b.trim());
+    match (a.parse::<f64>(), b.parse::<f64>()) {
+        (Ok(a), Ok(b)) => a.total_cmp(&b),
+        _ => a.cmp(b),
+    }
+}
+
+/// The recent values of a metric, shown as a sparkline.
+#[derive(Debug)]
+struct History {
+    title: &'static str,
+    /// The factor applied to the values before they are rounded for the sparkline.
+    scale: f64,
+    values: VecDeque<f64>,
+}
+
+impl History {
+    fn new(title: &'static str, scale: f64) -> Self {
+        History {
+            title,
+            scale,
+            values: VecDeque::with_capacity(HISTORY_LEN),
+        }
+    }
+
+    /// Append value, non-finite values are skipped.
+    #[cfg(any(
+        feature = "enable_opcode_metrics",
+        feature = "enable_cache_record",
+        feature = "enable_execution_duration_record",
+        feature = "enable_tps_gas_record",
+    ))]
+    fn push(&mut self, value: f64) {
+        if !value.is_finite() {
+            return;
+        }
+        if self.values.len() == HISTORY_LEN {
+            self.values.pop_front();
+        }
+        self.values.push_back(value);
+    }
+
+    /// Returns the title with the latest value.
+    fn title(&self) -> String {
+        match self.values.back() {
+            Some(value) => format!(" {}: {:.2} ", self.title, value),
+            None => format!(" {} ", self.title),
+        }
+    }
+
+    /// Returns the latest values which fit in width.
+    fn data(&self, width: u16) -> Vec<u64> {
+        let skip = self.values.len().saturating_sub(width as usize);
+        self.values
+            .iter()
+            .skip(skip)
+            .map(|value| (value * self.scale).max(0.0).round() as u64)
+            .collect()
+    }
+}
+
+/// A table of the latest record, whose cells are formatted already.
+#[derive(Debug)]
+struct DataTable {
+    header: &'static [&'static str],
+    rows: Vec<Vec<String>>,
+}
+
+/// The content of a tab, which is updated in place by the events.
+#[derive(Debug)]
+struct TabContent {
+    histories: Vec<History>,
+    table: DataTable,
+}
+
+impl TabContent {
+    fn new(histories: Vec<History>, header: &'static [&'static str]) -> Self {
+        TabContent {
+            histories,
+            table: DataTable {
+                header,
+                rows: Vec::new(),
+            },
+        }
+    }
+}
+
+/// The state shared by the sink and the UI thread.
+#[derive(Debug)]
+struct TuiState {
+    /// The latest block number of the events.
+    block_number: u64,
+    tabs: [TabContent; Tab::COUNT],
+    #[cfg(feature = "enable_cache_record")]
+    peak_state_size: usize,
+    /// Whether the sink is closed, which stops the UI.
+    closed: bool,
+}
+
+impl TuiState {
+    fn new() -> Self {
+        TuiState {
+            block_number: 0,
+            tabs: [
+                TabContent::new(
+                    vec![History::new("TPS", 1.0), History::new("MGas/s", 100.0)],
+                    &["Rate", "TPS", "MGas/s"],
+                ),
+                TabContent::new(
+                    vec![History::new("ExecutionStage time per report (s)", 1000.0)],
+                    &["Category", "Time (s)", "Time (%)"],
+                ),
+                TabContent::new(
+                    vec![History::new("Avg opcode cost per report (ns)", 100.0)],
+                    &[
+                        "Opcode",
+                        "Count",
+                        "Count (%)",
+                        "Time (s)",
+                        "Time (%)",
+                        "Cost (ns)",
+                        "Total Mgas",
+                        "Gas (%)",
+                        "Category",
+                    ],
+                ),
+                TabContent::new(
+                    vec![History::new("Miss ratio per report (%)", 100.0)],
+                    &[
+                        "State functions",
+                        "Hits",
+                        "Misses",
+                        "Miss ratio (%)",
+                        "Penalty time (s)",
+                        "Avg penalty (us)",
+                        "Avg hit (us)",
+                        "Miss delta (us)",
+                    ],
+                ),
+                TabContent::new(vec![History::new("State size", 1.0)], &["Metric", "Value"]),
+            ],
+            #[cfg(feature = "enable_cache_record")]
+            peak_state_size: 0,
+            closed: false,
+        }
+    }
+
+    #[cfg(any(
+        feature = "enable_opcode_metrics",
+        feature = "enable_cache_record",
+        feature = "enable_execution_duration_record",
+        feature = "enable_tps_gas_record",
+    ))]
+    fn tab(&mut self, tab: Tab) -> &mut TabContent {
+        &mut self.tabs[tab as usize]
+    }
+}
+
+/// The state of the UI which is not shared with the sink.
+#[derive(Debug, Default)]
+struct View {
+    tab: usize,
+    /// The sort column and whether it is descending, of each tab.
+    sorts: [Option<(usize, bool)>; Tab::COUNT],
+    /// The case-insensitive filter of the rows of each tab.
+    filters: [String; Tab::COUNT],
+    /// Whether the keys are typed into the filter of the current tab.
+    editing_filter: bool,
+    table_states: [TableState; Tab::COUNT],
+}
+
+impl View {
+    /// Handle a key pressed when the table of the current tab has columns, returns false
+    /// if the UI should quit.
+    fn handle_key(&mut self, key: KeyEvent, columns: usize) -> bool {
+        // The terminal is in raw mode, so Ctrl+C doesn't interrupt the process. It quits
+        // the UI instead, after which it interrupts the process as usual.
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return false;
+        }
+        let tab = self.tab;
+        if self.editing_filter {
+            let filter = &mut self.filters[tab];
+            match key.code {
+                KeyCode::Char(c) => filter.push(c),
+                KeyCode::Backspace => {
+                    filter.pop();
+                }
+                KeyCode::Enter => self.editing_filter = false,
+                KeyCode::Esc => {
+                    filter.clear();
+                    self.editing_filter = false;
+                }
+                _ => {}
+            }
+            self.table_states[tab] = TableState::default();
+            return true;
+        }
+
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Tab | KeyCode::Right => self.tab = (tab + 1) % Tab::COUNT,
+            KeyCode::BackTab | KeyCode::Left => self.tab = (tab + Tab::COUNT - 1) % Tab::COUNT,
+            KeyCode::Char(c @ '1'..='5') => self.tab = c as usize - '1' as usize,
+            KeyCode::Char('s') => {
+                // The names are sorted in ascending order, the numbers in descending order.
+                self.sorts[tab] = match self.sorts[tab] {
+                    None if columns > 0 => Some((0, false)),
+                    Some((column, _)) if column + 1 < columns => Some((column + 1, true)),
+                    _ => None,
+                }
+            }
+            KeyCode::Char('r') => {
+                if let Some((_, descending)) = &mut self.sorts[tab] {
+                    *descending = !*descending;
+                }
+            }
+            KeyCode::Char('/') => self.editing_filter = true,
+            KeyCode::Down => self.table_states[tab].select_next(),
+            KeyCode::Up => self.table_states[tab].select_previous(),
+            KeyCode::PageDown => self.table_states[tab].scroll_down_by(PAGE_ROWS),
+            KeyCode::PageUp => self.table_states[tab].scroll_up_by(PAGE_ROWS),
+            KeyCode::Home => self.table_states[tab].select_first(),
+            _ => {}
+        }
+        true
+    }
+}
+
+fn draw(frame: &mut Frame, state: &TuiState, view: &mut View) {
+    let [tabs_area, sparklines_area, table_area, footer_area] = Layout::vertical([
+        Constraint::Length(3),
+        Constraint::Length(SPARKLINE_HEIGHT),
+        Constraint::Min(3),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+
+    let titles = Tab::ALL
+        .iter()
+        .enumerate()
+        .map(|(i, tab)| format!("{} {}", i + 1, tab.title()));
+    let tabs = Tabs::new(titles)
+        .select(view.tab)
+        .highlight_style(Style::new().add_modifier(Modifier::BOLD | Modifier::REVERSED))
+        .block(Block::bordered().title(format!(" perf-metrics, block {} ", state.block_number)));
+    frame.render_widget(tabs, tabs_area);
+
+    let content = &state.tabs[view.tab];
+    draw_histories(frame, sparklines_area, &content.histories);
+    draw_table(frame, table_area, &content.table, view);
+
+    let filter = &view.filters[view.tab];
+    let footer = if view.editing_filter {
+        format!("Filter: {}_  (Enter: apply, Esc: clear)", filter)
+    } else if !filter.is_empty() {
+        format!("Filter: {}  |  {}", filter, HELP)
+    } else {
+        HELP.to_string()
+    };
+    frame.render_widget(Paragraph::new(footer), footer_area);
+}
+
+fn draw_histories(frame: &mut Frame, area: Rect, histories: &[History]) {
+    let constraints = vec![Constraint::Ratio(1, histories.len() as u32); histories.len()];
+    let areas = Layout::horizontal(constraints).split(area);
+    for (history, area) in histories.iter().zip(areas.iter()) {
+        let data = history.data(area.width.saturating_sub(2));
+        let sparkline = Sparkline::default()
+            .data(&data)
+            .block(Block::bordered().title(history.title()));
+        frame.render_widget(sparkline, *area);
+    }
+}
+
+fn draw_table(frame: &mut Frame, area: Rect, table: &DataTable, view: &mut View) {
+    let filter = view.filters[view.tab].to_lowercase();
+    let mut rows: Vec<&Vec<String>> = table
+        .rows
+        .iter()
+        .filter(|row| {
+            filter.is_empty() || row.iter().any(|cell| cell.to_lowercase().contains(&filter))
+        })
+        .collect();
+    let sort = view.sorts[view.tab];
+    if let Some((column, descending)) = sort {
+        rows.sort_by(|a, b| {
+            let order = compare_cells(&a[column], &b[column]);
+            if descending {
+                order.reverse()
+            } else {
+                order
+            }
+        });
+    }
+
+    // The names are aligned to the left, the other columns to the right.
+    let line = |i: usize, text: String| {
+        if i == 0 {
+            Line::from(text)
+        } else {
+            Line::from(text).right_aligned()
+        }
+    };
+    let header = table.header.iter().enumerate().map(|(i, name)| {
+        let text = match sort {
+            Some((column, descending)) if column == i => {
+                format!("{} {}", name, if descending { "v" } else { "^" })
+            }
+            _ => name.to_string(),
+        };
+        Cell::from(line(i, text))
+    });
+    let widths: Vec<Constraint> = (0..table.header.len())
+        .map(|i| {
+            let width = rows
+                .iter()
+                .map(|row| row[i].len())
+                .chain([table.header[i].len() + 2])
+                .max()
+                .unwrap_or(0);
+            Constraint::Length(width as u16)
+        })
+        .collect();
+    let title = if table.rows.is_empty() {
+        " No data ".to_string()
+    } else {
+        format!(" {} of {} rows ", rows.len(), table.rows.len())
+    };
+    let rows = rows.into_iter().map(|row| {
+        Row::new(
+            row.iter()
+                .enumerate()
+                .map(|(i, cell)| Cell::from(line(i, cell.clone()))),
+        )
+    });
+    let table = Table::new(rows, widths)
+        .header(Row::new(header).style(Style::new().add_modifier(Modifier::BOLD)))
+        .column_spacing(2)
+        .row_highlight_style(Style::new().add_modifier(Modifier::REVERSED))
+        .block(Block::bordered().title(title));
+    frame.render_stateful_widget(table, area, &mut view.table_states[view.tab]);
+}
+
+/// Draw state on terminal until the sink is closed or the UI quits.
+fn run_ui(mut terminal: DefaultTerminal, state: &Mutex<TuiState>) -> io::Result<()> {
+    let mut view = View::default();
+    loop {
+        let columns = {
+            let state = state.lock().expect("TUI state should not be poisoned!");
+            if state.closed {
+                return Ok(());
+            }
+            terminal.draw(|frame| draw(frame, &state, &mut view))?;
+            state.tabs[view.tab].table.header.len()
+        };
+        if event::poll(REFRESH_INTERVAL)? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press && !view.handle_key(key, columns) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+/// Shows the metrics in an interactive terminal UI drawn from a background thread, with
+/// tabs for the throughput, the breakdown of ExecutionStage, the opcodes, the cache and
+/// the memory. Nothing else should print to the terminal while it is shown, e.g. a
+/// [TerminalSink](super::TerminalSink).
+///
+/// "q" closes the UI and gives the terminal back, the events are still received then.
+#[derive(Debug)]
+pub struct TuiSink {
+    state: Arc<Mutex<TuiState>>,
+    ui: Option<JoinHandle<()>>,
+
+    #[cfg(feature = "enable_tps_gas_record")]
+    tps_gas_displayer: TpsAndGasDisplayer,
+}
+
+impl TuiSink {
+    /// Take over the terminal and draw the metrics on it from a background thread, until
+    /// the sink is shut down or the UI quits.
+    pub fn start() -> io::Result<Self> {
+        let terminal = ratatui::try_init()?;
+        let state = Arc::new(Mutex::new(TuiState::new()));
+        let ui_state = state.clone();
+        let ui = thread::Builder::new()
+            .name("perf-metrics-tui".to_string())
+            .spawn(move || {
+                let result = run_ui(terminal, &ui_state);
+                ratatui::restore();
+                if let Err(err) = result {
+                    eprintln!("Failed to draw the terminal UI: {:?}", err);
+                }
+            });
+        let ui = match ui {
+            Ok(ui) => ui,
+            Err(err) => {
+                ratatui::restore();
+                return Err(err);
+            }
+        };
+        Ok(TuiSink {
+            state,
+            ui: Some(ui),
+
+            #[cfg(feature = "enable_tps_gas_record")]
+            tps_gas_displayer: TpsAndGasDisplayer::default(),
+        })
+    }
+
+    /// Update the tabs with event, replay_timestamp_ms is the time (ms) it was dumped if
+    /// it is replayed.
+    #[cfg_attr(not(feature = "enable_tps_gas_record"), allow(unused_variables))]
+    fn update_event(&mut self, replay_timestamp_ms: Option<u64>, event: &MetricEvent) {
+        let mut state = self
+            .state
+            .lock()
+            .expect("TUI state should not be poisoned!");
+        state.block_number = state.block_number.max(event.block_number());
+
+        match event {
+            #[cfg(feature = "enable_tps_gas_record")]
+            MetricEvent::BlockTpsAndGas {
+                block_number,
+                record,
+            } => {
+                let displayer = &mut self.tps_gas_displayer;
+                if displayer
+                    .update(*block_number, record, replay_timestamp_ms)
+                    .is_none()
+                {
+                    return;
+                }
+                let tab = state.tab(Tab::Throughput);
+                if let Some((tps, mgas_ps)) = displayer.interval_rates() {
+                    tab.histories[0].push(tps);
+                    tab.histories[1].push(mgas_ps);
+                }
+                tab.table.rows = displayer
+                    .rates()
+                    .iter()
+                    .map(|(name, rates)| {
+                        let (tps, mgas_ps) = rates.unwrap_or((f64::NAN, f64::NAN));
+                        vec![name.to_string(), number(tps, 2), number(mgas_ps, 2)]
+                    })
+                    .collect();
+            }
+            #[cfg(feature = "enable_execution_duration_record")]
+            MetricEvent::ExecutionStageTime { record, delta, .. } => {
+                let tab = state.tab(Tab::Stages);
+                // The first item is the total.
+                let total = |items: &[(String, u64)]| items.first().map_or(0, |item| item.1);
+                tab.histories[0].push(cycles_as_secs(total(&delta.items())));
+                let items = record.items();
+                let total = total(&items) as f64;
+                tab.table.rows = items
+                    .into_iter()
+                    .map(|(label, cycles)| {
+                        vec![
+                            label,
+                            number(cycles_as_secs(cycles), 3),
+                            number(cycles as f64 / total * 100.0, 2),
+                        ]
+                    })
+                    .collect();
+            }
+            #[cfg(feature = "enable_opcode_metrics")]
+            MetricEvent::OpcodeInfo { record, delta, .. } => {
+                let tab = state.tab(Tab::Opcodes);
+                tab.histories[0].push(OpcodeStats::from(&**delta).overall.avg_cost);
+                let stats = OpcodeStats::from(&**record);
+                tab.table.rows = (0..OPCODE_NUMBER)
+                    .filter_map(|i| {
+                        let name = OpCode::new(i as u8)?;
+                        let stat = stats.opcode[i].as_ref().filter(|stat| stat.count > 0)?;
+                        Some(vec![
+                            name.as_str().to_string(),
+                            stat.count.to_string(),
+                            number(stat.count_pct * 100.0, 3),
+                            number(cycles_as_secs(stat.time), 2),
+                            number(stat.time_pct * 100.0, 3),
+                            number(stat.avg_cost, 1),
+                            number(stat.mgas, 2),
+                            number(stat.mgas_pct * 100.0, 2),
+                            stat.cat.unwrap_or("").to_string(),
+                        ])
+                    })
+                    .collect();
+            }
+            #[cfg(feature = "enable_cache_record")]
+            MetricEvent::CacheDbInfo {
+                block_number,
+                size,
+                record,
+                delta,
+            } => {
+                let tab = state.tab(Tab::Cache);
+                tab.histories[0].push(CacheStats::from(delta).total.miss_ratio * 100.0);
+                let stats = CacheStats::from(record);
+                let functions = Function::ALL
+                    .iter()
+                    .map(|function| (function.name(), &stats.functions[*function as usize]))
+                    .chain([("total", &stats.total)]);
+                tab.table.rows = functions
+                    .map(|(name, stat)| {
+                        vec![
+                            name.to_string(),
+                            stat.hits.to_string(),
+                            stat.misses.to_string(),
+                            number(stat.miss_ratio * 100.0, 3),
+                            number(stat.penalty, 3),
+                            number(stat.avg_penalty, 3),
+                            number(stat.avg_hit, 3),
+                            number(stat.avg_delta, 3),
+                        ]
+                    })
+                    .collect();
+
+                state.peak_state_size = state.peak_state_size.max(*size);
+                let peak_state_size = state.peak_state_size;
+                let tab = state.tab(Tab::Memory);
+                tab.histories[0].push(*size as f64);
+                tab.table.rows = vec![
+                    vec!["State size".to_string(), size.to_string()],
+                    vec!["Peak state size".to_string(), peak_state_size.to_string()],
+                    vec!["Reported at block".to_string(), block_number.to_string()],
+                ];
+            }
+            _ => {}
+        }
+    }
+
+    /// Stop the UI and give the terminal back.
+    fn close(&mut self) {
+        if let Some(ui) = self.ui.take() {
+            // The state is only poisoned if the UI thread panicked, which has stopped.
+            if let Ok(mut state) = self.state.lock() {
+                state.closed = true;
+            }
+            let _ = ui.join();
+        }
+    }
+}
+
+#[cfg(feature = "enable_tps_gas_record")]
+impl_tps_report_interval!(TuiSink);
+
+impl MetricSink for TuiSink {
+    fn on_event(&mut self, event: &MetricEvent) {
+        self.update_event(None, event);
+    }
+
+    fn on_replayed_event(&mut self, timestamp_ms: u64, event: &MetricEvent) {
+        self.update_event(Some(timestamp_ms), event);
+    }
+
+    fn shutdown(&mut self) {
+        self.close();
+    }
+}
+
+impl Drop for TuiSink {
+    /// The terminal is given back even if the sink is not shut down, e.g. on panic.
+    fn drop(&mut self) {
+        self.close();
+    }
+}
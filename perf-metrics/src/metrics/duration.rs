@@ -1,13 +1,22 @@
 //! This module is used to support recording the overhead of various parts
 //! of the execute_inner function in execution stage.
-use revm_utils::{metrics::types::TransactTime, time_utils::instant::Instant};
+use revm_utils::{
+    metrics::{
+        health::{add, cycles_between},
+        types::{TransactTime, TransactTimeBreakdown},
+    },
+    time_utils::instant::Instant,
+};
+use serde::{Deserialize, Serialize};
 
 /// This structure is used to record all overhead information.
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
 pub struct ExecutionDurationRecord {
     // Total time recorder.
+    #[serde(skip)]
     pub(crate) total_recorder: Instant,
     // General time recorder.
+    #[serde(skip)]
     pub(crate) time_recorder: Instant,
     // Time of execute inner.
     pub(crate) total: u64,
@@ -32,15 +41,49 @@ impl ExecutionDurationRecord {
         block_with_senders,
         time_recorder
     );
+
+    /// Update the recorded overhead with the other's.
+    pub(crate) fn update(&mut self, other: &Self) {
+        self.total = add(self.total, other.total);
+        self.block_td = add(self.block_td, other.block_td);
+        self.block_with_senders = add(self.block_with_senders, other.block_with_senders);
+        self.execution.update(&other.execution);
+        self.write_to_db.update(&other.write_to_db);
+    }
+
+    /// Returns the overhead recorded so far and resets it. The time recorders are kept, so
+    /// that a measurement in progress is not affected.
+    pub(crate) fn take(&mut self) -> Self {
+        let record = *self;
+        *self = ExecutionDurationRecord {
+            total_recorder: self.total_recorder,
+            time_recorder: self.time_recorder,
+            execution: ExecuteTxsRecord {
+                start_record: self.execution.start_record,
+                sub_record: self.execution.sub_record,
+                ..Default::default()
+            },
+            write_to_db: WriteToDbRecord {
+                start_record: self.write_to_db.start_record,
+                sub_record: self.write_to_db.sub_record,
+                write_start_record: self.write_to_db.write_start_record,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        record
+    }
 }
 
 /// This structure is used to support in-depth measurement of function execute_and_verify_receipt
 /// in stage execution.
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
 pub struct ExecuteTxsRecord {
     /// Record the starting time of function execute_and_verify_receipt.
+    #[serde(skip)]
     start_record: Instant,
     /// Record the start time of each subfunction.
+    #[serde(skip)]
     sub_record: Instant,
     /// Time of execute_and_verify_receipt.
     pub(crate) total: u64,
@@ -48,6 +91,8 @@ pub struct ExecuteTxsRecord {
     pub(crate) transact: u64,
     /// Time of revm's transact.
     pub(crate) revm_transact: TransactTime,
+    /// Time of revm's transact, keyed by the type and the outcome of transactions.
+    pub(crate) revm_transact_breakdown: TransactTimeBreakdown,
     /// Time of commit changes.
     pub(crate) commit_changes: u64,
     /// Time of add receipt.
@@ -86,11 +131,32 @@ impl ExecuteTxsRecord {
     );
     define_record_with_elapsed_time_function!(transact_record_inner, transact, sub_record);
 
+    /// Update the recorded overhead with the other's.
+    pub(crate) fn update(&mut self, other: &Self) {
+        self.total = add(self.total, other.total);
+        self.transact = add(self.transact, other.transact);
+        self.revm_transact.update(&other.revm_transact);
+        self.revm_transact_breakdown
+            .update(&other.revm_transact_breakdown);
+        self.commit_changes = add(self.commit_changes, other.commit_changes);
+        self.add_receipt = add(self.add_receipt, other.add_receipt);
+        self.apply_post_execution_state_change = add(
+            self.apply_post_execution_state_change,
+            other.apply_post_execution_state_change,
+        );
+        self.merge_transactions = add(self.merge_transactions, other.merge_transactions);
+        self.verify_receipt = add(self.verify_receipt, other.verify_receipt);
+        self.save_receipts = add(self.save_receipts, other.save_receipts);
+    }
+
     /// Add time of transact, which include revm's transact.
     pub(super) fn transact_record(&mut self) {
         self.transact_record_inner();
         let revm_transact = revm_utils::metrics::get_transact_time();
         self.revm_transact.update(&revm_transact);
+        let revm_transact_breakdown = revm_utils::metrics::get_transact_breakdown();
+        self.revm_transact_breakdown
+            .update(&revm_transact_breakdown);
     }
 
     /// Add time of save_receipts.
@@ -101,20 +167,23 @@ impl ExecuteTxsRecord {
 
     /// Record total time.
     fn record_total_time(&mut self, now: Instant) {
-        let cycles = now.checked_cycles_since(self.start_record).unwrap_or(0);
-        self.total = self.total.checked_add(cycles).expect("overflow");
+        let cycles = cycles_between(self.start_record, now);
+        self.total = add(self.total, cycles);
     }
 }
 
 /// This structure is used to record all the metrics of write_to_db, including
 /// the time spent writing and the amount of data written.
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
 pub struct WriteToDbRecord {
     /// Record the starting time of function write_to_db.
+    #[serde(skip)]
     start_record: Instant,
     /// Record the start time of each subfunction.
+    #[serde(skip)]
     sub_record: Instant,
     /// Record the start time of each put or upsert.
+    #[serde(skip)]
     write_start_record: Instant,
 
     /// Time of write_to_db.
@@ -239,6 +308,37 @@ impl WriteToDbRecord {
         write_start_record
     );
 
+    /// Update the recorded overhead and data size with the other's.
+    fn update(&mut self, other: &Self) {
+        macro_rules! add_fields {
+            ($($field:ident),*) => {
+                $(self.$field = add(self.$field, other.$field);)*
+            };
+        }
+        add_fields!(
+            total,
+            revert_storage_time,
+            revert_storage_size,
+            revert_storage_append_time,
+            revert_account_time,
+            revert_account_size,
+            revert_account_append_time,
+            write_receipts_time,
+            write_receipts_size,
+            receipts_append_time,
+            sort_time,
+            state_account_time,
+            state_account_size,
+            state_account_upsert_time,
+            state_bytecode_time,
+            state_bytecode_size,
+            state_bytecode_upsert_time,
+            state_storage_time,
+            state_storage_size,
+            state_storage_upsert_time
+        );
+    }
+
     /// Record time of write storage in StateChanges.
     pub(super) fn record_state_storage_time(&mut self) {
         let now = self.record_state_storage_time_inner();
@@ -246,7 +346,7 @@ impl WriteToDbRecord {
     }
     /// Record total time.
     fn record_total_time(&mut self, now: Instant) {
-        let cycles = now.checked_cycles_since(self.start_record).unwrap_or(0);
-        self.total = self.total.checked_add(cycles).expect("overflow");
+        let cycles = cycles_between(self.start_record, now);
+        self.total = add(self.total, cycles);
     }
 }
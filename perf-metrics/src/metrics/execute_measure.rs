@@ -6,32 +6,47 @@ pub mod execute_inner {
     use crate::metrics::metric::*;
     pub fn start_record() {
         #[cfg(feature = "enable_execution_duration_record")]
-        recorder().duration_record.start_total_record();
+        if is_enabled(MetricFamily::Duration) {
+            recorder().duration_record.start_total_record();
+        }
+    }
+
+    /// Enable the tracking of the hot keys of cachedb. At most capacity keys are tracked
+    /// for accesses and misses respectively, and a report is sent every report_interval
+    /// blocks. A capacity or report_interval of 0 disables the tracking.
+    #[cfg(feature = "enable_cache_record")]
+    pub fn enable_hot_key_tracking(capacity: usize, report_interval: u64) {
+        let capacity = if report_interval == 0 { 0 } else { capacity };
+        revm_utils::metrics::enable_hot_key_tracking(capacity);
+        recorder()
+            .hot_keys
+            .set_interval(if capacity == 0 { 0 } else { report_interval });
     }
 
     pub fn record_before_loop() {
         #[cfg(feature = "enable_tps_gas_record")]
-        let _ =
-            recorder()
-                .events_tx
-                .as_mut()
-                .expect("No sender")
-                .send(MetricEvent::BlockTpsAndGas {
+        if is_enabled(MetricFamily::TpsGas) {
+            let _ = recorder().events_tx.as_mut().expect("No sender").send(
+                MetricEvent::BlockTpsAndGas {
                     block_number: recorder().block_number,
                     record: TpsAndGasMessage::Switch(true),
-                });
+                },
+            );
+        }
     }
 
     pub fn record_before_td(block_number: u64) {
         #[cfg(feature = "enable_execution_duration_record")]
-        recorder().duration_record.start_time_record();
+        if is_enabled(MetricFamily::Duration) {
+            recorder().duration_record.start_time_record();
+        }
 
         recorder().block_number = block_number;
     }
 
     pub fn record_after_td() {
         #[cfg(feature = "enable_execution_duration_record")]
-        {
+        if is_enabled(MetricFamily::Duration) {
             recorder().duration_record.add_block_td_duration();
             recorder().duration_record.start_time_record();
         }
@@ -39,7 +54,7 @@ pub mod execute_inner {
 
     pub fn record_after_block_with_senders() {
         #[cfg(feature = "enable_execution_duration_record")]
-        {
+        if is_enabled(MetricFamily::Duration) {
             recorder().duration_record.add_block_with_senders_duration();
             recorder().duration_record.start_time_record();
         }
@@ -47,7 +62,7 @@ pub mod execute_inner {
 
     pub fn record_after_get_tps(_block_number: u64, _txs: u64, _gas: u64) {
         #[cfg(feature = "enable_tps_gas_record")]
-        {
+        if is_enabled(MetricFamily::TpsGas) {
             recorder()
                 .tps_gas_record
                 .record(_block_number, _txs as u128, _gas as u128);
@@ -62,34 +77,38 @@ pub mod execute_inner {
 
     pub fn record_after_take_output_state() {
         #[cfg(feature = "enable_tps_gas_record")]
-        let _ =
-            recorder()
-                .events_tx
-                .as_mut()
-                .expect("No sender")
-                .send(MetricEvent::BlockTpsAndGas {
+        if is_enabled(MetricFamily::TpsGas) {
+            let _ = recorder().events_tx.as_mut().expect("No sender").send(
+                MetricEvent::BlockTpsAndGas {
                     block_number: recorder().block_number,
                     record: TpsAndGasMessage::Switch(false),
-                });
+                },
+            );
+        }
 
         #[cfg(feature = "enable_execution_duration_record")]
-        recorder().duration_record.start_time_record();
+        if is_enabled(MetricFamily::Duration) {
+            recorder().duration_record.start_time_record();
+        }
     }
 
     pub fn record_at_end(_cachedb_size: usize) {
         #[cfg(feature = "enable_execution_duration_record")]
-        {
+        if is_enabled(MetricFamily::Duration) {
             recorder().duration_record.add_total_duration();
+            let delta = recorder().duration_record.take();
+            recorder().duration_total.update(&delta);
             let _ = recorder().events_tx.as_mut().expect("No sender").send(
                 MetricEvent::ExecutionStageTime {
                     block_number: recorder().block_number,
-                    record: recorder().duration_record,
+                    record: recorder().duration_total,
+                    delta,
                 },
             );
         }
 
         #[cfg(feature = "enable_cache_record")]
-        {
+        if is_enabled(MetricFamily::Cache) {
             let cachedb_record = revm_utils::metrics::get_cache_record();
             recorder().cachedb_record.update(&cachedb_record);
             let _ =
@@ -100,32 +119,136 @@ pub mod execute_inner {
                     .send(MetricEvent::CacheDbInfo {
                         block_number: recorder().block_number,
                         size: _cachedb_size,
-                        record: recorder().cachedb_record,
+                        record: recorder().cachedb_record.clone(),
+                        delta: cachedb_record,
                     });
+
+            if recorder().hot_keys.is_enabled() {
+                let hot_key_record = revm_utils::metrics::get_hot_key_record();
+                let block_number = recorder().block_number;
+                if let Some(report) = recorder().hot_keys.update(block_number, &hot_key_record) {
+                    let _ = recorder().events_tx.as_mut().expect("No sender").send(
+                        MetricEvent::CacheHotKeys {
+                            block_number,
+                            record: report,
+                        },
+                    );
+                }
+            }
         }
 
         #[cfg(feature = "enable_opcode_metrics")]
-        let _ = recorder()
-            .events_tx
-            .as_mut()
-            .expect("No sender")
-            .send(MetricEvent::OpcodeInfo {
-                block_number: recorder().block_number,
-                record: recorder().op_record,
-            });
+        if is_enabled(MetricFamily::Opcode) {
+            let _ =
+                recorder()
+                    .events_tx
+                    .as_mut()
+                    .expect("No sender")
+                    .send(MetricEvent::OpcodeInfo {
+                        block_number: recorder().block_number,
+                        record: Box::new(recorder().op_record.clone()),
+                        delta: Box::new(std::mem::take(&mut recorder().op_delta)),
+                    });
+        }
+
+        #[cfg(any(
+            feature = "enable_opcode_metrics",
+            feature = "enable_cache_record",
+            feature = "enable_execution_duration_record",
+            feature = "enable_tps_gas_record",
+        ))]
+        let _ =
+            recorder()
+                .events_tx
+                .as_mut()
+                .expect("No sender")
+                .send(MetricEvent::MetricsHealth {
+                    block_number: recorder().block_number,
+                    health: revm_utils::metrics::health::metrics_health(),
+                });
     }
 }
 
 // The functions in this module should be called in executor.
 #[cfg(feature = "enable_opcode_metrics")]
 pub mod revm_measure {
+    use crate::metrics::metric::*;
+
     /// After each transaction is executed, the execution status of instructions is counted and
     /// then updated to the global metric recorder. This function will be called in
     /// executor.
     pub fn record_opcode() {
+        record_opcode_inner(None);
+    }
+
+    /// The same as record_opcode, but the opcode record kept in the transaction history
+    /// can also be found by tx_hash.
+    pub fn record_opcode_with_tx_hash(tx_hash: [u8; 32]) {
+        record_opcode_inner(Some(tx_hash));
+    }
+
+    fn record_opcode_inner(tx_hash: Option<[u8; 32]>) {
+        if !is_enabled(MetricFamily::Opcode) {
+            return;
+        }
         let mut op_record = revm_utils::metrics::get_op_record();
+        let block_number = recorder().block_number;
+        recorder()
+            .op_history
+            .push(block_number, tx_hash, &op_record);
         if op_record.not_empty() {
-            crate::recorder().op_record.update(&mut op_record);
+            recorder().op_delta.merge(&op_record);
+            recorder().op_record.update(&mut op_record);
+        }
+    }
+
+    /// Set which transactions are fully instrumented by the opcode metrics, the opcodes of
+    /// the other transactions are only counted and the dashboard reports scaled estimates.
+    pub fn set_sampling_mode(mode: revm_utils::metrics::SamplingMode) {
+        revm_utils::metrics::set_sampling_mode(mode);
+    }
+
+    /// Set the number of transactions whose opcode records are kept in the history. The
+    /// history is off (0) by default, since each kept transaction clones its opcode record.
+    pub fn set_tx_opcode_history_capacity(capacity: usize) {
+        recorder().op_history.set_capacity(capacity);
+    }
+
+    /// Returns the opcode records kept in the history, ordered from the oldest to the newest.
+    pub fn get_tx_opcode_records() -> Vec<TxOpcodeRecord> {
+        recorder().op_history.records().cloned().collect()
+    }
+
+    /// Returns the opcode record of the transaction at tx_index in block_number, if it is
+    /// still kept in the history.
+    pub fn get_tx_opcode_record(block_number: u64, tx_index: u64) -> Option<TxOpcodeRecord> {
+        recorder().op_history.find(block_number, tx_index).cloned()
+    }
+
+    /// Returns the opcode record of the transaction with tx_hash, if it is still kept in
+    /// the history.
+    pub fn get_tx_opcode_record_by_hash(tx_hash: &[u8; 32]) -> Option<TxOpcodeRecord> {
+        recorder().op_history.find_by_hash(tx_hash).cloned()
+    }
+
+    /// Returns the n transactions in the history which spend the most time executing opcodes.
+    pub fn get_most_expensive_tx_opcode_records(n: usize) -> Vec<TxOpcodeRecord> {
+        recorder().op_history.most_expensive(n)
+    }
+
+    /// Send the opcode records of the n most expensive transactions in the history to the
+    /// dashboard for display.
+    pub fn dump_tx_opcode_records(n: usize) {
+        for record in recorder().op_history.most_expensive(n) {
+            let _ =
+                recorder()
+                    .events_tx
+                    .as_mut()
+                    .expect("No sender")
+                    .send(MetricEvent::TxOpcodeInfo {
+                        block_number: recorder().block_number,
+                        record: Box::new(record),
+                    });
         }
     }
 }
@@ -134,34 +257,70 @@ pub mod revm_measure {
 #[cfg(feature = "enable_execution_duration_record")]
 pub mod execute_txs {
     use crate::metrics::metric::*;
+    use revm_utils::metrics::types::{TxOutcome, TxType};
 
     /// start execute_tx record.
     pub fn start_execute_tx_record() {
+        if !is_enabled(MetricFamily::Duration) {
+            return;
+        }
         recorder().duration_record.execution.start_record();
     }
 
     /// start execute_tx sub record.
     pub fn start_execute_tx_sub_record() {
+        if !is_enabled(MetricFamily::Duration) {
+            return;
+        }
         recorder().duration_record.execution.start_sub_record();
     }
 
     /// transact record
     pub fn transact_record() {
+        if !is_enabled(MetricFamily::Duration) {
+            return;
+        }
+        recorder().duration_record.execution.transact_record();
+    }
+
+    /// transact record, with the type (the EIP-2718 type byte) and the status of the
+    /// receipt of the transaction. They break down the time of revm's transact when revm
+    /// does not record the type and the outcome itself. A failed receipt is counted as
+    /// reverted, since its status does not tell a revert from a halt.
+    pub fn transact_record_with_receipt(tx_type: u8, success: bool) {
+        if !is_enabled(MetricFamily::Duration) {
+            return;
+        }
+        let outcome = if success {
+            TxOutcome::Success
+        } else {
+            TxOutcome::Revert
+        };
+        revm_utils::metrics::transact_receipt_record(TxType::from_type_byte(tx_type), outcome);
         recorder().duration_record.execution.transact_record();
     }
 
     /// commit_changes_record
     pub fn commit_changes_record() {
+        if !is_enabled(MetricFamily::Duration) {
+            return;
+        }
         recorder().duration_record.execution.commit_changes_record();
     }
 
     /// add_receipt_record
     pub fn add_receipt_record() {
+        if !is_enabled(MetricFamily::Duration) {
+            return;
+        }
         recorder().duration_record.execution.add_receipt_record();
     }
 
     /// apply_post_execution_state_change_record
     pub fn apply_post_execution_state_change_record() {
+        if !is_enabled(MetricFamily::Duration) {
+            return;
+        }
         recorder()
             .duration_record
             .execution
@@ -170,6 +329,9 @@ pub mod execute_txs {
 
     /// merge_transactions_record
     pub fn merge_transactions_record() {
+        if !is_enabled(MetricFamily::Duration) {
+            return;
+        }
         recorder()
             .duration_record
             .execution
@@ -178,17 +340,25 @@ pub mod execute_txs {
 
     /// verify_receipt_record
     pub fn verify_receipt_record() {
+        if !is_enabled(MetricFamily::Duration) {
+            return;
+        }
         recorder().duration_record.execution.verify_receipt_record();
     }
 
     /// save_receipts_record
     pub fn save_receipts_record() {
+        if !is_enabled(MetricFamily::Duration) {
+            return;
+        }
         recorder().duration_record.execution.save_receipts_record();
     }
 
     /// get_execute_tx_record
     pub fn get_execute_tx_record() -> crate::metrics::ExecuteTxsRecord {
-        recorder().duration_record.execution
+        let mut record = recorder().duration_total.execution;
+        record.update(&recorder().duration_record.execution);
+        record
     }
 
     /// Record for verfity_and_save_receipts
@@ -217,21 +387,33 @@ pub mod write_to_db {
 
     /// start write_to_db's record.
     pub fn start_write_to_db_record() {
+        if !is_enabled(MetricFamily::Duration) {
+            return;
+        }
         recorder().duration_record.write_to_db.start_record();
     }
 
     /// start write_to_db's sub record.
     pub fn start_write_to_db_sub_record() {
+        if !is_enabled(MetricFamily::Duration) {
+            return;
+        }
         recorder().duration_record.write_to_db.start_sub_record();
     }
 
     /// start write_to_db's write record.
     fn start_write_record() {
+        if !is_enabled(MetricFamily::Duration) {
+            return;
+        }
         recorder().duration_record.write_to_db.start_write_record();
     }
 
     /// Record data size of write storage changes in StateReverts's write_to_db.
     fn record_revert_storage_size(size: usize) {
+        if !is_enabled(MetricFamily::Duration) {
+            return;
+        }
         recorder()
             .duration_record
             .write_to_db
@@ -240,6 +422,9 @@ pub mod write_to_db {
 
     /// Record time of write storage append time in StateReverts's write_to_db.
     fn record_revert_storage_append_time() {
+        if !is_enabled(MetricFamily::Duration) {
+            return;
+        }
         recorder()
             .duration_record
             .write_to_db
@@ -256,6 +441,9 @@ pub mod write_to_db {
 
     /// Record time of write storage changes in StateReverts's write_to_db.
     pub fn record_revert_storage_time() {
+        if !is_enabled(MetricFamily::Duration) {
+            return;
+        }
         recorder()
             .duration_record
             .write_to_db
@@ -264,6 +452,9 @@ pub mod write_to_db {
 
     /// Record data size of write account changes in StateReverts's write_to_db.
     fn record_revert_account_size(size: usize) {
+        if !is_enabled(MetricFamily::Duration) {
+            return;
+        }
         recorder()
             .duration_record
             .write_to_db
@@ -272,6 +463,9 @@ pub mod write_to_db {
 
     /// Record time of write account append time in StateReverts's write_to_db.
     fn record_revert_account_append_time() {
+        if !is_enabled(MetricFamily::Duration) {
+            return;
+        }
         recorder()
             .duration_record
             .write_to_db
@@ -288,6 +482,9 @@ pub mod write_to_db {
 
     /// Record time of write account changes in StateReverts's write_to_db.
     pub fn record_revert_account_time() {
+        if !is_enabled(MetricFamily::Duration) {
+            return;
+        }
         recorder()
             .duration_record
             .write_to_db
@@ -296,6 +493,9 @@ pub mod write_to_db {
 
     /// Record data size of write receipts in BundleStateWithReceipts's write_to_db.
     fn record_write_receipts_size(size: usize) {
+        if !is_enabled(MetricFamily::Duration) {
+            return;
+        }
         recorder()
             .duration_record
             .write_to_db
@@ -304,6 +504,9 @@ pub mod write_to_db {
 
     /// Record time of write receipts append in BundleStateWithReceipts's write_to_db.
     fn record_receipts_append_time() {
+        if !is_enabled(MetricFamily::Duration) {
+            return;
+        }
         recorder()
             .duration_record
             .write_to_db
@@ -320,6 +523,9 @@ pub mod write_to_db {
 
     /// Record time of write receipts  in BundleStateWithReceipts's write_to_db.
     pub fn record_write_receipts_time() {
+        if !is_enabled(MetricFamily::Duration) {
+            return;
+        }
         recorder()
             .duration_record
             .write_to_db
@@ -328,11 +534,17 @@ pub mod write_to_db {
 
     /// Record time of sort in StateChanges's write_to_db.
     pub fn record_sort_time() {
+        if !is_enabled(MetricFamily::Duration) {
+            return;
+        }
         recorder().duration_record.write_to_db.record_sort_time();
     }
 
     /// Record data size of write account in StateChanges's write_to_db.
     fn record_state_account_size(size: usize) {
+        if !is_enabled(MetricFamily::Duration) {
+            return;
+        }
         recorder()
             .duration_record
             .write_to_db
@@ -341,6 +553,9 @@ pub mod write_to_db {
 
     /// Record time of write account upsert in StateChanges's write_to_db.
     fn record_state_account_upsert_time() {
+        if !is_enabled(MetricFamily::Duration) {
+            return;
+        }
         recorder()
             .duration_record
             .write_to_db
@@ -357,6 +572,9 @@ pub mod write_to_db {
 
     /// Record time of write account in StateChanges's write_to_db.
     pub fn record_state_account_time() {
+        if !is_enabled(MetricFamily::Duration) {
+            return;
+        }
         recorder()
             .duration_record
             .write_to_db
@@ -365,6 +583,9 @@ pub mod write_to_db {
 
     /// Record data size of write bytecode in StateChanges's write_to_db.
     fn record_state_bytecode_size(size: usize) {
+        if !is_enabled(MetricFamily::Duration) {
+            return;
+        }
         recorder()
             .duration_record
             .write_to_db
@@ -373,6 +594,9 @@ pub mod write_to_db {
 
     /// Record time of write bytecode upsert in StateChanges's write_to_db.
     fn record_state_bytecode_upsert_time() {
+        if !is_enabled(MetricFamily::Duration) {
+            return;
+        }
         recorder()
             .duration_record
             .write_to_db
@@ -389,6 +613,9 @@ pub mod write_to_db {
 
     /// Record time of write bytecode in StateChanges's write_to_db.
     pub fn record_state_bytecode_time() {
+        if !is_enabled(MetricFamily::Duration) {
+            return;
+        }
         recorder()
             .duration_record
             .write_to_db
@@ -397,6 +624,9 @@ pub mod write_to_db {
 
     /// Record data size of write storage in StateChanges's write_to_db.
     fn record_state_storage_size(size: usize) {
+        if !is_enabled(MetricFamily::Duration) {
+            return;
+        }
         recorder()
             .duration_record
             .write_to_db
@@ -405,6 +635,9 @@ pub mod write_to_db {
 
     /// Record time of write storage upsert in StateChanges's write_to_db.
     fn record_state_storage_upsert_time() {
+        if !is_enabled(MetricFamily::Duration) {
+            return;
+        }
         recorder()
             .duration_record
             .write_to_db
@@ -421,6 +654,9 @@ pub mod write_to_db {
 
     /// Record time of write storage in StateChanges's write_to_db.
     pub fn record_state_storage_time() {
+        if !is_enabled(MetricFamily::Duration) {
+            return;
+        }
         recorder()
             .duration_record
             .write_to_db
@@ -0,0 +1,56 @@
+//! This module is used to aggregate the hot keys of CacheDB over a range of blocks.
+use revm_utils::metrics::hot_keys::HotKeyRecord;
+use serde::{Deserialize, Serialize};
+
+/// The hot keys of CacheDB accessed in a range of blocks.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HotKeysReport {
+    /// The first block of the range.
+    pub from_block: u64,
+    /// The last block of the range.
+    pub to_block: u64,
+    /// The hot keys accessed in the range.
+    pub record: HotKeyRecord,
+}
+
+/// Accumulates the hot keys until the range reaches the report interval.
+#[derive(Debug, Default)]
+pub(crate) struct HotKeysWindow {
+    /// The number of blocks covered by a report, 0 means that the tracking is disabled.
+    interval: u64,
+    /// The first block of the current range.
+    from_block: Option<u64>,
+    /// The hot keys accessed in the current range.
+    record: HotKeyRecord,
+}
+
+impl HotKeysWindow {
+    pub(crate) fn set_interval(&mut self, interval: u64) {
+        self.interval = interval;
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.interval > 0
+    }
+
+    /// Add the hot keys accessed up to block_number, returns the report if the range
+    /// covers at least interval blocks.
+    pub(crate) fn update(
+        &mut self,
+        block_number: u64,
+        record: &HotKeyRecord,
+    ) -> Option<HotKeysReport> {
+        let from_block = *self.from_block.get_or_insert(block_number);
+        self.record.update(record);
+        if block_number.saturating_sub(from_block) + 1 < self.interval {
+            return None;
+        }
+
+        self.from_block = Some(block_number + 1);
+        Some(HotKeysReport {
+            from_block,
+            to_block: block_number,
+            record: std::mem::take(&mut self.record),
+        })
+    }
+}
@@ -4,10 +4,10 @@ macro_rules! define_record_with_elapsed_time_function {
         pub(super) fn $name(&mut self) -> Instant {
             // Calculate duration and reset time_counter.
             let now = Instant::now();
-            let cycles = now.checked_cycles_since(self.$time_counter).unwrap_or(0);
+            let cycles = revm_utils::metrics::health::cycles_between(self.$time_counter, now);
             self.$time_counter = now;
             // Record duration.
-            self.$field = self.$field.checked_add(cycles).expect("overflow");
+            self.$field = revm_utils::metrics::health::add(self.$field, cycles);
             now
         }
     };
@@ -18,11 +18,10 @@ macro_rules! define_record_time_function {
     ($name:ident, $field:ident, $time_counter:ident) => {
         pub(super) fn $name(&mut self) {
             // Calculate duration.
-            let cycles = Instant::now()
-                .checked_cycles_since(self.$time_counter)
-                .expect("overflow");
+            let cycles =
+                revm_utils::metrics::health::cycles_between(self.$time_counter, Instant::now());
             // Record duration.
-            self.$field = self.$field.checked_add(cycles).expect("overflow");
+            self.$field = revm_utils::metrics::health::add(self.$field, cycles);
         }
     };
 }
@@ -31,7 +30,7 @@ macro_rules! define_record_time_function {
 macro_rules! define_record_size_function {
     ($name:ident, $field:ident) => {
         pub(super) fn $name(&mut self, size: usize) {
-            self.$field = self.$field.checked_add(size).expect("overflow");
+            self.$field = revm_utils::metrics::health::add(self.$field, size);
         }
     };
 }
@@ -3,14 +3,37 @@
 // pub use super::duration::ExecuteTxsRecord;
 #[cfg(feature = "enable_execution_duration_record")]
 use super::duration::ExecutionDurationRecord;
+#[cfg(feature = "enable_cache_record")]
+pub use super::hot_keys::HotKeysReport;
+#[cfg(feature = "enable_cache_record")]
+use super::hot_keys::HotKeysWindow;
+#[cfg(feature = "enable_opcode_metrics")]
+use super::opcode_history::OpcodeHistory;
+#[cfg(feature = "enable_opcode_metrics")]
+pub use super::opcode_history::TxOpcodeRecord;
 #[cfg(feature = "enable_tps_gas_record")]
 pub use super::tps_gas::TpsAndGasMessage;
 #[cfg(feature = "enable_tps_gas_record")]
 use super::tps_gas::TpsGasRecord;
+#[cfg(any(
+    feature = "enable_opcode_metrics",
+    feature = "enable_cache_record",
+    feature = "enable_execution_duration_record",
+    feature = "enable_tps_gas_record",
+))]
+use revm_utils::metrics::health::MetricsHealth;
+#[cfg(any(
+    feature = "enable_opcode_metrics",
+    feature = "enable_cache_record",
+    feature = "enable_execution_duration_record",
+    feature = "enable_tps_gas_record",
+))]
+pub use revm_utils::metrics::switches::{is_enabled, set_enabled, MetricFamily};
 #[cfg(feature = "enable_cache_record")]
 use revm_utils::metrics::types::CacheDbRecord;
 #[cfg(feature = "enable_opcode_metrics")]
 use revm_utils::metrics::types::OpcodeRecord;
+use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc::UnboundedSender;
 
 pub use super::execute_measure::execute_inner::*;
@@ -22,8 +45,11 @@ pub use super::execute_measure::{execute_txs::*, write_to_db::*};
 /// Alias type for metric producers to use.
 pub type MetricEventsSender = UnboundedSender<MetricEvent>;
 
-/// Collection of metric events.
-#[derive(Clone, Copy, Debug)]
+/// Collection of metric events, which are serialized with the name of the variant as
+/// "kind". The opcode records take tens of KB, so they are boxed to keep the events
+/// small when they are moved around.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "kind")]
 pub enum MetricEvent {
     /// Duration record of function execute_inner.
     #[cfg(feature = "enable_execution_duration_record")]
@@ -32,6 +58,8 @@ pub enum MetricEvent {
         block_number: u64,
         /// excution duration record.
         record: ExecutionDurationRecord,
+        /// excution duration record since the previous event.
+        delta: ExecutionDurationRecord,
     },
     /// Amount of txs and gas in a block.
     #[cfg(feature = "enable_tps_gas_record")]
@@ -47,7 +75,17 @@ pub enum MetricEvent {
         /// Current block_number.
         block_number: u64,
         /// opcode record in revm.
-        record: OpcodeRecord,
+        record: Box<OpcodeRecord>,
+        /// opcode record in revm since the previous event.
+        delta: Box<OpcodeRecord>,
+    },
+    /// Opcode record of a single transaction in revm.
+    #[cfg(feature = "enable_opcode_metrics")]
+    TxOpcodeInfo {
+        /// Current block_number.
+        block_number: u64,
+        /// opcode record of the transaction.
+        record: Box<TxOpcodeRecord>,
     },
     /// CacheDB metric record.
     #[cfg(feature = "enable_cache_record")]
@@ -58,24 +96,135 @@ pub enum MetricEvent {
         size: usize,
         /// cache db record.
         record: CacheDbRecord,
+        /// cache db record since the previous event.
+        delta: CacheDbRecord,
+    },
+    /// The number of abnormal events absorbed by the overflow policy of metrics.
+    #[cfg(any(
+        feature = "enable_opcode_metrics",
+        feature = "enable_cache_record",
+        feature = "enable_execution_duration_record",
+        feature = "enable_tps_gas_record",
+    ))]
+    MetricsHealth {
+        /// Current block_number.
+        block_number: u64,
+        /// health of metrics.
+        health: MetricsHealth,
     },
+    /// The most accessed and the most frequently missed keys of CacheDB.
+    #[cfg(feature = "enable_cache_record")]
+    CacheHotKeys {
+        /// Current block_number.
+        block_number: u64,
+        /// hot keys of the block range.
+        record: HotKeysReport,
+    },
+}
+
+impl MetricEvent {
+    /// The names of the variants enabled by the features, see kind.
+    pub const KINDS: &'static [&'static str] = &[
+        #[cfg(feature = "enable_execution_duration_record")]
+        "ExecutionStageTime",
+        #[cfg(feature = "enable_tps_gas_record")]
+        "BlockTpsAndGas",
+        #[cfg(feature = "enable_opcode_metrics")]
+        "OpcodeInfo",
+        #[cfg(feature = "enable_opcode_metrics")]
+        "TxOpcodeInfo",
+        #[cfg(feature = "enable_cache_record")]
+        "CacheDbInfo",
+        #[cfg(any(
+            feature = "enable_opcode_metrics",
+            feature = "enable_cache_record",
+            feature = "enable_execution_duration_record",
+            feature = "enable_tps_gas_record",
+        ))]
+        "MetricsHealth",
+        #[cfg(feature = "enable_cache_record")]
+        "CacheHotKeys",
+    ];
+
+    /// Returns the name of the variant, which is the "kind" of the serialized event.
+    pub fn kind(&self) -> &'static str {
+        match *self {
+            #[cfg(feature = "enable_execution_duration_record")]
+            MetricEvent::ExecutionStageTime { .. } => "ExecutionStageTime",
+            #[cfg(feature = "enable_tps_gas_record")]
+            MetricEvent::BlockTpsAndGas { .. } => "BlockTpsAndGas",
+            #[cfg(feature = "enable_opcode_metrics")]
+            MetricEvent::OpcodeInfo { .. } => "OpcodeInfo",
+            #[cfg(feature = "enable_opcode_metrics")]
+            MetricEvent::TxOpcodeInfo { .. } => "TxOpcodeInfo",
+            #[cfg(feature = "enable_cache_record")]
+            MetricEvent::CacheDbInfo { .. } => "CacheDbInfo",
+            #[cfg(any(
+                feature = "enable_opcode_metrics",
+                feature = "enable_cache_record",
+                feature = "enable_execution_duration_record",
+                feature = "enable_tps_gas_record",
+            ))]
+            MetricEvent::MetricsHealth { .. } => "MetricsHealth",
+            #[cfg(feature = "enable_cache_record")]
+            MetricEvent::CacheHotKeys { .. } => "CacheHotKeys",
+        }
+    }
+
+    /// Returns the block number of the event.
+    pub fn block_number(&self) -> u64 {
+        match *self {
+            #[cfg(feature = "enable_execution_duration_record")]
+            MetricEvent::ExecutionStageTime { block_number, .. } => block_number,
+            #[cfg(feature = "enable_tps_gas_record")]
+            MetricEvent::BlockTpsAndGas { block_number, .. } => block_number,
+            #[cfg(feature = "enable_opcode_metrics")]
+            MetricEvent::OpcodeInfo { block_number, .. } => block_number,
+            #[cfg(feature = "enable_opcode_metrics")]
+            MetricEvent::TxOpcodeInfo { block_number, .. } => block_number,
+            #[cfg(feature = "enable_cache_record")]
+            MetricEvent::CacheDbInfo { block_number, .. } => block_number,
+            #[cfg(any(
+                feature = "enable_opcode_metrics",
+                feature = "enable_cache_record",
+                feature = "enable_execution_duration_record",
+                feature = "enable_tps_gas_record",
+            ))]
+            MetricEvent::MetricsHealth { block_number, .. } => block_number,
+            #[cfg(feature = "enable_cache_record")]
+            MetricEvent::CacheHotKeys { block_number, .. } => block_number,
+        }
+    }
 }
 
 /// This structure is used to facilitate all metric operations in reth's performance test.
 #[derive(Default)]
 pub struct PerfMetric {
-    /// Record the time consumption of each function in execution stage.
+    /// Record the time consumption of each function in execution stage since the
+    /// previous event, which is then added to duration_total.
     #[cfg(feature = "enable_execution_duration_record")]
     pub(crate) duration_record: ExecutionDurationRecord,
+    /// Record the time consumption of each function in execution stage.
+    #[cfg(feature = "enable_execution_duration_record")]
+    pub(crate) duration_total: ExecutionDurationRecord,
     /// Record tps and gas.
     #[cfg(feature = "enable_tps_gas_record")]
     pub(crate) tps_gas_record: TpsGasRecord,
     /// Record cache hits, number of accesses, and memory usage.
     #[cfg(feature = "enable_cache_record")]
     pub(crate) cachedb_record: CacheDbRecord,
+    /// Record the hot keys of cachedb over a range of blocks.
+    #[cfg(feature = "enable_cache_record")]
+    pub(crate) hot_keys: HotKeysWindow,
     /// Record information on instruction execution.
     #[cfg(feature = "enable_opcode_metrics")]
     pub(crate) op_record: OpcodeRecord,
+    /// Record information on instruction execution since the previous event.
+    #[cfg(feature = "enable_opcode_metrics")]
+    pub(crate) op_delta: OpcodeRecord,
+    /// Record information on instruction execution of the most recent transactions.
+    #[cfg(feature = "enable_opcode_metrics")]
+    pub(crate) op_history: OpcodeHistory,
 
     /// A channel for sending recorded indicator information to the dashboard for display.
     pub(crate) events_tx: Option<MetricEventsSender>,
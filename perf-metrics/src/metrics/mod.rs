@@ -3,6 +3,10 @@ mod macros;
 #[cfg(feature = "enable_execution_duration_record")]
 mod duration;
 mod execute_measure;
+#[cfg(feature = "enable_cache_record")]
+mod hot_keys;
+#[cfg(feature = "enable_opcode_metrics")]
+mod opcode_history;
 #[cfg(feature = "enable_tps_gas_record")]
 mod tps_gas;
 
@@ -11,5 +15,11 @@ pub mod metric;
 #[cfg(feature = "enable_execution_duration_record")]
 pub(crate) use duration::{ExecuteTxsRecord, ExecutionDurationRecord, WriteToDbRecord};
 
+#[cfg(feature = "enable_cache_record")]
+pub use hot_keys::HotKeysReport;
+
+#[cfg(feature = "enable_opcode_metrics")]
+pub use opcode_history::TxOpcodeRecord;
+
 #[cfg(feature = "enable_tps_gas_record")]
 pub use tps_gas::{TpsAndGasMessage, TpsGasRecord};
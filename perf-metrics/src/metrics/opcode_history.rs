@@ -0,0 +1,166 @@
+//! This module is used to keep the opcode records of the most recent transactions,
+//! so that the profile of a single transaction can be inspected afterwards.
+use revm_utils::metrics::types::OpcodeRecord;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// The default number of transactions kept in the history. Keeping a transaction clones
+/// its whole opcode record in the measured process, so the history is off by default.
+const DEFAULT_HISTORY_CAPACITY: usize = 0;
+
+/// The opcode record of a single transaction.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TxOpcodeRecord {
+    /// The block number of the transaction.
+    pub block_number: u64,
+    /// The index of the transaction in the block.
+    pub tx_index: u64,
+    /// The hash of the transaction, if provided by the caller.
+    pub tx_hash: Option<[u8; 32]>,
+    /// The opcode record of the transaction.
+    pub record: OpcodeRecord,
+}
+
+/// A bounded ring of the opcode records of the most recent transactions, the oldest
+/// record will be dropped when the ring is full.
+#[derive(Debug)]
+pub(crate) struct OpcodeHistory {
+    /// The maximum number of records kept.
+    capacity: usize,
+    /// The block number of the last recorded transaction.
+    block_number: u64,
+    /// The index of the next transaction in the current block.
+    next_tx_index: u64,
+    /// Records ordered from the oldest to the newest.
+    records: VecDeque<TxOpcodeRecord>,
+}
+
+impl Default for OpcodeHistory {
+    fn default() -> Self {
+        OpcodeHistory {
+            capacity: DEFAULT_HISTORY_CAPACITY,
+            block_number: 0,
+            next_tx_index: 0,
+            records: VecDeque::new(),
+        }
+    }
+}
+
+impl OpcodeHistory {
+    /// Set the maximum number of records kept, the oldest records will be dropped
+    /// if there are more records than the new capacity.
+    pub(crate) fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.records.len() > capacity {
+            self.records.pop_front();
+        }
+    }
+
+    /// Add the opcode record of a transaction executed in block_number. The index of the
+    /// transaction is counted from the first transaction recorded in this block.
+    pub(crate) fn push(
+        &mut self,
+        block_number: u64,
+        tx_hash: Option<[u8; 32]>,
+        record: &OpcodeRecord,
+    ) {
+        if block_number != self.block_number {
+            self.block_number = block_number;
+            self.next_tx_index = 0;
+        }
+        let tx_index = self.next_tx_index;
+        self.next_tx_index += 1;
+
+        // Transactions that don't execute any opcode are counted, but not kept.
+        if self.capacity == 0 || !record.not_empty() {
+            return;
+        }
+        if self.records.len() == self.capacity {
+            self.records.pop_front();
+        }
+        self.records.push_back(TxOpcodeRecord {
+            block_number,
+            tx_index,
+            tx_hash,
+            record: record.clone(),
+        });
+    }
+
+    /// Returns all records, ordered from the oldest to the newest.
+    pub(crate) fn records(&self) -> impl Iterator<Item = &TxOpcodeRecord> {
+        self.records.iter()
+    }
+
+    /// Returns the record of the transaction at tx_index in block_number.
+    pub(crate) fn find(&self, block_number: u64, tx_index: u64) -> Option<&TxOpcodeRecord> {
+        self.records
+            .iter()
+            .find(|r| r.block_number == block_number && r.tx_index == tx_index)
+    }
+
+    /// Returns the record of the transaction with tx_hash.
+    pub(crate) fn find_by_hash(&self, tx_hash: &[u8; 32]) -> Option<&TxOpcodeRecord> {
+        self.records
+            .iter()
+            .find(|r| r.tx_hash.as_ref() == Some(tx_hash))
+    }
+
+    /// Returns the n records which spend the most time executing opcodes, ordered
+    /// from the most expensive one.
+    pub(crate) fn most_expensive(&self, n: usize) -> Vec<TxOpcodeRecord> {
+        let mut records: Vec<&TxOpcodeRecord> = self.records.iter().collect();
+        records.sort_by_key(|r| std::cmp::Reverse(r.record.total_time));
+        records.into_iter().take(n).cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(total_time: u64) -> OpcodeRecord {
+        OpcodeRecord {
+            total_time,
+            is_updated: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_history_is_bounded() {
+        let mut history = OpcodeHistory::default();
+        history.set_capacity(2);
+        history.push(1, None, &record(10));
+        history.push(1, Some([1; 32]), &record(30));
+        history.push(2, None, &record(20));
+
+        let keys: Vec<(u64, u64)> = history
+            .records()
+            .map(|r| (r.block_number, r.tx_index))
+            .collect();
+        assert_eq!(keys, vec![(1, 1), (2, 0)]);
+        assert!(history.find(1, 0).is_none());
+        assert_eq!(history.find_by_hash(&[1; 32]).unwrap().tx_index, 1);
+        assert_eq!(history.most_expensive(1)[0].record.total_time, 30);
+    }
+
+    #[test]
+    fn test_history_is_off_by_default() {
+        let mut history = OpcodeHistory::default();
+        history.push(1, None, &record(10));
+
+        assert_eq!(history.records().count(), 0);
+        assert!(history.most_expensive(1).is_empty());
+    }
+
+    #[test]
+    fn test_empty_records_are_counted() {
+        let mut history = OpcodeHistory::default();
+        history.set_capacity(2);
+        history.push(1, None, &OpcodeRecord::default());
+        history.push(1, None, &record(10));
+
+        assert_eq!(history.records().count(), 1);
+        assert_eq!(history.find(1, 1).unwrap().record.total_time, 10);
+    }
+}
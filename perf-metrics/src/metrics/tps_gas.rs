@@ -1,21 +1,26 @@
 //! This module is used to track the total number of transactions
 //! and total gas consumed so far.
-#[derive(Debug, Default, Copy, Clone)]
+use revm_utils::metrics::health::add;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Default, Copy, Clone, Serialize, Deserialize)]
 pub struct TpsGasRecord {
     pub(crate) block_number: u64,
+    #[serde(with = "revm_utils::metrics::int128")]
     pub(crate) txs: u128,
+    #[serde(with = "revm_utils::metrics::int128")]
     pub(crate) gas: u128,
 }
 
 impl TpsGasRecord {
     pub(crate) fn record(&mut self, block_number: u64, txs: u128, gas: u128) {
         self.block_number = block_number;
-        self.txs = self.txs.checked_add(txs).expect("overflow");
-        self.gas = self.gas.checked_add(gas).expect("overflow");
+        self.txs = add(self.txs, txs);
+        self.gas = add(self.gas, gas);
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub enum TpsAndGasMessage {
     Switch(bool),
     Record(TpsGasRecord),
@@ -0,0 +1,237 @@
+//! perf-report renders the metric events saved by [JsonlSink] or [MessagePackSink] as the
+//! live dashboard does, or re-exports them to csv tables or another dump. With a baseline
+//! dump, the run is compared against it, and perf-report exits with 1 if any metric
+//! regressed, or with 2 on errors or if no metric is compared.
+use clap::{builder::PossibleValuesParser, Parser, ValueEnum};
+use perf_metrics::{
+    dashboard::{
+        read_dump, CompareOutcome, CompareThresholds, ComparisonReport, CsvSink, DisplayMode,
+        DumpFormat, HtmlReportSink, JsonlSink, MessagePackSink, MetricSink, RunRecords,
+        TerminalSink, TpsReportInterval,
+    },
+    MetricEvent,
+};
+use std::{
+    io,
+    path::{Path, PathBuf},
+    process,
+    time::Duration,
+};
+
+#[derive(Debug, Parser)]
+#[command(name = "perf-report", about = "Render saved metric dumps")]
+struct Args {
+    /// The dumps to read in order, in MessagePack format if the extension is "msgpack" or
+    /// "mpk", otherwise in JSON Lines format.
+    #[arg(required = true)]
+    inputs: Vec<PathBuf>,
+
+    /// Skip the events before this block.
+    #[arg(long)]
+    from_block: Option<u64>,
+
+    /// Skip the events after this block.
+    #[arg(long)]
+    to_block: Option<u64>,
+
+    /// Only keep the events of this kind, e.g. "OpcodeInfo", can be repeated.
+    #[arg(long = "kind", value_parser = PossibleValuesParser::new(MetricEvent::KINDS))]
+    kinds: Vec<String>,
+
+    /// Which of the cumulative and the per-interval records are rendered.
+    #[arg(long, value_enum, default_value_t = Mode::Cumulative)]
+    display_mode: Mode,
+
+    /// Report the tps and mgas/s every n blocks, or at the interval of execution time
+    /// with a suffix "s", e.g. "30s".
+    #[arg(long, value_parser = parse_tps_interval)]
+    tps_interval: Option<TpsReportInterval>,
+
+    /// Export the opcode, cache and duration tables to this directory in csv format.
+    #[arg(long, value_name = "DIR")]
+    csv: Option<PathBuf>,
+
+    /// Render the kept events as an html report with charts at this path.
+    #[arg(long, value_name = "PATH")]
+    html: Option<PathBuf>,
+
+    /// Write the kept events to this dump, in the format decided by its extension.
+    #[arg(long, value_name = "PATH")]
+    output: Option<PathBuf>,
+
+    /// Do not print the tables on stdout.
+    #[arg(long)]
+    no_print: bool,
+
+    /// Compare the latest records of the kept events against those of this dump, which
+    /// is filtered the same way.
+    #[arg(long, value_name = "DUMP")]
+    baseline: Option<PathBuf>,
+
+    /// The relative change (%) of a metric beyond which it regressed or improved.
+    #[arg(long, value_name = "PCT", default_value_t = 5.0)]
+    threshold: f64,
+
+    /// Override the threshold of a table, i.e. "opcode", "opcode_category", "cache" or
+    /// "execution_stage", e.g. "opcode=10", can be repeated.
+    #[arg(long = "table-threshold", value_name = "TABLE=PCT", value_parser = parse_table_threshold)]
+    table_thresholds: Vec<(String, f64)>,
+
+    /// Skip the rows executed or accessed fewer times than this in either run.
+    #[arg(long, default_value_t = 100)]
+    min_count: u64,
+
+    /// Write the comparison to this file, in json format if the extension is "json",
+    /// otherwise in csv format.
+    #[arg(long, value_name = "PATH", requires = "baseline")]
+    comparison: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Mode {
+    Cumulative,
+    Delta,
+    Both,
+}
+
+impl From<Mode> for DisplayMode {
+    fn from(mode: Mode) -> Self {
+        match mode {
+            Mode::Cumulative => DisplayMode::Cumulative,
+            Mode::Delta => DisplayMode::Delta,
+            Mode::Both => DisplayMode::Both,
+        }
+    }
+}
+
+fn parse_tps_interval(value: &str) -> Result<TpsReportInterval, String> {
+    match value.strip_suffix('s') {
+        Some(secs) => secs
+            .parse::<f64>()
+            .ok()
+            .filter(|secs| *secs > 0.0)
+            .map(|secs| TpsReportInterval::Time(Duration::from_secs_f64(secs)))
+            .ok_or_else(|| format!("invalid number of seconds: {}", secs)),
+        None => value
+            .parse::<u64>()
+            .ok()
+            .filter(|blocks| *blocks > 0)
+            .map(TpsReportInterval::Blocks)
+            .ok_or_else(|| format!("invalid number of blocks: {}", value)),
+    }
+}
+
+fn parse_table_threshold(value: &str) -> Result<(String, f64), String> {
+    let (table, pct) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected TABLE=PCT: {}", value))?;
+    let pct = pct
+        .parse::<f64>()
+        .map_err(|_| format!("invalid percentage: {}", pct))?;
+    Ok((table.to_string(), pct))
+}
+
+impl Args {
+    /// Returns whether event is kept by the filters.
+    fn keep(&self, event: &MetricEvent) -> bool {
+        let block_number = event.block_number();
+        self.from_block.is_none_or(|from| block_number >= from)
+            && self.to_block.is_none_or(|to| block_number <= to)
+            && (self.kinds.is_empty() || self.kinds.iter().any(|kind| kind == event.kind()))
+    }
+
+    fn sinks(&self) -> io::Result<Vec<Box<dyn MetricSink>>> {
+        let display_mode = self.display_mode.into();
+        let mut sinks: Vec<Box<dyn MetricSink>> = Vec::new();
+        if !self.no_print {
+            let mut terminal = TerminalSink::new().with_display_mode(display_mode);
+            if let Some(interval) = self.tps_interval {
+                terminal = terminal.with_tps_report_interval(interval);
+            }
+            sinks.push(Box::new(terminal));
+        }
+        if let Some(dir) = &self.csv {
+            sinks.push(Box::new(CsvSink::new(dir).with_display_mode(display_mode)));
+        }
+        if let Some(path) = &self.html {
+            let mut html = HtmlReportSink::new(path);
+            if let Some(interval) = self.tps_interval {
+                html = html.with_tps_report_interval(interval);
+            }
+            sinks.push(Box::new(html));
+        }
+        if let Some(path) = &self.output {
+            match DumpFormat::from_path(path) {
+                DumpFormat::JsonLines => sinks.push(Box::new(JsonlSink::new(path)?)),
+                DumpFormat::MessagePack => sinks.push(Box::new(MessagePackSink::new(path)?)),
+            }
+        }
+        Ok(sinks)
+    }
+
+    fn thresholds(&self) -> CompareThresholds {
+        self.table_thresholds.iter().fold(
+            CompareThresholds::new(self.threshold / 100.0).with_min_count(self.min_count),
+            |thresholds, (table, pct)| thresholds.with_table(table, pct / 100.0),
+        )
+    }
+
+    /// Pass the kept events of the dump at path to handle in order. A read error stops
+    /// the replay, since the rest of a corrupted dump can not be located reliably.
+    fn replay(&self, path: &Path, mut handle: impl FnMut(u64, &MetricEvent)) -> io::Result<()> {
+        let with_path =
+            |err: io::Error| io::Error::new(err.kind(), format!("{}: {}", path.display(), err));
+        for dumped in read_dump(path).map_err(with_path)? {
+            let dumped = dumped.map_err(with_path)?;
+            if self.keep(&dumped.event) {
+                handle(dumped.timestamp_ms, &dumped.event);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the outcome of the comparison against the baseline, which passes without it.
+fn run(args: &Args) -> io::Result<CompareOutcome> {
+    let mut sinks = args.sinks()?;
+    let mut current = RunRecords::default();
+    for input in &args.inputs {
+        args.replay(input, |timestamp_ms, event| {
+            for sink in sinks.iter_mut() {
+                sink.on_replayed_event(timestamp_ms, event);
+            }
+            if args.baseline.is_some() {
+                current.record(event);
+            }
+        })?;
+    }
+    sinks.iter_mut().for_each(|sink| sink.shutdown());
+
+    let Some(baseline_path) = &args.baseline else {
+        return Ok(CompareOutcome::Passed);
+    };
+    let mut baseline = RunRecords::default();
+    args.replay(baseline_path, |_, event| baseline.record(event))?;
+    let report = ComparisonReport::compare(&baseline, &current, &args.thresholds());
+    report.print();
+    if let Some(path) = &args.comparison {
+        report.write_to_file(path)?;
+    }
+    Ok(report.outcome())
+}
+
+fn main() {
+    let args = Args::parse();
+    match run(&args) {
+        Ok(CompareOutcome::Passed) => {}
+        Ok(CompareOutcome::NothingCompared) => {
+            eprintln!("perf-report failed: no metric is compared with the baseline");
+            process::exit(CompareOutcome::NothingCompared.exit_code());
+        }
+        Ok(outcome) => process::exit(outcome.exit_code()),
+        Err(err) => {
+            eprintln!("perf-report failed: {}", err);
+            process::exit(2);
+        }
+    }
+}
@@ -0,0 +1,168 @@
+//! This module defines the policy applied when a metric counter overflows or a measured
+//! interval is negative, e.g. because of a TSC glitch, and counts how many such events
+//! were absorbed instead of taking down the process.
+use crate::time_utils::instant::Instant;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
+/// The policy applied when a metric counter overflows or a measured interval is negative.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OverflowPolicy {
+    /// Panic, which is useful to find the bugs of measurement in tests.
+    Panic = 0,
+    /// Keep the counter at its maximum value, and count the event.
+    Saturate,
+    /// Wrap the counter around, and count the event.
+    Wrap,
+}
+
+static OVERFLOW_POLICY: AtomicU8 = AtomicU8::new(OverflowPolicy::Saturate as u8);
+static OVERFLOWS: AtomicU64 = AtomicU64::new(0);
+static NEGATIVE_INTERVALS: AtomicU64 = AtomicU64::new(0);
+
+/// Set the policy applied to all metric counters, the default is Saturate.
+pub fn set_overflow_policy(policy: OverflowPolicy) {
+    OVERFLOW_POLICY.store(policy as u8, Ordering::Relaxed);
+}
+
+/// Returns the policy applied to all metric counters.
+pub fn overflow_policy() -> OverflowPolicy {
+    match OVERFLOW_POLICY.load(Ordering::Relaxed) {
+        0 => OverflowPolicy::Panic,
+        1 => OverflowPolicy::Saturate,
+        _ => OverflowPolicy::Wrap,
+    }
+}
+
+/// The number of abnormal events absorbed by the overflow policy.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MetricsHealth {
+    /// The number of counter overflows.
+    pub overflows: u64,
+    /// The number of intervals whose end is earlier than their start.
+    pub negative_intervals: u64,
+}
+
+impl MetricsHealth {
+    /// Returns true if no abnormal event happened.
+    pub fn is_healthy(&self) -> bool {
+        self.overflows == 0 && self.negative_intervals == 0
+    }
+}
+
+/// Returns the number of abnormal events absorbed since the start of the process.
+pub fn metrics_health() -> MetricsHealth {
+    MetricsHealth {
+        overflows: OVERFLOWS.load(Ordering::Relaxed),
+        negative_intervals: NEGATIVE_INTERVALS.load(Ordering::Relaxed),
+    }
+}
+
+/// The integer types used as metric counters.
+pub trait Counter: Copy {
+    fn checked_add(self, rhs: Self) -> Option<Self>;
+    fn saturating_add(self, rhs: Self) -> Self;
+    fn wrapping_add(self, rhs: Self) -> Self;
+}
+
+macro_rules! impl_counter {
+    ($($t:ty),*) => {
+        $(
+            impl Counter for $t {
+                #[inline]
+                fn checked_add(self, rhs: Self) -> Option<Self> {
+                    <$t>::checked_add(self, rhs)
+                }
+                #[inline]
+                fn saturating_add(self, rhs: Self) -> Self {
+                    <$t>::saturating_add(self, rhs)
+                }
+                #[inline]
+                fn wrapping_add(self, rhs: Self) -> Self {
+                    <$t>::wrapping_add(self, rhs)
+                }
+            }
+        )*
+    };
+}
+
+impl_counter!(u32, u64, u128, usize, i128);
+
+/// Returns a + b, the overflow policy is applied if it overflows.
+#[inline]
+pub fn add<T: Counter>(a: T, b: T) -> T {
+    match a.checked_add(b) {
+        Some(sum) => sum,
+        None => add_overflowed(a, b),
+    }
+}
+
+#[cold]
+fn add_overflowed<T: Counter>(a: T, b: T) -> T {
+    let sum = overflowed_sum(overflow_policy(), a, b);
+    OVERFLOWS.fetch_add(1, Ordering::Relaxed);
+    sum
+}
+
+/// Returns the sum of a and b which overflows under policy.
+fn overflowed_sum<T: Counter>(policy: OverflowPolicy, a: T, b: T) -> T {
+    match policy {
+        OverflowPolicy::Panic => panic!("overflow"),
+        OverflowPolicy::Wrap => a.wrapping_add(b),
+        OverflowPolicy::Saturate => a.saturating_add(b),
+    }
+}
+
+/// Returns the cpu cycles from earlier to now. If earlier is later than now, the overflow
+/// policy is applied and the interval is taken as 0.
+#[inline]
+pub fn cycles_between(earlier: Instant, now: Instant) -> u64 {
+    match now.checked_cycles_since(earlier) {
+        Some(cycles) => cycles,
+        None => negative_interval(),
+    }
+}
+
+#[cold]
+fn negative_interval() -> u64 {
+    if overflow_policy() == OverflowPolicy::Panic {
+        panic!("overflow");
+    }
+    NEGATIVE_INTERVALS.fetch_add(1, Ordering::Relaxed);
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overflow_policy() {
+        assert_eq!(overflowed_sum(OverflowPolicy::Wrap, u64::MAX, 2), 1);
+        assert_eq!(
+            overflowed_sum(OverflowPolicy::Saturate, i128::MAX, 1),
+            i128::MAX
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "overflow")]
+    fn test_overflow_policy_panic() {
+        overflowed_sum(OverflowPolicy::Panic, u32::MAX, 1);
+    }
+
+    /// The policy is left as the default Saturate, and the global counters are only
+    /// checked to grow, since other tests may record into them in parallel.
+    #[test]
+    fn test_abnormal_events_are_counted() {
+        assert_eq!(add(1u64, 2), 3);
+
+        let before = metrics_health();
+        assert_eq!(add(u64::MAX, 2), u64::MAX);
+        assert_eq!(cycles_between(Instant::now(), Instant::default()), 0);
+
+        let after = metrics_health();
+        assert!(after.overflows > before.overflows);
+        assert!(after.negative_intervals > before.negative_intervals);
+    }
+}
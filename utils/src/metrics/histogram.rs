@@ -0,0 +1,305 @@
+//! This module provides a log-linear histogram in the style of HdrHistogram, which keeps
+//! a fixed relative precision over a wide range of values, so that both sub-microsecond
+//! cache hits and multi-millisecond database misses can be recorded in one histogram.
+use super::health::add;
+use serde::{Deserialize, Serialize};
+
+/// The default number of significant decimal digits kept for each value.
+const DEFAULT_SIGNIFICANT_DIGITS: u8 = 2;
+/// The default highest trackable value (ns), i.e. 100 seconds.
+const DEFAULT_HIGHEST_VALUE: u64 = 100_000_000_000;
+
+/// A log-linear histogram of time (ns), values are grouped into buckets whose width is
+/// proportional to their magnitude, so the relative error of each value is bounded by
+/// the number of significant digits. Values larger than the highest trackable value are
+/// recorded into the last bucket, but the exact maximum is always kept.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "CompactLogHistogram", into = "CompactLogHistogram")]
+pub struct LogHistogram {
+    /// The number of significant decimal digits kept for each value.
+    significant_digits: u8,
+    /// The highest trackable value.
+    highest_value: u64,
+    /// log2 of the number of sub-buckets in a bucket.
+    sub_bucket_count_magnitude: u32,
+    /// The number of values recorded.
+    count: u64,
+    /// The sum of values recorded.
+    sum: u64,
+    /// The minimum value recorded.
+    min: u64,
+    /// The maximum value recorded.
+    max: u64,
+    /// The counts of each sub-bucket, allocated on the first record.
+    counts: Vec<u64>,
+}
+
+impl Default for LogHistogram {
+    fn default() -> Self {
+        Self::new(DEFAULT_SIGNIFICANT_DIGITS, DEFAULT_HIGHEST_VALUE)
+    }
+}
+
+impl LogHistogram {
+    /// Creates a histogram tracking values from 1 to highest_value with significant_digits
+    /// (1 to 5) decimal digits of precision.
+    pub fn new(significant_digits: u8, highest_value: u64) -> Self {
+        assert!(
+            (1..=5).contains(&significant_digits),
+            "significant_digits should be in 1..=5"
+        );
+        let largest_single_unit = 2 * 10u64.pow(significant_digits as u32);
+        LogHistogram {
+            significant_digits,
+            highest_value: highest_value.max(largest_single_unit),
+            sub_bucket_count_magnitude: 64 - (largest_single_unit - 1).leading_zeros(),
+            count: 0,
+            sum: 0,
+            min: u64::MAX,
+            max: 0,
+            counts: Vec::new(),
+        }
+    }
+
+    fn sub_bucket_half_count_magnitude(&self) -> u32 {
+        self.sub_bucket_count_magnitude - 1
+    }
+
+    fn sub_bucket_half_count(&self) -> usize {
+        1 << self.sub_bucket_half_count_magnitude()
+    }
+
+    /// The number of sub-buckets needed to cover values up to highest_value.
+    fn counts_len(&self) -> usize {
+        let mut smallest_untrackable = 1u64 << self.sub_bucket_count_magnitude;
+        let mut bucket_count = 1;
+        while smallest_untrackable <= self.highest_value {
+            if smallest_untrackable > u64::MAX / 2 {
+                bucket_count += 1;
+                break;
+            }
+            smallest_untrackable <<= 1;
+            bucket_count += 1;
+        }
+        (bucket_count + 1) * self.sub_bucket_half_count()
+    }
+
+    fn counts_index(&self, value: u64) -> usize {
+        let sub_bucket_mask = (1u64 << self.sub_bucket_count_magnitude) - 1;
+        let bucket_index = (64 - self.sub_bucket_count_magnitude)
+            .saturating_sub((value | sub_bucket_mask).leading_zeros());
+        let sub_bucket_index = (value >> bucket_index) as usize;
+        ((bucket_index as usize + 1) << self.sub_bucket_half_count_magnitude()) + sub_bucket_index
+            - self.sub_bucket_half_count()
+    }
+
+    /// Returns the highest value which falls into the same sub-bucket as counts[index].
+    fn highest_equivalent_value(&self, index: usize) -> u64 {
+        let half = self.sub_bucket_half_count();
+        let (bucket_index, sub_bucket_index) = if index < 2 * half {
+            (0, index)
+        } else {
+            let bucket_index = index / half - 1;
+            (bucket_index, index % half + half)
+        };
+        let lowest = (sub_bucket_index as u64) << bucket_index;
+        lowest + (1u64 << bucket_index) - 1
+    }
+
+    /// Record value (ns) count times.
+    pub fn record_n(&mut self, value: u64, count: u64) {
+        if count == 0 {
+            return;
+        }
+        if self.counts.is_empty() {
+            self.counts = vec![0; self.counts_len()];
+        }
+        let index = self
+            .counts_index(value.min(self.highest_value))
+            .min(self.counts.len() - 1);
+        self.counts[index] = add(self.counts[index], count);
+        self.count = add(self.count, count);
+        self.sum = add(self.sum, value.saturating_mul(count));
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    /// Record a time in ns, which is rounded to the nearest integer.
+    pub fn record(&mut self, time_in_ns: f64) {
+        self.record_n(time_in_ns.round() as u64, 1);
+    }
+
+    /// Update this histogram with the other's data.
+    pub fn update(&mut self, other: &Self) {
+        if other.count == 0 {
+            return;
+        }
+        if self.significant_digits != other.significant_digits
+            || self.highest_value != other.highest_value
+        {
+            for (index, &count) in other.counts.iter().enumerate() {
+                self.record_n(other.highest_equivalent_value(index), count);
+            }
+            return;
+        }
+
+        if self.counts.is_empty() {
+            self.counts = vec![0; self.counts_len()];
+        }
+        for (this, other) in self.counts.iter_mut().zip(other.counts.iter()) {
+            *this = add(*this, *other);
+        }
+        self.count = add(self.count, other.count);
+        self.sum = add(self.sum, other.sum);
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+
+    /// Returns the number of values recorded.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Returns the minimum value recorded, or 0 if nothing is recorded.
+    pub fn min(&self) -> u64 {
+        if self.count == 0 {
+            0
+        } else {
+            self.min
+        }
+    }
+
+    /// Returns the maximum value recorded.
+    pub fn max(&self) -> u64 {
+        self.max
+    }
+
+    /// Returns the mean of values recorded.
+    pub fn mean(&self) -> f64 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        self.sum as f64 / self.count as f64
+    }
+
+    /// Returns the value at quantile (0.0 to 1.0), i.e. the highest value that quantile
+    /// of recorded values are less than or equal to, within the precision of the histogram.
+    pub fn quantile(&self, quantile: f64) -> u64 {
+        if self.count == 0 {
+            return 0;
+        }
+        let target = ((quantile.clamp(0.0, 1.0) * self.count as f64).ceil() as u64).max(1);
+        let mut cumulative = 0;
+        for (index, &count) in self.counts.iter().enumerate() {
+            cumulative += count;
+            if cumulative >= target {
+                return self
+                    .highest_equivalent_value(index)
+                    .clamp(self.min, self.max);
+            }
+        }
+        self.max
+    }
+
+    /// Returns the non-empty sub-buckets as (highest equivalent value, count).
+    pub fn buckets(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.counts
+            .iter()
+            .enumerate()
+            .filter(|(_, &count)| count > 0)
+            .map(|(index, &count)| (self.highest_equivalent_value(index), count))
+    }
+}
+
+/// The serialized form of LogHistogram, in which only non-empty sub-buckets are kept.
+#[derive(Serialize, Deserialize)]
+struct CompactLogHistogram {
+    significant_digits: u8,
+    highest_value: u64,
+    count: u64,
+    sum: u64,
+    min: u64,
+    max: u64,
+    /// Non-empty sub-buckets as (index, count).
+    counts: Vec<(u32, u64)>,
+}
+
+impl From<LogHistogram> for CompactLogHistogram {
+    fn from(histogram: LogHistogram) -> Self {
+        CompactLogHistogram {
+            significant_digits: histogram.significant_digits,
+            highest_value: histogram.highest_value,
+            count: histogram.count,
+            sum: histogram.sum,
+            min: histogram.min,
+            max: histogram.max,
+            counts: histogram
+                .counts
+                .iter()
+                .enumerate()
+                .filter(|(_, &count)| count > 0)
+                .map(|(index, &count)| (index as u32, count))
+                .collect(),
+        }
+    }
+}
+
+impl From<CompactLogHistogram> for LogHistogram {
+    fn from(compact: CompactLogHistogram) -> Self {
+        let mut histogram = LogHistogram::new(
+            compact.significant_digits.clamp(1, 5),
+            compact.highest_value,
+        );
+        if !compact.counts.is_empty() {
+            histogram.counts = vec![0; histogram.counts_len()];
+            for (index, count) in compact.counts {
+                if let Some(slot) = histogram.counts.get_mut(index as usize) {
+                    *slot = count;
+                }
+            }
+        }
+        histogram.count = compact.count;
+        histogram.sum = compact.sum;
+        histogram.min = compact.min;
+        histogram.max = compact.max;
+        histogram
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quantiles_keep_precision() {
+        let mut histogram = LogHistogram::default();
+        for value in 1..=10_000u64 {
+            histogram.record_n(value, 1);
+        }
+        // A 5ms miss is far beyond the values recorded before, but keeps its precision.
+        histogram.record(5_000_000.0);
+
+        assert_eq!(histogram.count(), 10_001);
+        assert_eq!(histogram.max(), 5_000_000);
+        let p50 = histogram.quantile(0.5) as f64;
+        assert!((p50 - 5_000.0).abs() / 5_000.0 < 0.01, "p50 = {}", p50);
+        let p99 = histogram.quantile(0.99) as f64;
+        assert!((p99 - 9_900.0).abs() / 9_900.0 < 0.01, "p99 = {}", p99);
+        assert_eq!(histogram.quantile(1.0), 5_000_000);
+    }
+
+    #[test]
+    fn test_merge_and_serde() {
+        let mut a = LogHistogram::default();
+        let mut b = LogHistogram::default();
+        a.record(100.0);
+        b.record(1_000_000.0);
+        a.update(&b);
+        assert_eq!((a.count(), a.min(), a.max()), (2, 100, 1_000_000));
+        assert_eq!(a.mean(), 500_050.0);
+
+        let json = serde_json::to_string(&a).unwrap();
+        let decoded: LogHistogram = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, a);
+    }
+}
@@ -0,0 +1,299 @@
+//! This module is used to track the most frequently accessed keys of CacheDB. The keys
+//! are counted by the space-saving algorithm, so the memory usage is bounded no matter
+//! how many distinct keys are accessed.
+use super::health::add;
+use serde::{Deserialize, Serialize};
+use std::{cmp::Reverse, collections::HashMap, fmt};
+
+/// The key of the state accessed through CacheDB.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AccessKey {
+    /// The address of an account.
+    Account([u8; 20]),
+    /// The address of an account and the index of a storage slot.
+    Storage([u8; 20], [u8; 32]),
+    /// The hash of a bytecode.
+    Code([u8; 32]),
+    /// The number of a block.
+    BlockHash(u64),
+}
+
+fn write_hex(f: &mut fmt::Formatter<'_>, bytes: &[u8]) -> fmt::Result {
+    write!(f, "0x")?;
+    for byte in bytes {
+        write!(f, "{:02x}", byte)?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for AccessKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccessKey::Account(address) => write_hex(f, address),
+            AccessKey::Storage(address, slot) => {
+                write_hex(f, address)?;
+                write!(f, "/")?;
+                write_hex(f, slot)
+            }
+            AccessKey::Code(hash) => write_hex(f, hash),
+            AccessKey::BlockHash(number) => write!(f, "block #{}", number),
+        }
+    }
+}
+
+/// The count of a tracked key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyCount {
+    /// The tracked key.
+    pub key: AccessKey,
+    /// The estimated count, which never underestimates the real count.
+    pub count: u64,
+    /// The maximum overestimation of count.
+    pub error: u64,
+}
+
+/// The space-saving heavy-hitter tracker, which keeps at most capacity keys. When a new
+/// key comes and the tracker is full, the key with the minimum count is replaced and the
+/// new key inherits its count as error.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpaceSaving {
+    /// The maximum number of tracked keys.
+    capacity: usize,
+    /// The tracked keys.
+    entries: Vec<KeyCount>,
+    /// The index of each tracked key in entries.
+    #[serde(skip)]
+    index: HashMap<AccessKey, usize>,
+}
+
+impl SpaceSaving {
+    pub fn new(capacity: usize) -> Self {
+        SpaceSaving {
+            capacity,
+            entries: Vec::with_capacity(capacity),
+            index: HashMap::with_capacity(capacity),
+        }
+    }
+
+    /// Returns the maximum number of tracked keys.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Count key once.
+    pub fn record(&mut self, key: AccessKey) {
+        self.add(key, 1, 0);
+    }
+
+    fn add(&mut self, key: AccessKey, count: u64, error: u64) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.index.len() != self.entries.len() {
+            self.rebuild_index();
+        }
+
+        if let Some(&i) = self.index.get(&key) {
+            let entry = &mut self.entries[i];
+            entry.count = add(entry.count, count);
+            entry.error = add(entry.error, error);
+            return;
+        }
+
+        if self.entries.len() < self.capacity {
+            self.index.insert(key, self.entries.len());
+            self.entries.push(KeyCount { key, count, error });
+            return;
+        }
+
+        let (i, min) = self
+            .entries
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, entry)| entry.count)
+            .map(|(i, entry)| (i, entry.count))
+            .expect("entries should not empty");
+        self.index.remove(&self.entries[i].key);
+        self.index.insert(key, i);
+        self.entries[i] = KeyCount {
+            key,
+            count: add(min, count),
+            error: add(min, error),
+        };
+    }
+
+    /// The index is not serialized, so it has to be rebuilt after deserialization.
+    fn rebuild_index(&mut self) {
+        self.index = self
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| (entry.key, i))
+            .collect();
+    }
+
+    /// Returns the maximum count a key which is not tracked may have, i.e. the minimum
+    /// count of the tracked keys if the tracker is full, otherwise 0.
+    pub fn min_count(&self) -> u64 {
+        if self.entries.len() < self.capacity {
+            return 0;
+        }
+        self.entries
+            .iter()
+            .map(|entry| entry.count)
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Update this struct with the other's data, the keys of both are merged and the
+    /// keys with the largest counts are kept. A key tracked by only one side may have
+    /// been counted up to the min_count of the other side, which is added to both its
+    /// count and error, so that the merged counts still never underestimate.
+    pub fn update(&mut self, other: &Self) {
+        if self.capacity == 0 {
+            self.capacity = other.capacity;
+        }
+        if self.index.len() != self.entries.len() {
+            self.rebuild_index();
+        }
+        let self_min = self.min_count();
+        let other_min = other.min_count();
+
+        let mut merged = vec![false; self.entries.len()];
+        for entry in other.entries.iter() {
+            match self.index.get(&entry.key) {
+                Some(&i) => {
+                    let this = &mut self.entries[i];
+                    this.count = add(this.count, entry.count);
+                    this.error = add(this.error, entry.error);
+                    merged[i] = true;
+                }
+                None => {
+                    self.index.insert(entry.key, self.entries.len());
+                    self.entries.push(KeyCount {
+                        key: entry.key,
+                        count: add(entry.count, self_min),
+                        error: add(entry.error, self_min),
+                    });
+                }
+            }
+        }
+        for (entry, _) in self
+            .entries
+            .iter_mut()
+            .zip(merged)
+            .filter(|(_, merged)| !merged)
+        {
+            entry.count = add(entry.count, other_min);
+            entry.error = add(entry.error, other_min);
+        }
+
+        if self.entries.len() > self.capacity {
+            self.entries.sort_by_key(|entry| Reverse(entry.count));
+            self.entries.truncate(self.capacity);
+            self.rebuild_index();
+        }
+    }
+
+    /// Returns the n keys with the largest counts, ordered from the largest one.
+    pub fn top(&self, n: usize) -> Vec<KeyCount> {
+        let mut entries = self.entries.clone();
+        entries.sort_by_key(|entry| Reverse(entry.count));
+        entries.truncate(n);
+        entries
+    }
+
+    /// Returns true if no key is tracked.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// HotKeyRecord records the most accessed and the most frequently missed keys of CacheDB.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HotKeyRecord {
+    /// The most accessed keys.
+    pub accesses: SpaceSaving,
+    /// The most frequently missed keys.
+    pub misses: SpaceSaving,
+}
+
+impl HotKeyRecord {
+    pub fn new(capacity: usize) -> Self {
+        HotKeyRecord {
+            accesses: SpaceSaving::new(capacity),
+            misses: SpaceSaving::new(capacity),
+        }
+    }
+
+    /// Update this struct with the other's data.
+    pub fn update(&mut self, other: &Self) {
+        self.accesses.update(&other.accesses);
+        self.misses.update(&other.misses);
+    }
+
+    /// Returns true if the tracking is enabled.
+    pub fn is_enabled(&self) -> bool {
+        self.accesses.capacity() > 0
+    }
+
+    pub(super) fn hit(&mut self, key: AccessKey) {
+        self.accesses.record(key);
+    }
+
+    pub(super) fn miss(&mut self, key: AccessKey) {
+        self.accesses.record(key);
+        self.misses.record(key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_space_saving_keeps_heavy_hitters() {
+        let mut tracker = SpaceSaving::new(2);
+        for number in [1, 1, 1, 2, 3, 1, 3, 3, 3] {
+            tracker.record(AccessKey::BlockHash(number));
+        }
+
+        let top = tracker.top(2);
+        // Block 3 replaced block 2 and inherits its count as error.
+        assert_eq!(top[0].key, AccessKey::BlockHash(3));
+        assert_eq!((top[0].count, top[0].error), (5, 1));
+        assert_eq!(top[1].key, AccessKey::BlockHash(1));
+        assert_eq!((top[1].count, top[1].error), (4, 0));
+
+        let mut merged = SpaceSaving::new(2);
+        merged.update(&tracker);
+        merged.update(&tracker);
+        assert_eq!(merged.top(1)[0].count, 10);
+    }
+
+    #[test]
+    fn test_space_saving_merge_full_summaries() {
+        let mut left = SpaceSaving::new(2);
+        for number in [1, 1, 1, 1, 2, 2] {
+            left.record(AccessKey::BlockHash(number));
+        }
+        let mut right = SpaceSaving::new(2);
+        for number in [3, 3, 3, 1, 1] {
+            right.record(AccessKey::BlockHash(number));
+        }
+        assert_eq!((left.min_count(), right.min_count()), (2, 2));
+
+        left.update(&right);
+        let top = left.top(3);
+        assert_eq!(top.len(), 2);
+        // Block 1 is tracked by both sides, so its count is exact.
+        assert_eq!(top[0].key, AccessKey::BlockHash(1));
+        assert_eq!((top[0].count, top[0].error), (6, 0));
+        // Block 3 may have been counted up to the min count of the left side.
+        assert_eq!(top[1].key, AccessKey::BlockHash(3));
+        assert_eq!((top[1].count, top[1].error), (5, 2));
+        // Block 2 gets 2 from the right side as well, but its 4 is truncated. The real
+        // counts 6 and 3 stay within [count - error, count].
+        assert!(top[1].count - top[1].error <= 3);
+    }
+}
@@ -1,8 +1,20 @@
 //! This module defines a structure to support the recording of metrics
 //! during instruction execution.
+use super::health::{add, cycles_between};
+use super::sampling::Sampler;
 use super::types::*;
 use crate::time_utils::{convert_cycles_to_ns_f64, instant::Instant};
 
+/// The time and gas of the last executed state access opcode. Both are needed to record
+/// a cold or warm access, but they are reported separately.
+#[derive(Debug, Default)]
+struct PendingStateAccess {
+    opcode: u8,
+    cycles: Option<u64>,
+    gas: Option<u64>,
+    kind: Option<StateAccessKind>,
+}
+
 /// This struct is used to record information during instruction execution
 /// and finally stores the data in the opcode_record field.
 #[derive(Debug, Default)]
@@ -12,11 +24,27 @@ pub(super) struct InstructionMetricRecoder {
     pre_time: Option<Instant>,
     pre_opcode: Option<u8>,
     started: bool,
+    pending_state_access: Option<PendingStateAccess>,
+    /// The peak memory size (bytes) of the current transaction.
+    peak_memory: u64,
+    /// Decide whether each transaction is sampled.
+    sampler: Sampler,
+    /// Whether the current transaction is sampled, the opcodes of a transaction which is
+    /// not sampled are only counted.
+    sampled: bool,
 }
 
 impl InstructionMetricRecoder {
     /// Start record.
     pub(super) fn start_record(&mut self) {
+        if !self.started {
+            self.sampled = self.sampler.sample();
+        }
+        if !self.sampled {
+            self.started = true;
+            return;
+        }
+
         let now = Instant::now();
 
         if !self.started {
@@ -30,6 +58,12 @@ impl InstructionMetricRecoder {
         self.started = true;
     }
 
+    /// Set the sampler which decides whether each transaction is sampled, it takes effect
+    /// from the next transaction.
+    pub(super) fn set_sampler(&mut self, sampler: Sampler) {
+        self.sampler = sampler;
+    }
+
     /// Determine whether an instruction is a call related instruction.
     fn has_call_opcode(&self) -> bool {
         if let Some(opcode) = self.pre_opcode {
@@ -52,36 +86,33 @@ impl InstructionMetricRecoder {
 
     /// Record the time taken for instruction execution.
     fn record_time(&mut self, now: Instant, opcode: u8) -> u64 {
-        let cycles = now
-            .checked_cycles_since(self.pre_time.expect("pre time is empty"))
-            .expect("overflow");
-        self.record.opcode_record[opcode as usize].1 = self.record.opcode_record[opcode as usize]
-            .1
-            .checked_add(cycles.into())
-            .expect("overflow");
+        let cycles = cycles_between(self.pre_time.expect("pre time is empty"), now);
+        self.record.opcode_record[opcode as usize].1 =
+            add(self.record.opcode_record[opcode as usize].1, cycles.into());
         self.pre_time = Some(now);
 
         // update total time
-        self.record.total_time = now
-            .checked_cycles_since(self.start_time.expect("start time is empty"))
-            .expect("overflow")
-            .into();
+        self.record.total_time =
+            cycles_between(self.start_time.expect("start time is empty"), now).into();
 
         cycles
     }
 
     /// Record opcode execution information, recording: count, time and sload percentile.
     pub(super) fn record_op(&mut self, opcode: u8) {
+        if !self.sampled {
+            self.record_count(opcode);
+            self.record.is_updated = true;
+            return;
+        }
+
         let now = Instant::now();
 
-        // record count
-        self.record.opcode_record[opcode as usize].0 = self.record.opcode_record[opcode as usize]
-            .0
-            .checked_add(1)
-            .expect("overflow");
+        self.record_count(opcode);
 
         // record time
         let cycles = self.record_time(now, opcode);
+        self.record.opcode_time_stats[opcode as usize].record(cycles);
 
         // SLOAD = 0x54,
         // statistical percentile of sload duration
@@ -90,24 +121,138 @@ impl InstructionMetricRecoder {
                 .add_sload_opcode_record(convert_cycles_to_ns_f64(cycles));
         }
 
+        if state_access_index(opcode).is_some() {
+            let pending = self.pending_state_access(opcode);
+            if pending.cycles.is_some() {
+                *pending = PendingStateAccess {
+                    opcode,
+                    ..Default::default()
+                };
+            }
+            pending.cycles = Some(cycles);
+            self.try_record_state_access();
+        }
+
         self.record.is_updated = true;
     }
 
+    /// Record the count of opcode, and the bytes accessed by opcode with fixed size.
+    fn record_count(&mut self, opcode: u8) {
+        self.record.opcode_record[opcode as usize].0 =
+            add(self.record.opcode_record[opcode as usize].0, 1);
+
+        // MLOAD = 0x51, MSTORE = 0x52, MSTORE8 = 0x53,
+        // the number of bytes they access is fixed.
+        match opcode {
+            0x51 | 0x52 => self.record.add_opcode_bytes(opcode, 32),
+            0x53 => self.record.add_opcode_bytes(opcode, 1),
+            _ => {}
+        }
+    }
+
+    /// Record the number of bytes read, written or copied by opcode with dynamic size.
+    pub(super) fn record_bytes(&mut self, opcode: u8, bytes: u64) {
+        self.record.add_opcode_bytes(opcode, bytes);
+    }
+
+    /// Record the expansion of the memory from old_size to new_size (bytes).
+    pub(super) fn record_memory_expansion(&mut self, old_size: u64, new_size: u64) {
+        let words = new_size.div_ceil(32).saturating_sub(old_size.div_ceil(32));
+        self.record.memory.words_grown = add(self.record.memory.words_grown, words);
+        self.peak_memory = self.peak_memory.max(new_size);
+    }
+
     /// Retrieve the records of opcode execution, which will be reset after retrieval.
+    /// It is expected to be called after each transaction, so that the peak memory size
+    /// is recorded per transaction.
     pub(super) fn get_record(&mut self) -> OpcodeRecord {
+        if self.record.is_updated {
+            self.record.memory.peak_memory = self.peak_memory;
+            self.record.memory.peak_memory_sum = self.peak_memory;
+            self.record.memory.tx_count = 1;
+            self.record.sampling.txs = 1;
+            if self.sampled {
+                self.record.sampling.sampled_txs = 1;
+                self.record
+                    .sampling
+                    .sampled_tx_time
+                    .record(self.record.total_time);
+            }
+        }
         self.start_time = None;
         self.pre_time = None;
         self.pre_opcode = None;
         self.started = false;
+        self.pending_state_access = None;
+        self.peak_memory = 0;
+        self.sampled = false;
         std::mem::replace(&mut self.record, OpcodeRecord::default())
     }
 
     /// Record the gas consumption during opcode execution.
     pub(super) fn record_gas(&mut self, opcode: u8, gas_used: u64) {
         // calculate gas
-        self.record.opcode_record[opcode as usize].2 = self.record.opcode_record[opcode as usize]
-            .2
-            .checked_add(gas_used.into())
-            .expect("overflow");
+        self.record.opcode_record[opcode as usize].2 = add(
+            self.record.opcode_record[opcode as usize].2,
+            gas_used.into(),
+        );
+
+        // The time of state access is only known in sampled transactions.
+        if self.sampled && state_access_index(opcode).is_some() {
+            let pending = self.pending_state_access(opcode);
+            if pending.gas.is_some() {
+                *pending = PendingStateAccess {
+                    opcode,
+                    ..Default::default()
+                };
+            }
+            pending.gas = Some(gas_used);
+            self.try_record_state_access();
+        }
+    }
+
+    /// Record whether the account or storage slot accessed by the executing opcode is
+    /// cold or warm, which takes precedence over the classification by gas.
+    pub(super) fn record_state_access_kind(&mut self, opcode: u8, kind: StateAccessKind) {
+        if self.sampled && state_access_index(opcode).is_some() {
+            self.pending_state_access(opcode).kind = Some(kind);
+        }
+    }
+
+    /// Returns the pending state access of opcode, the pending state access of other
+    /// opcode will be discarded.
+    fn pending_state_access(&mut self, opcode: u8) -> &mut PendingStateAccess {
+        let pending = self
+            .pending_state_access
+            .get_or_insert_with(PendingStateAccess::default);
+        if pending.opcode != opcode {
+            *pending = PendingStateAccess {
+                opcode,
+                ..Default::default()
+            };
+        }
+        pending
+    }
+
+    /// Record the pending state access as cold or warm once both time and gas are known.
+    fn try_record_state_access(&mut self) {
+        let Some(PendingStateAccess {
+            opcode,
+            cycles: Some(cycles),
+            gas: Some(gas),
+            kind,
+        }) = self.pending_state_access
+        else {
+            return;
+        };
+        self.pending_state_access = None;
+
+        let (Some(index), Some(kind)) = (
+            state_access_index(opcode),
+            kind.or_else(|| StateAccessKind::from_gas(opcode, gas)),
+        ) else {
+            return;
+        };
+        self.record.state_access_record[index].record(kind, cycles, gas);
     }
 }
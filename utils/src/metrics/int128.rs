@@ -0,0 +1,150 @@
+//! This module is used with `#[serde(with = "int128")]` to serialize the 128-bit integers
+//! of the records as 64-bit integers when they fit, otherwise as decimal strings. The
+//! records can not be deserialized from the buffered content of an internally tagged
+//! enum, e.g. MetricEvent, with 128-bit integers, which MessagePack does not support
+//! either.
+use serde::{
+    de::{Error, Visitor},
+    Deserializer, Serializer,
+};
+use std::{fmt, marker::PhantomData};
+
+/// A 128-bit integer, i.e. u128 or i128.
+pub trait Int128: Copy + Sized {
+    fn serialize<S: Serializer>(self, serializer: S) -> Result<S::Ok, S::Error>;
+    fn from_u128(value: u128) -> Option<Self>;
+    fn from_i128(value: i128) -> Option<Self>;
+    fn from_str(value: &str) -> Option<Self>;
+}
+
+impl Int128 for u128 {
+    fn serialize<S: Serializer>(self, serializer: S) -> Result<S::Ok, S::Error> {
+        match u64::try_from(self) {
+            Ok(value) => serializer.serialize_u64(value),
+            Err(_) => serializer.collect_str(&self),
+        }
+    }
+
+    fn from_u128(value: u128) -> Option<Self> {
+        Some(value)
+    }
+
+    fn from_i128(value: i128) -> Option<Self> {
+        u128::try_from(value).ok()
+    }
+
+    fn from_str(value: &str) -> Option<Self> {
+        value.parse().ok()
+    }
+}
+
+impl Int128 for i128 {
+    fn serialize<S: Serializer>(self, serializer: S) -> Result<S::Ok, S::Error> {
+        match i64::try_from(self) {
+            Ok(value) => serializer.serialize_i64(value),
+            Err(_) => serializer.collect_str(&self),
+        }
+    }
+
+    fn from_u128(value: u128) -> Option<Self> {
+        i128::try_from(value).ok()
+    }
+
+    fn from_i128(value: i128) -> Option<Self> {
+        Some(value)
+    }
+
+    fn from_str(value: &str) -> Option<Self> {
+        value.parse().ok()
+    }
+}
+
+pub fn serialize<S: Serializer, T: Int128>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+    value.serialize(serializer)
+}
+
+/// Accepts any integer which fits, or a decimal string.
+pub fn deserialize<'de, D: Deserializer<'de>, T: Int128>(deserializer: D) -> Result<T, D::Error> {
+    deserializer.deserialize_any(Int128Visitor(PhantomData))
+}
+
+struct Int128Visitor<T>(PhantomData<T>);
+
+impl<T: Int128> Int128Visitor<T> {
+    fn fit<E: Error>(value: Option<T>) -> Result<T, E> {
+        value.ok_or_else(|| E::custom("integer out of range"))
+    }
+}
+
+impl<'de, T: Int128> Visitor<'de> for Int128Visitor<T> {
+    type Value = T;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a 128-bit integer or a decimal string")
+    }
+
+    fn visit_u64<E: Error>(self, value: u64) -> Result<T, E> {
+        Self::fit(T::from_u128(value.into()))
+    }
+
+    fn visit_i64<E: Error>(self, value: i64) -> Result<T, E> {
+        Self::fit(T::from_i128(value.into()))
+    }
+
+    fn visit_u128<E: Error>(self, value: u128) -> Result<T, E> {
+        Self::fit(T::from_u128(value))
+    }
+
+    fn visit_i128<E: Error>(self, value: i128) -> Result<T, E> {
+        Self::fit(T::from_i128(value))
+    }
+
+    fn visit_str<E: Error>(self, value: &str) -> Result<T, E> {
+        Self::fit(T::from_str(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(tag = "kind")]
+    enum Tagged {
+        Record {
+            #[serde(with = "super")]
+            unsigned: u128,
+            #[serde(with = "super")]
+            signed: i128,
+        },
+    }
+
+    #[test]
+    fn test_round_trip_in_tagged_enum() {
+        let record = Tagged::Record {
+            unsigned: u64::MAX as u128,
+            signed: i64::MIN as i128,
+        };
+        let json = serde_json::to_string(&record).unwrap();
+        assert_eq!(
+            json,
+            r#"{"kind":"Record","unsigned":18446744073709551615,"signed":-9223372036854775808}"#
+        );
+        assert_eq!(serde_json::from_str::<Tagged>(&json).unwrap(), record);
+
+        let record = Tagged::Record {
+            unsigned: u128::MAX,
+            signed: i128::MIN,
+        };
+        let json = serde_json::to_string(&record).unwrap();
+        assert_eq!(
+            json,
+            format!(
+                r#"{{"kind":"Record","unsigned":"{}","signed":"{}"}}"#,
+                u128::MAX,
+                i128::MIN
+            )
+        );
+        assert_eq!(serde_json::from_str::<Tagged>(&json).unwrap(), record);
+    }
+}
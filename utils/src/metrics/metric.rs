@@ -2,9 +2,25 @@
 //! metrics of Revm, while providing some functions for measuring metrics
 //! in the source code and some functions for obtaining the final metrics
 //! externally.
+//!
+//! Each thread records into its own structure without locking, so that transactions can
+//! be executed in parallel. The get_* functions retrieve the records of the current
+//! thread. The records of a thread are published by publish_records after a transaction
+//! and when the thread exits, and the aggregate_* functions retrieve and merge the
+//! published records of all threads.
+use super::hot_keys::*;
 use super::instruction::*;
+use super::sampling::{Sampler, SamplingMode};
+use super::switches::{is_enabled, MetricFamily};
 use super::transact::*;
 use super::types::*;
+use std::{
+    cell::RefCell,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
 
 /// This structure records all metric information for measuring Revm.
 #[derive(Default)]
@@ -15,129 +31,406 @@ struct Metric {
     cachedb_record: CacheDbRecord,
     /// Recording transact metrics.
     transact_record: TransactDurationRecorder,
+    /// Recording the hot keys of cachedb, which is disabled if the capacity is 0.
+    hot_key_record: HotKeyRecord,
+    /// The index of the thread, which selects the random sequence of the sampler.
+    thread_index: u64,
+    /// The SAMPLING_EPOCH which the sampler is created in.
+    sampling_epoch: u64,
+    /// The HOT_KEY_EPOCH which the hot key record is created in.
+    hot_key_epoch: u64,
 }
 
-static mut METRIC_RECORDER: Option<Metric> = None;
+impl Metric {
+    /// Apply the sampling mode and the hot key capacity changed by other threads.
+    fn refresh_config(&mut self) {
+        let epoch = SAMPLING_EPOCH.load(Ordering::Acquire);
+        if self.sampling_epoch != epoch {
+            self.sampling_epoch = epoch;
+            self.instruction_record
+                .set_sampler(Sampler::new(sampling_mode(), self.thread_index));
+        }
+        let epoch = HOT_KEY_EPOCH.load(Ordering::Acquire);
+        if self.hot_key_epoch != epoch {
+            self.hot_key_epoch = epoch;
+            self.hot_key_record = HotKeyRecord::new(HOT_KEY_CAPACITY.load(Ordering::Relaxed));
+        }
+    }
+
+    /// Take the records, which will be reset, so that they can be published.
+    fn take_records(&mut self) -> PublishedRecord {
+        PublishedRecord {
+            op_record: self.instruction_record.get_record(),
+            cachedb_record: std::mem::take(&mut self.cachedb_record),
+            transact_time: self.transact_record.get_transact_time(),
+            transact_breakdown: self.transact_record.get_transact_breakdown(),
+            hot_key_record: take_hot_key_record(self),
+        }
+    }
+}
+
+/// The records published by a thread, which are retrieved by the aggregate_* functions.
+#[derive(Default)]
+struct PublishedRecord {
+    op_record: OpcodeRecord,
+    cachedb_record: CacheDbRecord,
+    transact_time: TransactTime,
+    transact_breakdown: TransactTimeBreakdown,
+    hot_key_record: HotKeyRecord,
+}
+
+impl PublishedRecord {
+    /// Update this struct with the other's data.
+    fn update(&mut self, mut other: Self) {
+        self.op_record.update(&mut other.op_record);
+        self.cachedb_record.update(&other.cachedb_record);
+        self.transact_time.update(&other.transact_time);
+        self.transact_breakdown.update(&other.transact_breakdown);
+        self.hot_key_record.update(&other.hot_key_record);
+    }
+}
+
+/// The published records of all threads which have recorded metrics.
+struct Registry {
+    /// The published records of each living thread.
+    threads: Vec<Arc<Mutex<PublishedRecord>>>,
+    /// The records of the exited threads, which are merged when a thread exits.
+    exited: Option<PublishedRecord>,
+}
+
+impl Registry {
+    const fn new() -> Self {
+        Registry {
+            threads: Vec::new(),
+            exited: None,
+        }
+    }
+
+    /// Call f with the published records of each thread, including the exited ones.
+    fn for_each_published(&mut self, mut f: impl FnMut(&mut PublishedRecord)) {
+        if let Some(exited) = self.exited.as_mut() {
+            f(exited);
+        }
+        for published in self.threads.iter() {
+            f(&mut published
+                .lock()
+                .expect("Published record should not be poisoned!"));
+        }
+    }
+}
+
+static REGISTRY: Mutex<Registry> = Mutex::new(Registry::new());
+
+/// The number of threads which have recorded metrics, used as the index of the next one.
+static THREAD_COUNT: AtomicU64 = AtomicU64::new(0);
+
+/// The capacity of hot key tracking, which is shared by the recorders of all threads.
+static HOT_KEY_CAPACITY: AtomicUsize = AtomicUsize::new(0);
+
+/// Bumped by enable_hot_key_tracking, so that each thread recreates its hot key record.
+static HOT_KEY_EPOCH: AtomicU64 = AtomicU64::new(0);
+
+/// The sampling mode of opcode metrics, which is shared by the recorders of all threads.
+static SAMPLING_MODE: Mutex<SamplingMode> = Mutex::new(SamplingMode::Full);
+
+/// Bumped by set_sampling_mode, so that each thread recreates its sampler.
+static SAMPLING_EPOCH: AtomicU64 = AtomicU64::new(0);
+
+/// The recorder of a thread. Recording only borrows the records of the thread itself,
+/// they are moved to published by publish_records, and to the exited records of the
+/// registry when the thread exits.
+struct LocalRecorder {
+    metric: RefCell<Metric>,
+    published: Arc<Mutex<PublishedRecord>>,
+    registry: &'static Mutex<Registry>,
+}
+
+impl LocalRecorder {
+    /// Create the recorder of the current thread and add it to registry.
+    fn register(registry: &'static Mutex<Registry>) -> Self {
+        let mut metric = Metric {
+            thread_index: THREAD_COUNT.fetch_add(1, Ordering::Relaxed),
+            sampling_epoch: u64::MAX,
+            hot_key_epoch: u64::MAX,
+            ..Default::default()
+        };
+        metric.refresh_config();
+        let published = Arc::new(Mutex::new(PublishedRecord::default()));
+        registry
+            .lock()
+            .expect("Metric registry should not be poisoned!")
+            .threads
+            .push(published.clone());
+        LocalRecorder {
+            metric: RefCell::new(metric),
+            published,
+            registry,
+        }
+    }
+
+    /// Move the records of the current thread to published.
+    fn publish(&self) {
+        let record = self.metric.borrow_mut().take_records();
+        self.published
+            .lock()
+            .expect("Published record should not be poisoned!")
+            .update(record);
+    }
+}
+
+impl Drop for LocalRecorder {
+    fn drop(&mut self) {
+        let mut record = self.metric.get_mut().take_records();
+        let mut registry = self
+            .registry
+            .lock()
+            .expect("Metric registry should not be poisoned!");
+        registry
+            .threads
+            .retain(|published| !Arc::ptr_eq(published, &self.published));
+        record.update(std::mem::take(
+            &mut self
+                .published
+                .lock()
+                .expect("Published record should not be poisoned!"),
+        ));
+        registry
+            .exited
+            .get_or_insert_with(PublishedRecord::default)
+            .update(record);
+    }
+}
 
-// This function will be called directly during program initialization.
-#[ctor::ctor]
-unsafe fn init() {
-    METRIC_RECORDER = Some(Metric::default());
+thread_local! {
+    static METRIC_RECORDER: LocalRecorder = LocalRecorder::register(&REGISTRY);
+}
+
+/// Call f with the recorder of the current thread.
+fn with_recorder<R>(f: impl FnOnce(&mut Metric) -> R) -> R {
+    METRIC_RECORDER.with(|recorder| f(&mut recorder.metric.borrow_mut()))
+}
+
+/// Publish the records of the current thread, and call f with the published records of
+/// each thread.
+fn for_each_published(f: impl FnMut(&mut PublishedRecord)) {
+    publish_records();
+    REGISTRY
+        .lock()
+        .expect("Metric registry should not be poisoned!")
+        .for_each_published(f);
+}
+
+/// Publish the records of the current thread, so that the aggregate_* functions of any
+/// thread can retrieve them. It is expected to be called after each transaction by the
+/// threads which execute transactions in parallel, while the records are also published
+/// when the thread exits.
+pub fn publish_records() {
+    METRIC_RECORDER.with(LocalRecorder::publish);
 }
 
 /// Start to record the information of opcode execution, which will be called
 /// in the source code.
 pub fn start_record_op() {
-    unsafe {
-        METRIC_RECORDER
-            .as_mut()
-            .expect("Metric recorder should not empty!")
-            .instruction_record
-            .start_record();
+    if !is_enabled(MetricFamily::Opcode) {
+        return;
     }
+    with_recorder(|recorder| {
+        recorder.refresh_config();
+        recorder.instruction_record.start_record()
+    });
 }
 
 /// Called before each instruction execution, it is mainly used to handle
 /// the situation that the INTERPRETER will be created circularly when the
 /// call related instructions are executed.
 pub fn record_before_op(opcode: u8) {
-    unsafe {
-        METRIC_RECORDER
-            .as_mut()
-            .expect("Metric recorder should not empty!")
-            .instruction_record
-            .record_before_op(opcode);
+    if !is_enabled(MetricFamily::Opcode) {
+        return;
     }
+    with_recorder(|recorder| recorder.instruction_record.record_before_op(opcode));
 }
 
 /// Record the information of opcode execution, which will be called in the
 /// source code.
 pub fn record_op(opcode: u8) {
-    unsafe {
-        METRIC_RECORDER
-            .as_mut()
-            .expect("Metric recorder should not empty!")
-            .instruction_record
-            .record_op(opcode);
+    if !is_enabled(MetricFamily::Opcode) {
+        return;
     }
+    with_recorder(|recorder| recorder.instruction_record.record_op(opcode));
 }
 
 /// Record the gas of opcode execution, which will be called in the source code.
 pub fn record_gas(opcode: u8, gas_used: u64) {
-    unsafe {
-        METRIC_RECORDER
-            .as_mut()
-            .expect("Metric recorder should not empty!")
+    if !is_enabled(MetricFamily::Opcode) {
+        return;
+    }
+    with_recorder(|recorder| recorder.instruction_record.record_gas(opcode, gas_used));
+}
+
+/// Record the number of bytes read, written or copied by opcode, which will be called in
+/// the source code of opcodes with dynamic size, such as CALLDATACOPY, MCOPY and KECCAK256.
+pub fn record_bytes(opcode: u8, bytes: u64) {
+    if !is_enabled(MetricFamily::Opcode) {
+        return;
+    }
+    with_recorder(|recorder| recorder.instruction_record.record_bytes(opcode, bytes));
+}
+
+/// Record the expansion of the EVM memory from old_size to new_size (bytes), which will
+/// be called in the source code when the memory is resized.
+pub fn record_memory_expansion(old_size: u64, new_size: u64) {
+    if !is_enabled(MetricFamily::Opcode) {
+        return;
+    }
+    with_recorder(|recorder| {
+        recorder
             .instruction_record
-            .record_gas(opcode, gas_used);
+            .record_memory_expansion(old_size, new_size)
+    });
+}
+
+/// Record whether the account or storage slot accessed by the executing state access
+/// opcode is cold or warm. Without it, the access is classified by the gas it charged.
+pub fn record_state_access_kind(opcode: u8, kind: StateAccessKind) {
+    if !is_enabled(MetricFamily::Opcode) {
+        return;
     }
+    with_recorder(|recorder| {
+        recorder
+            .instruction_record
+            .record_state_access_kind(opcode, kind)
+    });
+}
+
+/// Set which transactions are fully instrumented by the opcode metrics, the opcodes of
+/// the other transactions are only counted. It applies to all threads from their next
+/// transaction, and each thread draws its own sequence if the mode is random.
+pub fn set_sampling_mode(mode: SamplingMode) {
+    *SAMPLING_MODE
+        .lock()
+        .expect("Sampling mode should not be poisoned!") = mode;
+    SAMPLING_EPOCH.fetch_add(1, Ordering::Release);
+}
+
+/// Returns the sampling mode of the opcode metrics.
+pub fn sampling_mode() -> SamplingMode {
+    *SAMPLING_MODE
+        .lock()
+        .expect("Sampling mode should not be poisoned!")
 }
 
 /// Retrieve the records of opcode execution, which will be reset after retrieval.
 /// It will be called by the code of reth.
 pub fn get_op_record() -> OpcodeRecord {
-    unsafe {
-        METRIC_RECORDER
-            .as_mut()
-            .expect("Metric recorder should not empty!")
-            .instruction_record
-            .get_record()
-    }
+    with_recorder(|recorder| recorder.instruction_record.get_record())
+}
+
+/// Retrieve and merge the published records of opcode execution of all threads, which
+/// will be reset after retrieval. The records of the current thread are published first.
+pub fn aggregate_op_record() -> OpcodeRecord {
+    let mut record = OpcodeRecord::default();
+    for_each_published(|published| record.update(&mut published.op_record));
+    record
 }
 
 /// The function called upon cache hit, which is encapsulated in HitRecord.
-pub(super) fn hit_record(function: Function) {
-    unsafe {
-        METRIC_RECORDER
-            .as_mut()
-            .expect("Metric recorder should not empty!")
-            .cachedb_record
-            .hit(function);
+pub(super) fn hit_record(function: Function, cycles: u64) {
+    if !is_enabled(MetricFamily::Cache) {
+        return;
     }
+    with_recorder(|recorder| recorder.cachedb_record.hit(function, cycles));
 }
 
 /// The function called upon cache miss, which is encapsulated in MissRecord.
 pub(super) fn miss_record(function: Function, cycles: u64) {
-    unsafe {
-        METRIC_RECORDER
-            .as_mut()
-            .expect("Metric recorder should not empty!")
-            .cachedb_record
-            .miss(function, cycles);
+    if !is_enabled(MetricFamily::Cache) {
+        return;
     }
+    with_recorder(|recorder| recorder.cachedb_record.miss(function, cycles));
+}
+
+/// Record the key accessed upon cache hit, which is encapsulated in HitRecord.
+pub(super) fn hot_key_hit_record(key: AccessKey) {
+    if !is_enabled(MetricFamily::Cache) {
+        return;
+    }
+    with_recorder(|recorder| {
+        recorder.refresh_config();
+        if recorder.hot_key_record.is_enabled() {
+            recorder.hot_key_record.hit(key);
+        }
+    });
+}
+
+/// Record the key accessed upon cache miss, which is encapsulated in MissRecord.
+pub(super) fn hot_key_miss_record(key: AccessKey) {
+    if !is_enabled(MetricFamily::Cache) {
+        return;
+    }
+    with_recorder(|recorder| {
+        recorder.refresh_config();
+        if recorder.hot_key_record.is_enabled() {
+            recorder.hot_key_record.miss(key);
+        }
+    });
+}
+
+/// Enable the tracking of the hot keys of cachedb, at most capacity keys are tracked
+/// for accesses and misses respectively. A capacity of 0 disables the tracking.
+/// It applies to all threads, and the hot keys recorded before are discarded.
+pub fn enable_hot_key_tracking(capacity: usize) {
+    HOT_KEY_CAPACITY.store(capacity, Ordering::Relaxed);
+    HOT_KEY_EPOCH.fetch_add(1, Ordering::Release);
+    with_recorder(Metric::refresh_config);
+    for_each_published(|published| published.hot_key_record = HotKeyRecord::new(capacity));
+}
+
+fn take_hot_key_record(recorder: &mut Metric) -> HotKeyRecord {
+    let capacity = recorder.hot_key_record.accesses.capacity();
+    std::mem::replace(&mut recorder.hot_key_record, HotKeyRecord::new(capacity))
+}
+
+/// Retrieve the hot keys of cachedb, which will be reset after retrieval.
+pub fn get_hot_key_record() -> HotKeyRecord {
+    with_recorder(take_hot_key_record)
+}
+
+/// Retrieve and merge the published hot keys of cachedb of all threads, which will be
+/// reset after retrieval. The records of the current thread are published first.
+pub fn aggregate_hot_key_record() -> HotKeyRecord {
+    let mut record = HotKeyRecord::new(HOT_KEY_CAPACITY.load(Ordering::Relaxed));
+    for_each_published(|published| record.update(&std::mem::take(&mut published.hot_key_record)));
+    record
 }
 
 /// Retrieve the records of cachedb, which will be reset after retrieval.
 /// It will be called by the code of reth.
 pub fn get_cache_record() -> CacheDbRecord {
-    unsafe {
-        let record = METRIC_RECORDER
-            .as_mut()
-            .expect("Metric recorder should not empty!");
-        std::mem::replace(&mut record.cachedb_record, CacheDbRecord::default())
-    }
+    with_recorder(|recorder| std::mem::take(&mut recorder.cachedb_record))
+}
+
+/// Retrieve and merge the published records of cachedb of all threads, which will be
+/// reset after retrieval. The records of the current thread are published first.
+pub fn aggregate_cache_record() -> CacheDbRecord {
+    let mut record = CacheDbRecord::default();
+    for_each_published(|published| record.update(&std::mem::take(&mut published.cachedb_record)));
+    record
 }
 
 /// Record the start time of transact.
 pub fn transact_start_record() {
-    unsafe {
-        METRIC_RECORDER
-            .as_mut()
-            .expect("Metric recorder should not empty!")
-            .transact_record
-            .start_record()
+    if !is_enabled(MetricFamily::Duration) {
+        return;
     }
+    with_recorder(|recorder| recorder.transact_record.start_record())
 }
 
 /// Record the start time of sub function.
 pub fn transact_sub_record() {
-    unsafe {
-        METRIC_RECORDER
-            .as_mut()
-            .expect("Metric recorder should not empty!")
-            .transact_record
-            .start_sub_record();
+    if !is_enabled(MetricFamily::Duration) {
+        return;
     }
+    with_recorder(|recorder| recorder.transact_record.start_sub_record());
 }
 
 /// Wrapper of transact_sub_record and transact_start_record.
@@ -148,66 +441,141 @@ pub fn transact_record() {
 
 /// Record time of preverify_transaction_inner.
 pub fn preverify_transaction_inner_record() {
-    unsafe {
-        METRIC_RECORDER
-            .as_mut()
-            .expect("Metric recorder should not empty!")
-            .transact_record
-            .preverify_transaction_inner_record();
+    if !is_enabled(MetricFamily::Duration) {
+        return;
     }
+    with_recorder(|recorder| {
+        recorder
+            .transact_record
+            .preverify_transaction_inner_record()
+    });
 }
 
 /// Record the time before execute opcode in transact_preverified_inner.
 pub fn before_execute_record() {
-    unsafe {
-        METRIC_RECORDER
-            .as_mut()
-            .expect("Metric recorder should not empty!")
-            .transact_record
-            .before_execute_record();
+    if !is_enabled(MetricFamily::Duration) {
+        return;
     }
+    with_recorder(|recorder| recorder.transact_record.before_execute_record());
 }
 
 /// Record the time of execute opcode in transact_preverified_inner.
 pub fn execute_record() {
-    unsafe {
-        METRIC_RECORDER
-            .as_mut()
-            .expect("Metric recorder should not empty!")
-            .transact_record
-            .execute_record();
+    if !is_enabled(MetricFamily::Duration) {
+        return;
     }
+    with_recorder(|recorder| recorder.transact_record.execute_record());
 }
 
 /// Record the time after execute opcode in transact_preverified_inner.
 pub fn after_execute_record() {
-    unsafe {
-        METRIC_RECORDER
-            .as_mut()
-            .expect("Metric recorder should not empty!")
-            .transact_record
-            .after_execute_record();
+    if !is_enabled(MetricFamily::Duration) {
+        return;
     }
+    with_recorder(|recorder| recorder.transact_record.after_execute_record());
 }
 
 /// Record the time of handler.end().
 pub fn handler_end_record() {
-    unsafe {
-        METRIC_RECORDER
-            .as_mut()
-            .expect("Metric recorder should not empty!")
-            .transact_record
-            .handler_end_record();
+    if !is_enabled(MetricFamily::Duration) {
+        return;
+    }
+    with_recorder(|recorder| recorder.transact_record.handler_end_record());
+}
+
+/// Record the type of the transaction executed by transact.
+pub fn transact_tx_type_record(tx_type: TxType) {
+    if !is_enabled(MetricFamily::Duration) {
+        return;
+    }
+    with_recorder(|recorder| recorder.transact_record.tx_type_record(tx_type));
+}
+
+/// Record the outcome of the transaction executed by transact.
+pub fn transact_outcome_record(outcome: TxOutcome) {
+    if !is_enabled(MetricFamily::Duration) {
+        return;
+    }
+    with_recorder(|recorder| recorder.transact_record.outcome_record(outcome));
+}
+
+/// Record the type and the outcome of the transaction executed by transact from its
+/// receipt, unless they are already recorded by transact_tx_type_record and
+/// transact_outcome_record. It should be called before get_transact_time.
+pub fn transact_receipt_record(tx_type: Option<TxType>, outcome: TxOutcome) {
+    if !is_enabled(MetricFamily::Duration) {
+        return;
     }
+    with_recorder(|recorder| recorder.transact_record.receipt_record(tx_type, outcome));
+}
+
+/// Retrieve the breakdown of transact time by the type and the outcome of transactions,
+/// which will be reset after retrieval.
+pub fn get_transact_breakdown() -> TransactTimeBreakdown {
+    with_recorder(|recorder| recorder.transact_record.get_transact_breakdown())
+}
+
+/// Retrieve and merge the published breakdown of transact time of all threads, which
+/// will be reset after retrieval. The records of the current thread are published first.
+pub fn aggregate_transact_breakdown() -> TransactTimeBreakdown {
+    let mut breakdown = TransactTimeBreakdown::default();
+    for_each_published(|published| {
+        breakdown.update(&std::mem::take(&mut published.transact_breakdown))
+    });
+    breakdown
 }
 
 /// Retrieve transact time, which will be reset after retrieval.
 pub fn get_transact_time() -> TransactTime {
-    unsafe {
-        METRIC_RECORDER
-            .as_mut()
-            .expect("Metric recorder should not empty!")
-            .transact_record
-            .get_transact_time()
+    with_recorder(|recorder| recorder.transact_record.get_transact_time())
+}
+
+/// Retrieve and merge the published transact time of all threads, which will be reset
+/// after retrieval. The records of the current thread are published first.
+pub fn aggregate_transact_time() -> TransactTime {
+    let mut time = TransactTime::default();
+    for_each_published(|published| time.update(&std::mem::take(&mut published.transact_time)));
+    time
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aggregate_records_of_all_threads() {
+        let registry: &'static Mutex<Registry> = Box::leak(Box::new(Mutex::new(Registry::new())));
+        let hits = |recorder: &LocalRecorder, n| {
+            for _ in 0..n {
+                recorder
+                    .metric
+                    .borrow_mut()
+                    .cachedb_record
+                    .hit(Function::Storage, 10);
+            }
+        };
+
+        // The records of an exited thread are kept.
+        std::thread::spawn(move || hits(&LocalRecorder::register(registry), 3))
+            .join()
+            .unwrap();
+        // The records of a living thread are only seen once published.
+        let living = LocalRecorder::register(registry);
+        hits(&living, 4);
+        living.publish();
+        hits(&living, 5);
+
+        let mut record = CacheDbRecord::default();
+        registry.lock().unwrap().for_each_published(|published| {
+            record.update(&std::mem::take(&mut published.cachedb_record))
+        });
+        assert_eq!(record.hit_stats().function[Function::Storage as usize], 7);
+        assert_eq!(registry.lock().unwrap().threads.len(), 1);
+
+        drop(living);
+        let registry = registry.lock().unwrap();
+        assert!(registry.threads.is_empty());
+        let exited = &registry.exited.as_ref().unwrap().cachedb_record;
+        assert_eq!(exited.hit_stats().function[Function::Storage as usize], 5);
     }
 }
@@ -1,9 +1,17 @@
+pub mod health;
+mod histogram;
+pub mod hot_keys;
 mod instruction;
+pub mod int128;
 mod metric;
+pub mod sampling;
+pub mod switches;
 mod transact;
 pub mod types;
 mod wrapper;
 
+pub use hot_keys::AccessKey;
 pub use metric::*;
+pub use sampling::SamplingMode;
 pub use types::Function;
 pub use wrapper::*;
@@ -0,0 +1,106 @@
+//! This module provides the sampling of transactions for opcode metrics. Measuring the
+//! time of each opcode roughly doubles the execution time, so only the sampled
+//! transactions are fully instrumented, while the opcodes of the others are just counted.
+use serde::{Deserialize, Serialize};
+
+/// Which transactions are fully instrumented by the opcode metrics.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum SamplingMode {
+    /// Every transaction is fully instrumented.
+    #[default]
+    Full,
+    /// One in every n transactions is fully instrumented, starting from the first one.
+    EveryN(u64),
+    /// Each transaction is fully instrumented with probability fraction (0.0 to 1.0). The
+    /// random sequence is determined by seed, so that a run can be reproduced.
+    Fraction { fraction: f64, seed: u64 },
+}
+
+/// Decides whether each transaction is sampled according to a SamplingMode.
+#[derive(Debug, Clone)]
+pub(super) struct Sampler {
+    mode: SamplingMode,
+    /// The number of transactions seen, used by EveryN.
+    counter: u64,
+    /// The state of xorshift64*, used by Fraction.
+    state: u64,
+}
+
+impl Default for Sampler {
+    fn default() -> Self {
+        Self::new(SamplingMode::Full, 0)
+    }
+}
+
+impl Sampler {
+    /// Create a sampler. Samplers with the same mode but different stream, e.g. those of
+    /// different threads, draw different random sequences from the same seed.
+    pub(super) fn new(mode: SamplingMode, stream: u64) -> Self {
+        let seed = match mode {
+            SamplingMode::Fraction { seed, .. } => seed,
+            _ => 0,
+        };
+        Sampler {
+            mode,
+            counter: 0,
+            state: splitmix64(seed ^ stream.wrapping_mul(0x9E37_79B9_7F4A_7C15)),
+        }
+    }
+
+    /// Returns true if the next transaction should be fully instrumented.
+    pub(super) fn sample(&mut self) -> bool {
+        match self.mode {
+            SamplingMode::Full => true,
+            SamplingMode::EveryN(n) => {
+                let sampled = self.counter.is_multiple_of(n.max(1));
+                self.counter = self.counter.wrapping_add(1);
+                sampled
+            }
+            SamplingMode::Fraction { fraction, .. } => {
+                // The top 53 bits give a uniform f64 in [0, 1).
+                let value = (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+                value < fraction
+            }
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+}
+
+/// Scramble seed into a non-zero state of xorshift64*.
+fn splitmix64(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    (z ^ (z >> 31)).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sampling_modes() {
+        let mut every_n = Sampler::new(SamplingMode::EveryN(3), 0);
+        let sampled: Vec<bool> = (0..6).map(|_| every_n.sample()).collect();
+        assert_eq!(sampled, [true, false, false, true, false, false]);
+
+        let mode = SamplingMode::Fraction {
+            fraction: 0.25,
+            seed: 42,
+        };
+        let draw = |stream| {
+            let mut sampler = Sampler::new(mode, stream);
+            (0..10_000).filter(|_| sampler.sample()).count()
+        };
+        // The same seed reproduces the same sequence.
+        assert_eq!(draw(0), draw(0));
+        assert!((2_300..2_700).contains(&draw(0)), "sampled {}", draw(0));
+        assert_ne!(draw(0), draw(1));
+    }
+}
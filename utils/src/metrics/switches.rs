@@ -0,0 +1,118 @@
+//! This module provides runtime switches for each family of metrics, so that one
+//! instrumented binary can measure different aspects without recompilation. The switches
+//! are checked with a relaxed atomic load on the hot paths, and all families are enabled
+//! by default.
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// The environment variable read at startup to select the enabled families, which is a
+/// comma separated list of family names, e.g. "opcode,cache", or "all" or "none".
+pub const METRICS_ENV_VAR: &str = "REVM_METRICS";
+
+/// The families of metrics which can be switched at runtime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MetricFamily {
+    /// Opcode execution metrics.
+    Opcode = 0,
+    /// CacheDB hit, miss and hot key metrics.
+    Cache,
+    /// Duration of transact and execution stage.
+    Duration,
+    /// TPS and gas metrics.
+    TpsGas,
+}
+
+impl MetricFamily {
+    /// The number of variants.
+    pub const COUNT: usize = 4;
+
+    /// All variants, ordered by their index.
+    pub const ALL: [MetricFamily; MetricFamily::COUNT] = [
+        MetricFamily::Opcode,
+        MetricFamily::Cache,
+        MetricFamily::Duration,
+        MetricFamily::TpsGas,
+    ];
+
+    /// Returns the name used in METRICS_ENV_VAR.
+    pub fn name(&self) -> &'static str {
+        match self {
+            MetricFamily::Opcode => "opcode",
+            MetricFamily::Cache => "cache",
+            MetricFamily::Duration => "duration",
+            MetricFamily::TpsGas => "tps_gas",
+        }
+    }
+
+    /// Returns the family named name.
+    pub fn from_name(name: &str) -> Option<Self> {
+        MetricFamily::ALL
+            .into_iter()
+            .find(|family| family.name() == name)
+    }
+}
+
+static ENABLED: [AtomicBool; MetricFamily::COUNT] = [
+    AtomicBool::new(true),
+    AtomicBool::new(true),
+    AtomicBool::new(true),
+    AtomicBool::new(true),
+];
+
+// Apply METRICS_ENV_VAR during program initialization.
+#[ctor::ctor]
+fn init() {
+    load_from_env();
+}
+
+/// Returns true if family is enabled.
+#[inline]
+pub fn is_enabled(family: MetricFamily) -> bool {
+    ENABLED[family as usize].load(Ordering::Relaxed)
+}
+
+/// Enable or disable family.
+pub fn set_enabled(family: MetricFamily, enabled: bool) {
+    ENABLED[family as usize].store(enabled, Ordering::Relaxed);
+}
+
+/// Enable exactly the families in METRICS_ENV_VAR, nothing changes if it is not set.
+pub fn load_from_env() {
+    if let Ok(value) = std::env::var(METRICS_ENV_VAR) {
+        let enabled = parse(&value);
+        for family in MetricFamily::ALL {
+            set_enabled(family, enabled[family as usize]);
+        }
+    }
+}
+
+/// Returns whether each family is enabled by value, see METRICS_ENV_VAR for its format.
+fn parse(value: &str) -> [bool; MetricFamily::COUNT] {
+    let mut enabled = [false; MetricFamily::COUNT];
+    for name in value
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+    {
+        match name {
+            "all" => enabled = [true; MetricFamily::COUNT],
+            "none" => enabled = [false; MetricFamily::COUNT],
+            _ => match MetricFamily::from_name(name) {
+                Some(family) => enabled[family as usize] = true,
+                None => println!("Unknown metric family in {}: {}", METRICS_ENV_VAR, name),
+            },
+        }
+    }
+    enabled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_env_value() {
+        assert_eq!(parse("opcode, tps_gas"), [true, false, false, true]);
+        assert_eq!(parse("all"), [true; MetricFamily::COUNT]);
+        assert_eq!(parse("cache,none"), [false; MetricFamily::COUNT]);
+    }
+}
@@ -1,5 +1,6 @@
 //! This module is used to measure the time consumption of various parts of function
 //! transact in Revm.
+use super::health::{add, cycles_between};
 use super::types::*;
 use crate::time_utils::instant::Instant;
 
@@ -11,6 +12,13 @@ pub(super) struct TransactDurationRecorder {
     sub_record: Instant,
     /// Record the time consumption of each part of function transact.
     transact_time: TransactTime,
+    /// The type of the executing transaction.
+    tx_type: Option<TxType>,
+    /// The outcome of the executing transaction.
+    outcome: Option<TxOutcome>,
+    /// Record the time consumption of each part of function transact, keyed by the type
+    /// and the outcome of transactions.
+    breakdown: TransactTimeBreakdown,
 }
 
 impl TransactDurationRecorder {
@@ -26,77 +34,114 @@ impl TransactDurationRecorder {
     /// Add time of preverify_transaction_inner.
     pub(super) fn preverify_transaction_inner_record(&mut self) {
         let (cycles, _) = self.record_sub_time();
-        self.transact_time.preverify_transaction_inner = self
-            .transact_time
-            .preverify_transaction_inner
-            .checked_add(cycles)
-            .expect("overflow");
+        self.transact_time.preverify_transaction_inner =
+            add(self.transact_time.preverify_transaction_inner, cycles);
     }
 
     /// Add the time before execute opcode in transact_preverified_inner.
     pub(super) fn before_execute_record(&mut self) {
         let (cycles, _) = self.record_sub_time();
-        self.transact_time.transact_preverified_inner.before_execute = self
-            .transact_time
-            .transact_preverified_inner
-            .before_execute
-            .checked_add(cycles)
-            .expect("overflow");
+        self.transact_time.transact_preverified_inner.before_execute = add(
+            self.transact_time.transact_preverified_inner.before_execute,
+            cycles,
+        );
     }
 
     /// Add the time of execute opcode in transact_preverified_inner.
     pub(super) fn execute_record(&mut self) {
         let (cycles, _) = self.record_sub_time();
-        self.transact_time.transact_preverified_inner.execute = self
-            .transact_time
-            .transact_preverified_inner
-            .execute
-            .checked_add(cycles)
-            .expect("overflow");
+        self.transact_time.transact_preverified_inner.execute = add(
+            self.transact_time.transact_preverified_inner.execute,
+            cycles,
+        );
     }
 
     /// Add the time after execute opcode in transact_preverified_inner.
     pub(super) fn after_execute_record(&mut self) {
         let (cycles, _) = self.record_sub_time();
-        self.transact_time.transact_preverified_inner.after_execute = self
-            .transact_time
-            .transact_preverified_inner
-            .after_execute
-            .checked_add(cycles)
-            .expect("overflow");
+        self.transact_time.transact_preverified_inner.after_execute = add(
+            self.transact_time.transact_preverified_inner.after_execute,
+            cycles,
+        );
     }
 
     /// Add the time of handler.end().
     pub(super) fn handler_end_record(&mut self) {
         let (cycles, now) = self.record_sub_time();
-        self.transact_time.handle_end = self
-            .transact_time
-            .handle_end
-            .checked_add(cycles)
-            .expect("overflow");
+        self.transact_time.handle_end = add(self.transact_time.handle_end, cycles);
         self.record_total_time(now);
     }
 
     /// Record total time.
     fn record_total_time(&mut self, now: Instant) {
-        let cycles = now.checked_cycles_since(self.start_record).unwrap_or(0);
-        self.transact_time.total = self
-            .transact_time
-            .total
-            .checked_add(cycles)
-            .expect("overflow");
+        let cycles = cycles_between(self.start_record, now);
+        self.transact_time.total = add(self.transact_time.total, cycles);
     }
 
     /// Record time of sub function.
     fn record_sub_time(&mut self) -> (u64, Instant) {
         let now = Instant::now();
-        let cycles = now.checked_cycles_since(self.sub_record).unwrap_or(0);
+        let cycles = cycles_between(self.sub_record, now);
         self.sub_record = now;
         (cycles, now)
     }
 
-    /// Retrieve transact time, which will be reset after retrieval.
+    /// Set the type of the executing transaction.
+    pub(super) fn tx_type_record(&mut self, tx_type: TxType) {
+        self.tx_type = Some(tx_type);
+    }
+
+    /// Set the outcome of the executing transaction.
+    pub(super) fn outcome_record(&mut self, outcome: TxOutcome) {
+        self.outcome = Some(outcome);
+    }
+
+    /// Set the type and the outcome of the executing transaction from its receipt, unless
+    /// they are already set by tx_type_record and outcome_record.
+    pub(super) fn receipt_record(&mut self, tx_type: Option<TxType>, outcome: TxOutcome) {
+        if self.tx_type.is_none() {
+            self.tx_type = tx_type;
+        }
+        self.outcome.get_or_insert(outcome);
+    }
+
+    /// Retrieve transact time, which will be reset after retrieval. It is expected to be
+    /// called after each transaction, so that the time is also added to the breakdown by
+    /// the type and the outcome of the transaction.
     pub(super) fn get_transact_time(&mut self) -> TransactTime {
+        self.breakdown.add(
+            self.tx_type.take(),
+            self.outcome.take(),
+            &self.transact_time,
+        );
         std::mem::replace(&mut self.transact_time, TransactTime::default())
     }
+
+    /// Retrieve the breakdown of transact time, which will be reset after retrieval.
+    pub(super) fn get_transact_breakdown(&mut self) -> TransactTimeBreakdown {
+        std::mem::take(&mut self.breakdown)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_receipt_record_is_a_fallback() {
+        let mut recorder = TransactDurationRecorder::default();
+        recorder.outcome_record(TxOutcome::Halt);
+        recorder.receipt_record(Some(TxType::Eip1559), TxOutcome::Revert);
+        recorder.get_transact_time();
+        recorder.receipt_record(None, TxOutcome::Success);
+        recorder.get_transact_time();
+
+        let breakdown = recorder.get_transact_breakdown();
+        assert_eq!(breakdown.by_type[TxType::Eip1559 as usize].count, 1);
+        assert_eq!(breakdown.by_outcome[TxOutcome::Halt as usize].count, 1);
+        assert_eq!(breakdown.by_outcome[TxOutcome::Revert as usize].count, 0);
+        assert_eq!(breakdown.by_outcome[TxOutcome::Success as usize].count, 1);
+        let types: u64 = breakdown.by_type.iter().map(|stats| stats.count).sum();
+        assert_eq!(types, 1);
+    }
 }
@@ -1,85 +1,358 @@
 //! This module defines some types used for revm metrics.
 use serde::{Deserialize, Serialize};
 
+use super::health::add;
 use crate::time_utils;
 
-const STEP_IN_US: usize = 1;
-const STEP_IN_NS: usize = 100;
-
-const US_SPAN_SIZE: usize = 200;
-const NS_SPAN_SIZE: usize = 40;
-const MAX_ARRAY_SIZE: usize = 200;
-/// This is a structure for statistical time distribution, which records the
-/// distribution of time from two levels: subtle and nanosecond.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
-
-pub struct TimeDistributionStats {
-    /// The subtle range of statistical distribution, step is STEP_IN_US.
-    pub span_in_us: usize,
-    /// The nanosecond range of statistical distribution, step is STEP_IN_NS.
-    pub span_in_ns: usize,
-    /// Record the time distribution at a subtle level.
-    #[serde(with = "serde_arrays")]
-    pub us_percentile: [u64; MAX_ARRAY_SIZE],
-    /// Record the time distribution at a nanosecond level.
-    #[serde(with = "serde_arrays")]
-    pub ns_percentile: [u64; MAX_ARRAY_SIZE],
+pub use super::histogram::LogHistogram;
+
+/// This structure records the streaming statistics of a series of samples: count,
+/// mean and variance (Welford's algorithm), min and max.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct StreamingStats {
+    /// The number of samples.
+    pub count: u64,
+    /// The mean of samples.
+    pub mean: f64,
+    /// The sum of squares of differences from the mean.
+    pub m2: f64,
+    /// The minimum sample.
+    pub min: u64,
+    /// The maximum sample.
+    pub max: u64,
 }
 
-impl Default for TimeDistributionStats {
+impl Default for StreamingStats {
     fn default() -> Self {
-        Self::new(US_SPAN_SIZE, NS_SPAN_SIZE)
+        StreamingStats {
+            count: 0,
+            mean: 0.0,
+            m2: 0.0,
+            min: u64::MAX,
+            max: 0,
+        }
     }
 }
 
-impl TimeDistributionStats {
-    pub fn new(span_in_us: usize, span_in_ns: usize) -> Self {
-        TimeDistributionStats {
-            span_in_us,
-            span_in_ns,
-            us_percentile: [0; MAX_ARRAY_SIZE],
-            ns_percentile: [0; MAX_ARRAY_SIZE],
+impl StreamingStats {
+    /// Add a sample.
+    pub fn record(&mut self, value: u64) {
+        self.count = add(self.count, 1);
+        let value_f64 = value as f64;
+        let delta = value_f64 - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value_f64 - self.mean);
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    /// Update this struct with the other's data, the result is the same as recording
+    /// the samples of both.
+    pub fn update(&mut self, other: &Self) {
+        if other.count == 0 {
+            return;
+        }
+        if self.count == 0 {
+            *self = *other;
+            return;
         }
+
+        let count = add(self.count, other.count);
+        let delta = other.mean - self.mean;
+        self.mean += delta * other.count as f64 / count as f64;
+        self.m2 +=
+            other.m2 + delta * delta * (self.count as f64 * other.count as f64) / count as f64;
+        self.count = count;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
     }
 
-    pub fn update(&mut self, other: &TimeDistributionStats) {
-        for index in 0..self.span_in_us {
-            self.us_percentile[index] = self.us_percentile[index]
-                .checked_add(other.us_percentile[index])
-                .expect("overflow");
+    /// Returns the sample variance, or None if there are less than two samples.
+    pub fn variance(&self) -> Option<f64> {
+        if self.count < 2 {
+            return None;
         }
-        for index in 0..self.span_in_ns {
-            self.ns_percentile[index] = self.ns_percentile[index]
-                .checked_add(other.ns_percentile[index])
-                .expect("overflow");
+        Some(self.m2 / (self.count - 1) as f64)
+    }
+
+    /// Returns the sample standard deviation, or None if there are less than two samples.
+    pub fn std_dev(&self) -> Option<f64> {
+        Some(self.variance()?.sqrt())
+    }
+
+    /// Returns the minimum sample, or None if there is no sample.
+    pub fn min(&self) -> Option<u64> {
+        (self.count > 0).then_some(self.min)
+    }
+
+    /// Returns the maximum sample, or None if there is no sample.
+    pub fn max(&self) -> Option<u64> {
+        (self.count > 0).then_some(self.max)
+    }
+}
+
+/// The number of state access opcodes whose cost depends on whether the accessed
+/// account or storage slot is warm.
+pub const STATE_ACCESS_OPCODE_LEN: usize = 6;
+/// The state access opcodes whose cost depends on whether the accessed account or
+/// storage slot is warm (EIP-2929), array means:
+/// (balance, extcodesize, extcodecopy, extcodehash, sload, sstore).
+pub const STATE_ACCESS_OPCODES: [u8; STATE_ACCESS_OPCODE_LEN] =
+    [0x31, 0x3B, 0x3C, 0x3F, 0x54, 0x55];
+
+/// Returns the index of opcode in STATE_ACCESS_OPCODES.
+pub fn state_access_index(opcode: u8) -> Option<usize> {
+    STATE_ACCESS_OPCODES.iter().position(|&op| op == opcode)
+}
+
+/// This type represents whether the account or storage slot accessed by an opcode is
+/// cold or warm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StateAccessKind {
+    Cold,
+    Warm,
+}
+
+impl StateAccessKind {
+    /// Classify the access of a state access opcode by the gas it charged, using the
+    /// costs defined in EIP-2929. Returns None if opcode is not a state access opcode or
+    /// the gas doesn't match any known cost, which is the case for blocks before Berlin.
+    /// EXTCODECOPY is never classified by its gas, since its copy and memory expansion
+    /// cost can exceed the cold surcharge, so it needs the explicit access kind.
+    pub fn from_gas(opcode: u8, gas: u64) -> Option<Self> {
+        const WARM_STORAGE_READ_COST: u64 = 100;
+        const COLD_SLOAD_COST: u64 = 2100;
+        const COLD_ACCOUNT_ACCESS_COST: u64 = 2600;
+        const SSTORE_RESET: u64 = 5000;
+        const SSTORE_SET: u64 = 20000;
+
+        match opcode {
+            // BALANCE | EXTCODESIZE | EXTCODEHASH
+            0x31 | 0x3B | 0x3F => match gas {
+                WARM_STORAGE_READ_COST => Some(Self::Warm),
+                COLD_ACCOUNT_ACCESS_COST => Some(Self::Cold),
+                _ => None,
+            },
+            // SLOAD
+            0x54 => match gas {
+                WARM_STORAGE_READ_COST => Some(Self::Warm),
+                COLD_SLOAD_COST => Some(Self::Cold),
+                _ => None,
+            },
+            // SSTORE, the cold surcharge is added to the warm cost.
+            0x55 => {
+                let is_warm_cost = |gas: u64| {
+                    gas == WARM_STORAGE_READ_COST
+                        || gas == SSTORE_RESET - COLD_SLOAD_COST
+                        || gas == SSTORE_SET
+                };
+                if is_warm_cost(gas) {
+                    Some(Self::Warm)
+                } else if gas > COLD_SLOAD_COST && is_warm_cost(gas - COLD_SLOAD_COST) {
+                    Some(Self::Cold)
+                } else {
+                    None
+                }
+            }
+            _ => None,
         }
     }
+}
+
+/// This structure records the performance information of state accesses of the same kind.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct StateAccessStats {
+    /// The number of accesses.
+    pub count: u64,
+    /// The total time (cpu cycles) of accesses.
+    pub time: u64,
+    /// The total gas of accesses.
+    pub gas: u64,
+    /// Record the time distribution (ns) of accesses.
+    pub percentile: LogHistogram,
+}
+
+impl StateAccessStats {
+    /// Update this struct with the other's data.
+    pub fn update(&mut self, other: &Self) {
+        self.count = add(self.count, other.count);
+        self.time = add(self.time, other.time);
+        self.gas = add(self.gas, other.gas);
+        self.percentile.update(&other.percentile);
+    }
+
+    fn record(&mut self, cycles: u64, gas: u64) {
+        self.count = add(self.count, 1);
+        self.time = add(self.time, cycles);
+        self.gas = add(self.gas, gas);
+        self.percentile
+            .record(time_utils::convert_cycles_to_ns_f64(cycles));
+    }
+}
 
-    pub fn record(&mut self, time_in_ns: f64) {
-        // Record the time distribution at a subtle level.
-        let mut index = (time_in_ns / (1000.0 * STEP_IN_US as f64)) as usize;
-        if index > self.span_in_us - 1 {
-            index = self.span_in_us - 1;
+/// This structure records the cold and warm accesses of a state access opcode separately.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct ColdWarmRecord {
+    /// Accesses to cold accounts or storage slots.
+    pub cold: StateAccessStats,
+    /// Accesses to warm accounts or storage slots.
+    pub warm: StateAccessStats,
+}
+
+impl ColdWarmRecord {
+    /// Update this struct with the other's data.
+    pub fn update(&mut self, other: &Self) {
+        self.cold.update(&other.cold);
+        self.warm.update(&other.warm);
+    }
+
+    /// Record an access of kind.
+    pub fn record(&mut self, kind: StateAccessKind, cycles: u64, gas: u64) {
+        match kind {
+            StateAccessKind::Cold => self.cold.record(cycles, gas),
+            StateAccessKind::Warm => self.warm.record(cycles, gas),
         }
-        self.us_percentile[index] = self.us_percentile[index].checked_add(1).expect("overflow");
+    }
+}
+
+/// This structure records the expansion of the EVM memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct MemoryRecord {
+    /// The total number of words the memory has grown.
+    pub words_grown: u64,
+    /// The maximum memory size (bytes) of all transactions.
+    pub peak_memory: u64,
+    /// The sum of the peak memory size (bytes) of each transaction.
+    pub peak_memory_sum: u64,
+    /// The number of transactions.
+    pub tx_count: u64,
+}
 
-        // When the time is less than 4 us, record the distribution of time at the nanosecond level.
-        if time_in_ns < (self.span_in_ns * STEP_IN_NS) as f64 {
-            let index = (time_in_ns / STEP_IN_NS as f64) as usize;
-            self.ns_percentile[index] = self.ns_percentile[index].checked_add(1).expect("overflow");
+impl MemoryRecord {
+    /// Update this struct with the other's data.
+    pub fn update(&mut self, other: &Self) {
+        self.words_grown = add(self.words_grown, other.words_grown);
+        self.peak_memory = self.peak_memory.max(other.peak_memory);
+        self.peak_memory_sum = add(self.peak_memory_sum, other.peak_memory_sum);
+        self.tx_count = add(self.tx_count, other.tx_count);
+    }
+
+    /// Returns the average peak memory size (bytes) per transaction, or 0 if no
+    /// transaction is recorded.
+    pub fn avg_peak_memory(&self) -> f64 {
+        if self.tx_count == 0 {
+            return 0.0;
         }
+        self.peak_memory_sum as f64 / self.tx_count as f64
+    }
+}
+
+/// This structure records how many transactions are fully instrumented by the opcode
+/// metrics, see SamplingMode.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Default)]
+pub struct SamplingRecord {
+    /// The number of transactions.
+    pub txs: u64,
+    /// The number of transactions fully instrumented.
+    pub sampled_txs: u64,
+    /// The statistics of the opcode time (cpu cycles) of each sampled transaction.
+    pub sampled_tx_time: StreamingStats,
+}
+
+impl SamplingRecord {
+    /// Update this struct with the other's data.
+    pub fn update(&mut self, other: &Self) {
+        self.txs = add(self.txs, other.txs);
+        self.sampled_txs = add(self.sampled_txs, other.sampled_txs);
+        self.sampled_tx_time.update(&other.sampled_tx_time);
+    }
+
+    /// Returns true if some transactions are not fully instrumented, i.e. the time
+    /// recorded should be scaled up.
+    pub fn is_partial(&self) -> bool {
+        self.sampled_txs < self.txs
+    }
+
+    /// Returns the estimated opcode time (cpu cycles) of all transactions and the half
+    /// width of its 95% confidence interval.
+    pub fn estimated_total_time(&self) -> (f64, f64) {
+        estimate_total(
+            self.txs,
+            self.sampled_tx_time.count,
+            self.sampled_tx_time.mean,
+            self.sampled_tx_time.std_dev(),
+        )
+    }
+}
+
+/// The z-score of a two-sided 95% confidence interval.
+const Z_95: f64 = 1.96;
+
+/// Estimate the total of population items from the mean and standard deviation of samples
+/// items, returns the estimate and the half width of its 95% confidence interval with the
+/// finite population correction. The half width is NaN if it can not be estimated.
+fn estimate_total(population: u64, samples: u64, mean: f64, std_dev: Option<f64>) -> (f64, f64) {
+    if samples == 0 {
+        return (0.0, f64::NAN);
+    }
+    let population = population.max(samples) as f64;
+    let samples = samples as f64;
+    let correction = (1.0 - samples / population).sqrt();
+    let margin = std_dev.map_or(f64::NAN, |std_dev| {
+        population * Z_95 * std_dev / samples.sqrt() * correction
+    });
+    (population * mean, margin)
+}
+
+/// Serializes the (count, time, gas) of the opcodes with the gas as [super::int128].
+mod opcode_record_serde {
+    use serde::{ser::SerializeTuple, Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    struct Gas(#[serde(with = "crate::metrics::int128")] i128);
+
+    /// Serialized as serde_arrays does, but element by element rather than through a
+    /// mapped copy of the array.
+    pub(super) fn serialize<S: Serializer>(
+        record: &[(u64, u64, i128); 256],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut tuple = serializer.serialize_tuple(record.len())?;
+        for &(count, time, gas) in record {
+            tuple.serialize_element(&(count, time, Gas(gas)))?;
+        }
+        tuple.end()
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<[(u64, u64, i128); 256], D::Error> {
+        let record: [(u64, u64, Gas); 256] = serde_arrays::deserialize(deserializer)?;
+        Ok(record.map(|(count, time, Gas(gas))| (count, time, gas)))
     }
 }
 
 const CALL_OPCODE_LEN: usize = 4;
 /// The OpcodeRecord contains all performance information for opcode executions.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct OpcodeRecord {
     /// The abscissa is opcode type, tuple means: (opcode counter, time, gas).
-    #[serde(with = "serde_arrays")]
+    #[serde(with = "opcode_record_serde")]
     pub opcode_record: [(u64, u64, i128); 256],
+    /// The abscissa is opcode type, records the statistics of the time (cpu cycles)
+    /// of each execution.
+    #[serde(with = "serde_arrays")]
+    pub opcode_time_stats: [StreamingStats; 256],
+    /// The abscissa is opcode type, records the number of bytes read, written or copied
+    /// by each opcode.
+    #[serde(with = "serde_arrays")]
+    pub opcode_bytes: [u64; 256],
+    /// Record the expansion of the EVM memory.
+    pub memory: MemoryRecord,
     /// Record the time distribution of the sload.
-    pub sload_percentile: TimeDistributionStats,
+    pub sload_percentile: LogHistogram,
+    /// Record the cold and warm accesses of each opcode in STATE_ACCESS_OPCODES.
+    pub state_access_record: [ColdWarmRecord; STATE_ACCESS_OPCODE_LEN],
     /// The total time (cpu cycles) of all opcode.
     pub total_time: u64,
     /// Update flag.
@@ -87,62 +360,67 @@ pub struct OpcodeRecord {
     /// Additional rdtsc counts that may be added when measuring call related instructions.
     /// array means: (call, call_code, delegate_call, static_call)
     pub additional_count: [u64; CALL_OPCODE_LEN],
+    /// Record how many transactions are fully instrumented. The time of the opcodes is
+    /// only recorded in the sampled transactions, while their count and gas are recorded
+    /// in all transactions.
+    pub sampling: SamplingRecord,
 }
 
 impl Default for OpcodeRecord {
     fn default() -> Self {
-        let sload_percentile = TimeDistributionStats::new(US_SPAN_SIZE, NS_SPAN_SIZE);
         Self {
             opcode_record: [(0, 0, 0); 256],
-            sload_percentile,
+            opcode_time_stats: [StreamingStats::default(); 256],
+            opcode_bytes: [0; 256],
+            memory: MemoryRecord::default(),
+            sload_percentile: LogHistogram::default(),
+            state_access_record: Default::default(),
             total_time: 0,
             is_updated: false,
             additional_count: [0u64; CALL_OPCODE_LEN],
+            sampling: SamplingRecord::default(),
         }
     }
 }
 
 impl OpcodeRecord {
-    /// Update this struct with the other's data.
+    /// Update this struct with the other's data. The data of other is moved if this
+    /// struct is empty, so other is left in an unspecified state.
     pub fn update(&mut self, other: &mut OpcodeRecord) {
+        if other.is_updated && !self.is_updated {
+            std::mem::swap(self, other);
+            return;
+        }
+        self.merge(other);
+    }
+
+    /// Update this struct with the other's data, which is left untouched.
+    pub fn merge(&mut self, other: &OpcodeRecord) {
         if !other.is_updated {
             return;
         }
 
-        self.total_time = self
-            .total_time
-            .checked_add(other.total_time)
-            .expect("overflow");
+        self.total_time = add(self.total_time, other.total_time);
 
         for i in 0..CALL_OPCODE_LEN {
-            self.additional_count[i] = self.additional_count[i]
-                .checked_add(other.additional_count[i])
-                .expect("overflow");
-        }
-
-        if !self.is_updated {
-            self.opcode_record = std::mem::replace(&mut other.opcode_record, self.opcode_record);
-            self.sload_percentile = other.sload_percentile;
-            self.is_updated = true;
-            return;
+            self.additional_count[i] = add(self.additional_count[i], other.additional_count[i]);
         }
+        self.memory.update(&other.memory);
+        self.sampling.update(&other.sampling);
 
         for i in 0..256 {
-            self.opcode_record[i].0 = self.opcode_record[i]
-                .0
-                .checked_add(other.opcode_record[i].0)
-                .expect("overflow");
-            self.opcode_record[i].1 = self.opcode_record[i]
-                .1
-                .checked_add(other.opcode_record[i].1)
-                .expect("overflow");
-            self.opcode_record[i].2 = self.opcode_record[i]
-                .2
-                .checked_add(other.opcode_record[i].2)
-                .expect("overflow");
+            self.opcode_record[i].0 = add(self.opcode_record[i].0, other.opcode_record[i].0);
+            self.opcode_record[i].1 = add(self.opcode_record[i].1, other.opcode_record[i].1);
+            self.opcode_record[i].2 = add(self.opcode_record[i].2, other.opcode_record[i].2);
+            self.opcode_time_stats[i].update(&other.opcode_time_stats[i]);
+            self.opcode_bytes[i] = add(self.opcode_bytes[i], other.opcode_bytes[i]);
         }
 
         self.sload_percentile.update(&other.sload_percentile);
+        for i in 0..STATE_ACCESS_OPCODE_LEN {
+            self.state_access_record[i].update(&other.state_access_record[i]);
+        }
+        self.is_updated = true;
     }
 
     /// Record sload duration percentile.
@@ -150,6 +428,22 @@ impl OpcodeRecord {
         self.sload_percentile.record(op_time_ns);
     }
 
+    /// Record the number of bytes read, written or copied by opcode.
+    pub fn add_opcode_bytes(&mut self, opcode: u8, bytes: u64) {
+        self.opcode_bytes[opcode as usize] = add(self.opcode_bytes[opcode as usize], bytes);
+    }
+
+    /// Returns the estimated time (cpu cycles) of opcode in all transactions, i.e. the time
+    /// recorded in the sampled transactions scaled by the count of the opcode.
+    pub fn estimated_opcode_time(&self, opcode: u8) -> u64 {
+        let (count, time, _) = self.opcode_record[opcode as usize];
+        let sampled = self.opcode_time_stats[opcode as usize].count;
+        if !self.sampling.is_partial() || sampled == 0 || sampled >= count {
+            return time;
+        }
+        (time as f64 * count as f64 / sampled as f64) as u64
+    }
+
     pub fn not_empty(&self) -> bool {
         self.is_updated
     }
@@ -171,36 +465,62 @@ impl OpcodeRecord {
         };
 
         if index < 4 {
-            self.additional_count[index] = self.additional_count[index]
-                .checked_add(count)
-                .expect("overflow");
+            self.additional_count[index] = add(self.additional_count[index], count);
         }
     }
 }
 
 /// This type represents in which function the access cache is accessed.
-#[derive(Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Function {
     CodeByHash = 0,
     Storage,
     BlockHash,
     LoadCacheAccount,
 }
+
+impl Function {
+    /// The number of variants.
+    pub const COUNT: usize = 4;
+
+    /// All variants, ordered by their index.
+    pub const ALL: [Function; Function::COUNT] = [
+        Function::CodeByHash,
+        Function::Storage,
+        Function::BlockHash,
+        Function::LoadCacheAccount,
+    ];
+
+    /// Returns the name of the function in CacheDB.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Function::CodeByHash => "code_by_hash",
+            Function::Storage => "storage",
+            Function::BlockHash => "block_hash",
+            Function::LoadCacheAccount => "load_cache_account",
+        }
+    }
+}
+
+impl std::fmt::Display for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
 /// This structure records the number of times cache hits/misses are accessed in each function.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
 pub struct AccessStats {
     /// This array is used to store the number of hits/misses/penalty in each function,
     /// and the index of the function corresponds to the order of the FunctionType.
     #[serde(with = "serde_arrays")]
-    pub function: [u64; 5],
+    pub function: [u64; Function::COUNT],
 }
 
 impl AccessStats {
     pub fn update(&mut self, other: &Self) {
         for i in 0..self.function.len() {
-            self.function[i] = self.function[i]
-                .checked_add(other.function[i])
-                .expect("overflow");
+            self.function[i] = add(self.function[i], other.function[i]);
         }
     }
 
@@ -210,42 +530,59 @@ impl AccessStats {
 
     fn add(&mut self, function: Function, value: u64) {
         let index = function as usize;
-        self.function[index] = self.function[index].checked_add(value).expect("overflow");
+        self.function[index] = add(self.function[index], value);
     }
 }
 
 /// The additional cost (cpu cycles) incurred when CacheDb is not hit.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
 pub struct MissesPenalty {
     // Record the penalty when each function hits the cache.
     pub time: AccessStats,
-    /// Record the time distribution at a subtle level.
-    pub percentile: TimeDistributionStats,
-}
-
-impl Default for MissesPenalty {
-    fn default() -> Self {
-        let percentile = TimeDistributionStats::new(US_SPAN_SIZE, NS_SPAN_SIZE);
-        MissesPenalty {
-            time: AccessStats::default(),
-            percentile,
-        }
-    }
+    /// Record the time distribution of all functions.
+    pub percentile: LogHistogram,
+    /// Record the time distribution of each function, and the index of the function
+    /// corresponds to the order of the FunctionType.
+    pub function_percentile: [LogHistogram; Function::COUNT],
 }
 
 impl MissesPenalty {
     pub fn update(&mut self, other: &Self) {
         self.time.update(&other.time);
         self.percentile.update(&other.percentile);
+        for (this, other) in self
+            .function_percentile
+            .iter_mut()
+            .zip(other.function_percentile.iter())
+        {
+            this.update(other);
+        }
     }
 
-    fn percentile(&mut self, time_in_ns: f64) {
+    fn percentile(&mut self, function: Function, time_in_ns: f64) {
         self.percentile.record(time_in_ns);
+        self.function_percentile[function as usize].record(time_in_ns);
+    }
+}
+
+/// The cost (cpu cycles) of accessing CacheDb when it is hit.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct HitsLatency {
+    // Record the latency when each function hits the cache.
+    pub time: AccessStats,
+    /// Record the time distribution of all functions.
+    pub percentile: LogHistogram,
+}
+
+impl HitsLatency {
+    pub fn update(&mut self, other: &Self) {
+        self.time.update(&other.time);
+        self.percentile.update(&other.percentile);
     }
 }
 
 /// CacheDbRecord records the relevant information of CacheDb hits during the execution process.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
 pub struct CacheDbRecord {
     /// The number of cache hits when accessing CacheDB.
     hits: AccessStats,
@@ -253,6 +590,8 @@ pub struct CacheDbRecord {
     misses: AccessStats,
     /// The additional cost incurred when accessing CacheDb without a cache hit.
     penalty: MissesPenalty,
+    /// The cost of accessing CacheDb with a cache hit.
+    hit_latency: HitsLatency,
 }
 
 impl CacheDbRecord {
@@ -261,6 +600,7 @@ impl CacheDbRecord {
         self.hits.update(&other.hits);
         self.misses.update(&other.misses);
         self.penalty.update(&other.penalty);
+        self.hit_latency.update(&other.hit_latency);
     }
 
     /// Returns the total number of times cache has been accessed in each function.
@@ -282,12 +622,22 @@ impl CacheDbRecord {
 
     /// Return the penalties missed in each function and their distribution.
     pub fn penalty_stats(&self) -> MissesPenalty {
-        self.penalty
+        self.penalty.clone()
     }
 
-    /// When hit, increase the number of hits count.
-    pub(super) fn hit(&mut self, function: Function) {
+    /// Return the latencies hit in each function and their distribution.
+    pub fn hit_latency_stats(&self) -> HitsLatency {
+        self.hit_latency.clone()
+    }
+
+    /// When hit, increase the number of hits count, record the latency, and record the
+    /// distribution of latency.
+    pub(super) fn hit(&mut self, function: Function, latency: u64) {
         self.hits.increment(function);
+        self.hit_latency.time.add(function, latency);
+        self.hit_latency
+            .percentile
+            .record(time_utils::convert_cycles_to_ns_f64(latency));
     }
 
     /// When a miss occurs, it is necessary to increase the number of misses count,
@@ -296,7 +646,7 @@ impl CacheDbRecord {
         self.misses.increment(function);
         self.penalty.time.add(function, penalty);
         self.penalty
-            .percentile(time_utils::convert_cycles_to_ns_f64(penalty));
+            .percentile(function, time_utils::convert_cycles_to_ns_f64(penalty));
     }
 }
 
@@ -312,15 +662,9 @@ pub struct TransactPreverifiedInnerTime {
 impl TransactPreverifiedInnerTime {
     /// Update this struct with the other's data.
     pub fn update(&mut self, other: &Self) {
-        self.before_execute = self
-            .before_execute
-            .checked_add(other.before_execute)
-            .expect("overflow");
-        self.execute = self.execute.checked_add(other.execute).expect("overflow");
-        self.after_execute = self
-            .after_execute
-            .checked_add(other.after_execute)
-            .expect("overflow");
+        self.before_execute = add(self.before_execute, other.before_execute);
+        self.execute = add(self.execute, other.execute);
+        self.after_execute = add(self.after_execute, other.after_execute);
     }
 }
 
@@ -341,16 +685,222 @@ pub struct TransactTime {
 impl TransactTime {
     /// Update this struct with the other's data.
     pub fn update(&mut self, other: &Self) {
-        self.total = self.total.checked_add(other.total).expect("overflow");
-        self.preverify_transaction_inner = self
-            .preverify_transaction_inner
-            .checked_add(other.preverify_transaction_inner)
-            .expect("overflow");
+        self.total = add(self.total, other.total);
+        self.preverify_transaction_inner = add(
+            self.preverify_transaction_inner,
+            other.preverify_transaction_inner,
+        );
         self.transact_preverified_inner
             .update(&other.transact_preverified_inner);
-        self.handle_end = self
-            .handle_end
-            .checked_add(other.handle_end)
-            .expect("overflow");
+        self.handle_end = add(self.handle_end, other.handle_end);
+    }
+}
+
+/// This type represents the type of the transaction executed by transact.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TxType {
+    Legacy = 0,
+    Eip2930,
+    Eip1559,
+    Eip4844,
+    Eip7702,
+    Deposit,
+}
+
+impl TxType {
+    /// The number of transaction types.
+    pub const COUNT: usize = 6;
+    /// All transaction types, in the order of their index.
+    pub const ALL: [TxType; Self::COUNT] = [
+        TxType::Legacy,
+        TxType::Eip2930,
+        TxType::Eip1559,
+        TxType::Eip4844,
+        TxType::Eip7702,
+        TxType::Deposit,
+    ];
+
+    /// Returns the transaction type of the EIP-2718 type byte.
+    pub fn from_type_byte(ty: u8) -> Option<Self> {
+        match ty {
+            0 => Some(TxType::Legacy),
+            1 => Some(TxType::Eip2930),
+            2 => Some(TxType::Eip1559),
+            3 => Some(TxType::Eip4844),
+            4 => Some(TxType::Eip7702),
+            0x7E => Some(TxType::Deposit),
+            _ => None,
+        }
+    }
+
+    /// Returns the name of the transaction type.
+    pub fn name(&self) -> &'static str {
+        match self {
+            TxType::Legacy => "legacy",
+            TxType::Eip2930 => "eip2930",
+            TxType::Eip1559 => "eip1559",
+            TxType::Eip4844 => "eip4844",
+            TxType::Eip7702 => "eip7702",
+            TxType::Deposit => "deposit",
+        }
+    }
+}
+
+/// This type represents the outcome of the transaction executed by transact.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TxOutcome {
+    Success = 0,
+    Revert,
+    Halt,
+}
+
+impl TxOutcome {
+    /// The number of transaction outcomes.
+    pub const COUNT: usize = 3;
+    /// All transaction outcomes, in the order of their index.
+    pub const ALL: [TxOutcome; Self::COUNT] =
+        [TxOutcome::Success, TxOutcome::Revert, TxOutcome::Halt];
+
+    /// Returns the name of the transaction outcome.
+    pub fn name(&self) -> &'static str {
+        match self {
+            TxOutcome::Success => "success",
+            TxOutcome::Revert => "revert",
+            TxOutcome::Halt => "halt",
+        }
+    }
+}
+
+/// This structure records the number of transactions and their time consumption of
+/// each part of function transact.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct TransactTimeStats {
+    /// The number of transactions.
+    pub count: u64,
+    /// The time consumption of each part of function transact.
+    pub time: TransactTime,
+}
+
+impl TransactTimeStats {
+    /// Update this struct with the other's data.
+    pub fn update(&mut self, other: &Self) {
+        self.count = add(self.count, other.count);
+        self.time.update(&other.time);
+    }
+
+    fn add(&mut self, time: &TransactTime) {
+        self.count = add(self.count, 1);
+        self.time.update(time);
+    }
+}
+
+/// This structure records the time consumption of each part of function transact,
+/// keyed by the type and the outcome of transactions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct TransactTimeBreakdown {
+    /// The index of the array corresponds to the order of TxType.
+    pub by_type: [TransactTimeStats; TxType::COUNT],
+    /// The index of the array corresponds to the order of TxOutcome.
+    pub by_outcome: [TransactTimeStats; TxOutcome::COUNT],
+}
+
+impl TransactTimeBreakdown {
+    /// Update this struct with the other's data.
+    pub fn update(&mut self, other: &Self) {
+        for i in 0..TxType::COUNT {
+            self.by_type[i].update(&other.by_type[i]);
+        }
+        for i in 0..TxOutcome::COUNT {
+            self.by_outcome[i].update(&other.by_outcome[i]);
+        }
+    }
+
+    /// Add the time consumption of a transaction, the unknown type or outcome is skipped.
+    pub fn add(
+        &mut self,
+        tx_type: Option<TxType>,
+        outcome: Option<TxOutcome>,
+        time: &TransactTime,
+    ) {
+        if let Some(tx_type) = tx_type {
+            self.by_type[tx_type as usize].add(time);
+        }
+        if let Some(outcome) = outcome {
+            self.by_outcome[outcome as usize].add(time);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_streaming_stats_update() {
+        let samples = [3u64, 7, 7, 19, 1, 40, 12];
+        let mut all = StreamingStats::default();
+        let mut left = StreamingStats::default();
+        let mut right = StreamingStats::default();
+        for (i, &sample) in samples.iter().enumerate() {
+            all.record(sample);
+            if i < 3 {
+                left.record(sample);
+            } else {
+                right.record(sample);
+            }
+        }
+        left.update(&right);
+
+        assert_eq!(left.count, all.count);
+        assert_eq!((left.min(), left.max()), (Some(1), Some(40)));
+        assert!((left.mean - all.mean).abs() < 1e-9);
+        assert!((left.variance().unwrap() - all.variance().unwrap()).abs() < 1e-9);
+        assert!((all.mean - 89.0 / 7.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_state_access_kind_from_gas() {
+        assert_eq!(
+            StateAccessKind::from_gas(0x54, 2100),
+            Some(StateAccessKind::Cold)
+        );
+        assert_eq!(
+            StateAccessKind::from_gas(0x54, 100),
+            Some(StateAccessKind::Warm)
+        );
+        assert_eq!(
+            StateAccessKind::from_gas(0x55, 2900 + 2100),
+            Some(StateAccessKind::Cold)
+        );
+        // A warm EXTCODECOPY of 1000 words costs more than a cold access.
+        let warm_copy_gas = 100 + 3 * 1000;
+        assert_eq!(StateAccessKind::from_gas(0x3C, warm_copy_gas), None);
+        assert_eq!(StateAccessKind::from_gas(0x3C, 2600), None);
+    }
+
+    #[test]
+    fn test_opcode_record_merge_matches_update() {
+        let mut tx = OpcodeRecord::default();
+        tx.opcode_record[0x54] = (2, 300, 4200);
+        tx.opcode_time_stats[0x54].record(100);
+        tx.opcode_time_stats[0x54].record(200);
+        tx.add_opcode_bytes(0x52, 32);
+        tx.add_sload_opcode_record(40.0);
+        tx.total_time = 300;
+        tx.is_updated = true;
+
+        let mut merged = OpcodeRecord::default();
+        merged.merge(&tx);
+        merged.merge(&tx);
+        let mut updated = OpcodeRecord::default();
+        updated.update(&mut tx.clone());
+        updated.update(&mut tx.clone());
+
+        assert_eq!(merged.opcode_record[0x54], (4, 600, 8400));
+        assert_eq!(merged.opcode_record, updated.opcode_record);
+        assert_eq!(merged.opcode_time_stats, updated.opcode_time_stats);
+        assert_eq!(merged.opcode_bytes, updated.opcode_bytes);
+        assert_eq!(merged.sload_percentile, updated.sload_percentile);
+        assert_eq!(merged.total_time, updated.total_time);
     }
 }
@@ -1,44 +1,92 @@
 //! This module encapsulates functions to support recording metrics in a RAII manner.
+use super::health::cycles_between;
+use super::hot_keys::AccessKey;
 use super::metric::*;
+use super::switches::{is_enabled, MetricFamily};
 use super::types::*;
 use crate::time_utils::instant::Instant;
 
+/// Returns the current time if the cache metrics are enabled, so that the TSC is not read
+/// on the hot paths of the cache when they are turned off.
+fn cache_start_time() -> Option<Instant> {
+    is_enabled(MetricFamily::Cache).then(Instant::now)
+}
+
 pub struct HitRecord {
     function: Function,
+    key: Option<AccessKey>,
+    start_time: Option<Instant>,
 }
 
 impl HitRecord {
     pub fn new(function: Function) -> HitRecord {
-        HitRecord { function }
+        HitRecord {
+            function,
+            key: None,
+            start_time: cache_start_time(),
+        }
+    }
+
+    /// Same as new, and the key is counted if hot key tracking is enabled.
+    pub fn new_with_key(function: Function, key: AccessKey) -> HitRecord {
+        HitRecord {
+            function,
+            key: Some(key),
+            start_time: cache_start_time(),
+        }
     }
 }
 
 impl Drop for HitRecord {
     fn drop(&mut self) {
-        hit_record(self.function);
+        let Some(start_time) = self.start_time else {
+            return;
+        };
+        let cycles = cycles_between(start_time, Instant::now());
+
+        hit_record(self.function, cycles);
+        if let Some(key) = self.key {
+            hot_key_hit_record(key);
+        }
     }
 }
 
 pub struct MissRecord {
     function: Function,
-    start_time: Instant,
+    key: Option<AccessKey>,
+    start_time: Option<Instant>,
 }
 
 impl MissRecord {
     pub fn new(function: Function) -> MissRecord {
         MissRecord {
             function,
-            start_time: Instant::now(),
+            key: None,
+            start_time: cache_start_time(),
+        }
+    }
+
+    /// Same as new, and the key is counted if hot key tracking is enabled.
+    pub fn new_with_key(function: Function, key: AccessKey) -> MissRecord {
+        MissRecord {
+            function,
+            key: Some(key),
+            start_time: cache_start_time(),
         }
     }
 }
 
 impl Drop for MissRecord {
     fn drop(&mut self) {
-        let now = Instant::now();
-        let cycles = now.checked_cycles_since(self.start_time).expect("overflow");
+        let Some(start_time) = self.start_time else {
+            return;
+        };
+        let cycles = cycles_between(start_time, Instant::now());
 
         miss_record(self.function, cycles);
+        if let Some(key) = self.key {
+            hot_key_miss_record(key);
+        }
     }
 }
 